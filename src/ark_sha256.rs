@@ -36,16 +36,82 @@ const H: State = [
     0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
 ];
 
+/// Runs the SHA-256 compression function natively over `data`, starting from the initial state,
+/// and returns the resulting state. This is the midstate that `Sha256Gadget::from_midstate`
+/// continues from. Panics if `data` is not a whole number of 64-byte blocks.
+pub fn native_midstate(data: &[u8]) -> [u32; STATE_LEN] {
+    assert_eq!(
+        data.len() % 64,
+        0,
+        "midstate data must be a whole number of blocks"
+    );
+
+    let mut state = H;
+    for block in data.chunks(64) {
+        native_compress(&mut state, block);
+    }
+    state
+}
+
+// The native analogue of Sha256Gadget::update_state
+fn native_compress(state: &mut State, block: &[u8]) {
+    let mut w = [0u32; 64];
+    for (word, chunk) in w.iter_mut().zip(block.chunks(4)) {
+        *word = u32::from_be_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+    }
+
+    for i in 16..64 {
+        let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+        let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+        w[i] = w[i - 16]
+            .wrapping_add(s0)
+            .wrapping_add(w[i - 7])
+            .wrapping_add(s1);
+    }
+
+    let mut h = *state;
+    for i in 0..64 {
+        let ch = (h[4] & h[5]) ^ (!h[4] & h[6]);
+        let ma = (h[0] & h[1]) ^ (h[0] & h[2]) ^ (h[1] & h[2]);
+        let s0 = h[0].rotate_right(2) ^ h[0].rotate_right(13) ^ h[0].rotate_right(22);
+        let s1 = h[4].rotate_right(6) ^ h[4].rotate_right(11) ^ h[4].rotate_right(25);
+        let t0 = h[7]
+            .wrapping_add(s1)
+            .wrapping_add(ch)
+            .wrapping_add(K[i])
+            .wrapping_add(w[i]);
+        let t1 = s0.wrapping_add(ma);
+
+        h = [
+            t0.wrapping_add(t1),
+            h[0],
+            h[1],
+            h[2],
+            h[3].wrapping_add(t0),
+            h[4],
+            h[5],
+            h[6],
+        ];
+    }
+
+    for (s, hi) in state.iter_mut().zip(h.iter()) {
+        *s = s.wrapping_add(*hi);
+    }
+}
+
 /// Extra traits not automatically implemented by UInt32
 pub(crate) trait UInt32Ext<ConstraintF: PrimeField>: Sized {
     /// Right shift
     fn shr(&self, by: usize) -> Self;
 
-    /// Bitwise NOT
-    fn not(&self) -> Self;
+    /// SHA-256's choice function. Each output bit is the bit of `b` if the bit of `self` is set,
+    /// else the bit of `c`. This is one constraint per bit, versus three for `(a ∧ b) ⊕ (¬a ∧ c)`.
+    fn ch(&self, b: &Self, c: &Self) -> Result<Self, SynthesisError>;
 
-    /// Bitwise AND
-    fn bitand(&self, rhs: &Self) -> Result<Self, SynthesisError>;
+    /// SHA-256's majority function. Each output bit is the bit of `c` if the bits of `self` and `b`
+    /// differ, else the bit of `self`. This is two constraints per bit, versus five for
+    /// `(a ∧ b) ⊕ (a ∧ c) ⊕ (b ∧ c)`.
+    fn maj(&self, b: &Self, c: &Self) -> Result<Self, SynthesisError>;
 
     /// Converts from big-endian bytes
     fn from_bytes_be(bytes: &[UInt8<ConstraintF>]) -> Result<Self, SynthesisError>;
@@ -68,17 +134,24 @@ impl<ConstraintF: PrimeField> UInt32Ext<ConstraintF> for UInt32<ConstraintF> {
         UInt32::from_bits_le(&new_bits)
     }
 
-    fn not(&self) -> Self {
-        let new_bits: Vec<_> = self.to_bits_le().iter().map(Boolean::not).collect();
-        UInt32::from_bits_le(&new_bits)
+    fn ch(&self, b: &Self, c: &Self) -> Result<Self, SynthesisError> {
+        let new_bits: Result<Vec<_>, SynthesisError> = self
+            .to_bits_le()
+            .iter()
+            .zip(b.to_bits_le().iter())
+            .zip(c.to_bits_le().iter())
+            .map(|((x, y), z)| Boolean::conditionally_select(x, y, z))
+            .collect();
+        Ok(UInt32::from_bits_le(&new_bits?))
     }
 
-    fn bitand(&self, rhs: &Self) -> Result<Self, SynthesisError> {
+    fn maj(&self, b: &Self, c: &Self) -> Result<Self, SynthesisError> {
         let new_bits: Result<Vec<_>, SynthesisError> = self
             .to_bits_le()
-            .into_iter()
-            .zip(rhs.to_bits_le().into_iter())
-            .map(|(a, b)| a.and(&b))
+            .iter()
+            .zip(b.to_bits_le().iter())
+            .zip(c.to_bits_le().iter())
+            .map(|((x, y), z)| Boolean::conditionally_select(&x.xor(y)?, z, x))
             .collect();
         Ok(UInt32::from_bits_le(&new_bits?))
     }
@@ -125,6 +198,24 @@ impl<ConstraintF: PrimeField> Default for Sha256Gadget<ConstraintF> {
 // Wikipedia's pseudocode is a good companion for understanding the below
 // https://en.wikipedia.org/wiki/SHA-2#Pseudocode
 impl<ConstraintF: PrimeField> Sha256Gadget<ConstraintF> {
+    /// Makes a gadget that picks up hashing where some other hasher left off. `midstate` is the
+    /// state after processing the first `completed_blocks` 64-byte blocks of the data. It can be
+    /// constant, a public input, or a witness.
+    ///
+    /// If the midstate is a witness, the circuit says nothing about the blocks it came from, and the
+    /// prover can pick it freely. Then the only thing stopping a forged hash is the compression
+    /// function's resistance to pseudo-preimages, which is much weaker than preimage resistance.
+    /// So the midstate should be a constant, or a public input that the verifier computes itself.
+    pub fn from_midstate(midstate: Vec<UInt32<ConstraintF>>, completed_blocks: u64) -> Self {
+        assert_eq!(midstate.len(), STATE_LEN);
+
+        Self {
+            state: midstate,
+            completed_data_blocks: completed_blocks,
+            ..Default::default()
+        }
+    }
+
     fn update_state(
         state: &mut [UInt32<ConstraintF>],
         data: &[UInt8<ConstraintF>],
@@ -154,17 +245,8 @@ impl<ConstraintF: PrimeField> Sha256Gadget<ConstraintF> {
 
         let mut h = state.to_vec();
         for i in 0..64 {
-            let ch = {
-                let x1 = h[4].bitand(&h[5])?;
-                let x2 = h[4].not().bitand(&h[6])?;
-                x1.xor(&x2)?
-            };
-            let ma = {
-                let x1 = h[0].bitand(&h[1])?;
-                let x2 = h[0].bitand(&h[2])?;
-                let x3 = h[1].bitand(&h[2])?;
-                x1.xor(&x2)?.xor(&x3)?
-            };
+            let ch = h[4].ch(&h[5], &h[6])?;
+            let ma = h[0].maj(&h[1], &h[2])?;
             let s0 = {
                 let x1 = h[0].rotr(2);
                 let x2 = h[0].rotr(13);
@@ -177,18 +259,22 @@ impl<ConstraintF: PrimeField> Sha256Gadget<ConstraintF> {
                 let x3 = h[4].rotr(25);
                 x1.xor(&x2)?.xor(&x3)?
             };
-            let t0 =
-                UInt32::addmany(&[h[7].clone(), s1, ch, UInt32::constant(K[i]), w[i].clone()])?;
-            let t1 = UInt32::addmany(&[s0, ma])?;
+
+            // Every modular addition allocates a full set of result bits, so rather than computing
+            // t0 = h + S1 + ch + k + w and t1 = S0 + maj and then adding those, we compute the new
+            // e and a each in a single addition
+            let t0_terms = [h[7].clone(), s1, ch, UInt32::constant(K[i]), w[i].clone()];
+            let new_e = UInt32::addmany(&[&t0_terms[..], &[h[3].clone()]].concat())?;
+            let new_a = UInt32::addmany(&[&t0_terms[..], &[s0, ma]].concat())?;
 
             h[7] = h[6].clone();
             h[6] = h[5].clone();
             h[5] = h[4].clone();
-            h[4] = UInt32::addmany(&[h[3].clone(), t0.clone()])?;
+            h[4] = new_e;
             h[3] = h[2].clone();
             h[2] = h[1].clone();
             h[1] = h[0].clone();
-            h[0] = UInt32::addmany(&[t0, t1])?;
+            h[0] = new_a;
         }
 
         for (s, hi) in state.iter_mut().zip(h.iter()) {
@@ -0,0 +1,246 @@
+//! Constraint counts for the circuits in this crate, broken down by namespace. A namespace is
+//! anything opened with `ns!`, or any gadget function instrumented with `tracing`. The counts are
+//! collected by a `tracing` layer that records the number of constraints on entering and exiting
+//! each namespace.
+
+use crate::{
+    error::Error,
+    issuance_checker::PassportHashChecker,
+    params::{Fr, PassportComScheme, PassportComSchemeG},
+    passport_info::{PersonalInfo, PersonalInfoVar},
+    preds::{
+        AgeAndExpiryChecker, AgeChecker, AgeFaceExpiryChecker, AgeMultishowExpiryChecker,
+        ExpiryChecker, FaceChecker, ShowChecker,
+    },
+};
+
+use zkcreds::pred::PredicateChecker;
+
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use ark_r1cs_std::alloc::AllocVar;
+use ark_relations::{
+    ns,
+    r1cs::{ConstraintSystem, ConstraintSystemRef},
+};
+use clap::ArgEnum;
+use serde::{Deserialize, Serialize};
+use tracing::{span::Id, Subscriber};
+use tracing_subscriber::{
+    layer::{Context, SubscriberExt},
+    registry::LookupSpan,
+    Layer, Registry,
+};
+
+/// The circuits in this crate
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ArgEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Circuit {
+    /// The issuance circuit, i.e., `PassportHashChecker`
+    Issuance,
+    Age,
+    Expiry,
+    Face,
+    AgeFaceExpiry,
+    AgeAndExpiry,
+    AgeMultishowExpiry,
+    /// `AgeChecker`, bound to a show context so it can be shown (see `ShowChecker`)
+    ShowAge,
+    /// `ExpiryChecker`, bound to a show context
+    ShowExpiry,
+    /// `AgeAndExpiryChecker`, bound to a show context
+    ShowAgeAndExpiry,
+}
+
+/// The constraints generated inside a single namespace
+#[derive(Serialize)]
+pub struct NamespaceStats {
+    /// The names of this namespace and all its parents, separated by `/`
+    pub path: String,
+    /// The number of constraints generated in this namespace, including its children
+    pub constraints: usize,
+    /// The number of times this namespace was entered
+    pub calls: usize,
+}
+
+impl NamespaceStats {
+    /// How deeply nested this namespace is. Top-level namespaces have depth 1.
+    pub fn depth(&self) -> usize {
+        self.path.matches('/').count() + 1
+    }
+}
+
+/// The size of a circuit, and where its constraints come from
+#[derive(Serialize)]
+pub struct CircuitStats {
+    pub circuit: Circuit,
+    pub num_constraints: usize,
+    pub num_instance_variables: usize,
+    pub num_witness_variables: usize,
+    /// Every namespace up to the requested depth, in the order they were first entered
+    pub namespaces: Vec<NamespaceStats>,
+}
+
+/// Synthesizes the given circuit with placeholder inputs and counts its constraints. Only
+/// namespaces at depth `max_depth` or shallower are reported. Note this measures the predicate
+/// alone; the commitment opening that zkcreds adds when proving is not included.
+pub fn circuit_stats(circuit: Circuit, max_depth: usize) -> Result<CircuitStats, Error> {
+    match circuit {
+        Circuit::Issuance => measure(circuit, PassportHashChecker::default(), max_depth),
+        Circuit::Age => measure(circuit, AgeChecker::default(), max_depth),
+        Circuit::Expiry => measure(circuit, ExpiryChecker::default(), max_depth),
+        Circuit::Face => measure(circuit, FaceChecker::default(), max_depth),
+        Circuit::AgeFaceExpiry => measure(circuit, AgeFaceExpiryChecker::default(), max_depth),
+        Circuit::AgeAndExpiry => measure(circuit, AgeAndExpiryChecker::default(), max_depth),
+        Circuit::AgeMultishowExpiry => {
+            measure(circuit, AgeMultishowExpiryChecker::default(), max_depth)
+        }
+        Circuit::ShowAge => measure(circuit, ShowChecker::<AgeChecker>::default(), max_depth),
+        Circuit::ShowExpiry => measure(circuit, ShowChecker::<ExpiryChecker>::default(), max_depth),
+        Circuit::ShowAgeAndExpiry => measure(
+            circuit,
+            ShowChecker::<AgeAndExpiryChecker>::default(),
+            max_depth,
+        ),
+    }
+}
+
+fn measure<P>(circuit: Circuit, pred: P, max_depth: usize) -> Result<CircuitStats, Error>
+where
+    P: PredicateChecker<Fr, PersonalInfo, PersonalInfoVar, PassportComScheme, PassportComSchemeG>,
+{
+    let cs = ConstraintSystem::<Fr>::new_ref();
+    let counter = NamespaceCounter {
+        max_depth,
+        state: Arc::default(),
+    };
+    let subscriber = Registry::default().with(counter.clone());
+
+    // Synthesize the predicate over witnessed attributes, with the counter listening
+    MEASURED_CS.with(|m| *m.borrow_mut() = Some(cs.clone()));
+    let res = tracing::subscriber::with_default(subscriber, || {
+        let attrs = PersonalInfoVar::new_witness(ns!(cs, "attrs"), || Ok(PersonalInfo::default()))?;
+        pred.pred(cs.clone(), &attrs)
+    });
+    MEASURED_CS.with(|m| m.borrow_mut().take());
+    res?;
+
+    let mut state = counter.state.lock().unwrap();
+    let order = std::mem::take(&mut state.order);
+    let namespaces = order
+        .into_iter()
+        .filter_map(|path| {
+            let (constraints, calls) = state.totals.get(&path).copied()?;
+            Some(NamespaceStats {
+                path,
+                constraints,
+                calls,
+            })
+        })
+        .collect();
+
+    Ok(CircuitStats {
+        circuit,
+        num_constraints: cs.num_constraints(),
+        num_instance_variables: cs.num_instance_variables(),
+        num_witness_variables: cs.num_witness_variables(),
+        namespaces,
+    })
+}
+
+thread_local! {
+    // The constraint system being measured. Tracing layers have to be thread-safe, and a
+    // ConstraintSystemRef isn't, so the layer looks it up here. This works because synthesis
+    // happens entirely on the thread that set it.
+    static MEASURED_CS: RefCell<Option<ConstraintSystemRef<Fr>>> = RefCell::new(None);
+}
+
+/// The number of constraints in the constraint system being measured
+fn current_num_constraints() -> usize {
+    MEASURED_CS.with(|m| {
+        m.borrow()
+            .as_ref()
+            .map_or(0, ConstraintSystemRef::num_constraints)
+    })
+}
+
+#[derive(Default)]
+struct CounterState {
+    // The namespaces we're currently in: their span ID, path, and constraint count on entry
+    open: Vec<(Id, String, usize)>,
+    // Every recorded path, in the order it was first entered. Parents always precede children.
+    order: Vec<String>,
+    // The total constraints and number of entries for every recorded path
+    totals: HashMap<String, (usize, usize)>,
+}
+
+/// A tracing layer that attributes constraints to the namespace they were generated in
+#[derive(Clone)]
+struct NamespaceCounter {
+    max_depth: usize,
+    state: Arc<Mutex<CounterState>>,
+}
+
+impl<S> Layer<S> for NamespaceCounter
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_enter(&self, id: &Id, ctx: Context<'_, S>) {
+        let name = ctx.span(id).map_or("<unknown>", |span| span.name());
+        let mut state = self.state.lock().unwrap();
+
+        let path = match state.open.last() {
+            Some((_, parent, _)) => format!("{}/{}", parent, name),
+            None => name.to_string(),
+        };
+        if path.matches('/').count() < self.max_depth && !state.totals.contains_key(&path) {
+            state.totals.insert(path.clone(), (0, 0));
+            state.order.push(path.clone());
+        }
+        state
+            .open
+            .push((id.clone(), path, current_num_constraints()));
+    }
+
+    fn on_exit(&self, id: &Id, _ctx: Context<'_, S>) {
+        let mut state = self.state.lock().unwrap();
+
+        // Namespaces usually close in the reverse order they were opened, but not always
+        let pos = match state.open.iter().rposition(|(open_id, ..)| open_id == id) {
+            Some(pos) => pos,
+            None => return,
+        };
+        let (_, path, start) = state.open.remove(pos);
+        if let Some((constraints, calls)) = state.totals.get_mut(&path) {
+            *constraints += current_num_constraints() - start;
+            *calls += 1;
+        }
+    }
+}
+
+/// Prints the totals, then a table of namespaces indented by depth
+impl std::fmt::Display for CircuitStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "circuit: {:?}", self.circuit)?;
+        writeln!(f, "constraints: {}", self.num_constraints)?;
+        writeln!(f, "instance variables: {}", self.num_instance_variables)?;
+        writeln!(f, "witness variables: {}", self.num_witness_variables)?;
+        writeln!(
+            f,
+            "{:<50} {:>12} {:>8}",
+            "namespace", "constraints", "calls"
+        )?;
+        for ns in &self.namespaces {
+            // Show only the last component of the path, indented by its depth
+            let name = ns.path.rsplit('/').next().unwrap_or(&ns.path);
+            let indented = format!("{:indent$}{}", "", name, indent = 2 * (ns.depth() - 1));
+            writeln!(f, "{:<50} {:>12} {:>8}", indented, ns.constraints, ns.calls)?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,133 @@
+use crate::passive_auth::PassiveAuthStep;
+
+use ark_relations::r1cs::SynthesisError;
+use ark_serialize::SerializationError;
+
+/// Every way a command can fail. Each variant maps to a distinct process exit code, so that
+/// automation can tell a bad input apart from a bad proof without parsing the message.
+///
+/// | Exit code | Kind                   | Meaning                                                   |
+/// |-----------|------------------------|-----------------------------------------------------------|
+/// | 0         |                        | Success                                                   |
+/// | 2         |                        | Bad command line arguments (reported by `clap`)           |
+/// | 3         | `io`                   | A file or stream couldn't be opened, read, or written     |
+/// | 4         | `parse`                | An input was well-formed but its contents didn't parse    |
+/// | 5         | `deserialization`      | An input couldn't be deserialized at all                  |
+/// | 6         | `proof_failure`        | A proof failed to verify, or couldn't be constructed      |
+/// | 7         | `signature_failure`    | A passport signature failed to verify                     |
+/// | 8         | `policy_violation`     | A passport doesn't satisfy the issuance policy            |
+/// | 9         | `passive_auth_failure` | A passport's data groups don't match its signed hashes    |
+/// | 10        | `crypto`               | Key generation, signing, or encryption failed             |
+#[derive(Debug)]
+pub enum Error {
+    /// An I/O error, along with a description of what we were trying to do
+    Io(String, std::io::Error),
+    /// A malformed field in an otherwise readable input, e.g., a date with non-digit characters
+    Parse(String),
+    /// An input that could not be deserialized
+    Deserialization(String),
+    /// A proof that could not be made or did not verify
+    ProofFailure(String),
+    /// A signature over a passport's econtent that did not verify
+    SignatureFailure,
+    /// A passport that is well-formed but doesn't meet the issuance policy, e.g., it's expired
+    PolicyViolation(String),
+    /// A step of passive authentication, other than the signature check, that failed. Signature
+    /// failures are always reported as `SignatureFailure`.
+    PassiveAuthFailure(PassiveAuthStep, String),
+    /// A cryptographic operation of our own that failed, e.g., generating a key or encrypting
+    Crypto(String),
+}
+
+impl Error {
+    /// A short, stable, snake_case identifier for this kind of error
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::Io(..) => "io",
+            Error::Parse(_) => "parse",
+            Error::Deserialization(_) => "deserialization",
+            Error::ProofFailure(_) => "proof_failure",
+            Error::SignatureFailure => "signature_failure",
+            Error::PolicyViolation(_) => "policy_violation",
+            Error::PassiveAuthFailure(..) => "passive_auth_failure",
+            Error::Crypto(_) => "crypto",
+        }
+    }
+
+    /// The process exit code for this kind of error. See the table in the type's documentation.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Io(..) => 3,
+            Error::Parse(_) => 4,
+            Error::Deserialization(_) => 5,
+            Error::ProofFailure(_) => 6,
+            Error::SignatureFailure => 7,
+            Error::PolicyViolation(_) => 8,
+            Error::PassiveAuthFailure(..) => 9,
+            Error::Crypto(_) => 10,
+        }
+    }
+
+    /// Formats the error as a single logfmt line, e.g.,
+    /// `error kind=policy_violation code=8 msg="passport is expired"`
+    pub fn to_logfmt(&self) -> String {
+        format!(
+            "error kind={} code={} msg={:?}",
+            self.kind(),
+            self.exit_code(),
+            self.to_string()
+        )
+    }
+
+    /// Returns a closure that wraps an I/O error with the given context. Meant to be used with
+    /// `map_err`.
+    pub fn io(context: &str) -> impl FnOnce(std::io::Error) -> Error + '_ {
+        move |e| Error::Io(context.to_string(), e)
+    }
+
+    /// Returns a closure that wraps a deserialization error with the given context. Meant to be
+    /// used with `map_err`.
+    pub fn deser<E: std::fmt::Debug>(context: &str) -> impl FnOnce(E) -> Error + '_ {
+        move |e| Error::Deserialization(format!("{}: {:?}", context, e))
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Io(context, e) => write!(f, "{}: {}", context, e),
+            Error::Parse(msg) => write!(f, "{}", msg),
+            Error::Deserialization(msg) => write!(f, "{}", msg),
+            Error::ProofFailure(msg) => write!(f, "{}", msg),
+            Error::SignatureFailure => write!(f, "passport signature verification failed"),
+            Error::PolicyViolation(msg) => write!(f, "{}", msg),
+            Error::PassiveAuthFailure(step, msg) => {
+                write!(
+                    f,
+                    "passive authentication failed at {} check: {}",
+                    step, msg
+                )
+            }
+            Error::Crypto(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+// Serialization errors only ever come from our (de)serialization of keys, proofs, and the like
+impl From<SerializationError> for Error {
+    fn from(e: SerializationError) -> Error {
+        match e {
+            SerializationError::IoError(e) => Error::Io("serialization I/O".to_string(), e),
+            e => Error::Deserialization(format!("{:?}", e)),
+        }
+    }
+}
+
+// Synthesis errors only ever come from proving and verifying
+impl From<SynthesisError> for Error {
+    fn from(e: SynthesisError) -> Error {
+        Error::ProofFailure(format!("{:?}", e))
+    }
+}
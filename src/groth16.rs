@@ -0,0 +1,59 @@
+//! Access to the Groth16 objects inside zkcreds' predicate keys and proofs. zkcreds doesn't expose
+//! them, but it serializes each one first, so we read them off the front of the serialization. The
+//! verifying key and proof are read with full point checks, so if zkcreds ever serializes
+//! something else first, extraction fails instead of returning garbage points.
+
+use crate::{
+    error::Error,
+    params::{PredProof, PredProvingKey, PredVerifyingKey, E},
+};
+
+use ark_groth16::{Proof, ProvingKey, VerifyingKey};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize, SerializationError};
+
+/// Reads a `T` off the front of the serialization of `val`, using `read`. Returns it along with the
+/// rest of the serialization.
+fn split<S, T>(
+    val: &S,
+    what: &str,
+    read: fn(&mut &[u8]) -> Result<T, SerializationError>,
+) -> Result<(T, Vec<u8>), Error>
+where
+    S: CanonicalSerialize,
+{
+    let mut buf = Vec::new();
+    val.serialize_uncompressed(&mut buf)?;
+    let mut reader = &buf[..];
+    let inner =
+        read(&mut reader).map_err(Error::deser(&format!("couldn't extract Groth16 {}", what)))?;
+    Ok((inner, reader.to_vec()))
+}
+
+/// Extracts the Groth16 verifying key from a predicate verifying key
+pub fn groth16_vk(vk: &PredVerifyingKey) -> Result<VerifyingKey<E>, Error> {
+    split(vk, "verifying key", |r| {
+        VerifyingKey::deserialize_uncompressed(r)
+    })
+    .map(|(vk, _)| vk)
+}
+
+/// Extracts the Groth16 proof from a predicate proof
+pub fn groth16_proof(proof: &PredProof) -> Result<Proof<E>, Error> {
+    split(proof, "proof", |r| Proof::deserialize_uncompressed(r)).map(|(proof, _)| proof)
+}
+
+/// Extracts the Groth16 proving key from a predicate proving key. Also returns the rest of the
+/// serialization, so the key can be put back with `join_pk`. Proving keys are large and trusted,
+/// so their points aren't checked.
+pub fn split_pk(pk: &PredProvingKey) -> Result<(ProvingKey<E>, Vec<u8>), Error> {
+    split(pk, "proving key", |r| ProvingKey::deserialize_unchecked(r))
+}
+
+/// The inverse of `split_pk`
+pub fn join_pk(groth16_pk: &ProvingKey<E>, rest: &[u8]) -> Result<PredProvingKey, Error> {
+    let mut buf = Vec::new();
+    groth16_pk.serialize_uncompressed(&mut buf)?;
+    buf.extend_from_slice(rest);
+    PredProvingKey::deserialize_unchecked(&buf[..])
+        .map_err(Error::deser("couldn't rebuild predicate proving key"))
+}
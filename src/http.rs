@@ -0,0 +1,195 @@
+//! The HTTP plumbing shared by the issuer and verifier services, and by the commands that talk to
+//! them. Every response body is JSON.
+
+use crate::{
+    error::Error,
+    output::{Envelope, OutputFormat},
+};
+
+use std::io::{self, Read};
+
+use serde::{Deserialize, Serialize};
+use tiny_http::{Header, Server};
+
+// The largest request or response body we read. Issuance requests and show proofs are a few
+// kilobytes, and trees are bigger but still nowhere near this. Larger bodies are rejected rather
+// than truncated.
+const MAX_BODY_LEN: u64 = 1 << 26;
+
+/// A response to an HTTP request
+pub struct Response {
+    pub status: u16,
+    /// A JSON document
+    pub body: String,
+}
+
+impl Response {
+    pub(crate) fn envelope(status: u16, envelope: &Envelope) -> Result<Response, Error> {
+        let mut body = Vec::new();
+        envelope.write(OutputFormat::Json, &mut body)?;
+        Ok(Response {
+            status,
+            body: String::from_utf8(body).unwrap(),
+        })
+    }
+
+    pub(crate) fn json<T: Serialize>(status: u16, val: &T) -> Response {
+        Response {
+            status,
+            body: serde_json::to_string(val).unwrap(),
+        }
+    }
+
+    pub(crate) fn error(e: &Error) -> Response {
+        Response::error_with_status(status_code(e), e)
+    }
+
+    pub(crate) fn error_with_status(status: u16, e: &Error) -> Response {
+        #[derive(Serialize)]
+        struct ErrorBody<'a> {
+            kind: &'a str,
+            msg: String,
+        }
+
+        let body = ErrorBody {
+            kind: e.kind(),
+            msg: e.to_string(),
+        };
+        Response::json(status, &body)
+    }
+
+    /// The response for a path nothing is served at
+    pub(crate) fn not_found(method: &str, path: &str) -> Response {
+        let e = Error::Parse(format!("no such endpoint: {} {}", method, path));
+        Response::error_with_status(404, &e)
+    }
+}
+
+/// The HTTP status code for an error. Unreadable requests are the client's fault, and requests that
+/// fail a check are well-formed but unacceptable.
+pub(crate) fn status_code(e: &Error) -> u16 {
+    match e {
+        Error::Io(..) | Error::Crypto(_) => 500,
+        Error::Parse(_) | Error::Deserialization(_) => 400,
+        Error::ProofFailure(_)
+        | Error::SignatureFailure
+        | Error::PolicyViolation(_)
+        | Error::PassiveAuthFailure(..) => 422,
+    }
+}
+
+/// Serves requests on the given address until the process is killed, passing the method, path,
+/// and body of each to `handle`. Requests are handled one at a time, in the order they arrive.
+pub(crate) fn serve<F>(addr: &str, mut handle: F) -> Result<(), Error>
+where
+    F: FnMut(&str, &str, &[u8]) -> Response,
+{
+    let server = Server::http(addr).map_err(|e| {
+        Error::Io(
+            format!("couldn't listen on {}", addr),
+            io::Error::new(io::ErrorKind::Other, e),
+        )
+    })?;
+    let content_type = Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]).unwrap();
+
+    for mut request in server.incoming_requests() {
+        // Don't bother reading a body that says up front that it's too big
+        let declared_len = request.body_length().unwrap_or(0) as u64;
+        let mut body = Vec::new();
+        let res = if declared_len > MAX_BODY_LEN {
+            Ok(declared_len)
+        } else {
+            read_limited(request.as_reader(), &mut body, "couldn't read request body")
+        };
+        let response = match res {
+            Ok(len) if len > MAX_BODY_LEN => {
+                let e = Error::Parse(format!(
+                    "request body is larger than {} bytes",
+                    MAX_BODY_LEN
+                ));
+                Response::error_with_status(413, &e)
+            }
+            Ok(_) => handle(request.method().as_str(), request.url(), &body),
+            Err(e) => Response::error(&e),
+        };
+
+        let http_response = tiny_http::Response::from_string(response.body)
+            .with_status_code(response.status)
+            .with_header(content_type.clone());
+        if let Err(e) = request.respond(http_response) {
+            eprintln!(
+                "{}",
+                Error::Io("couldn't send response".to_string(), e).to_logfmt()
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Fetches the given URL. Returns the status code and the body. Error statuses aren't errors here,
+/// since the services put the reason for the error in the body.
+pub(crate) fn get(url: &str) -> Result<(u16, Vec<u8>), Error> {
+    read_response(url, ureq::get(url).call())
+}
+
+/// Posts the given body to the given URL. Returns the status code and the body, like `get`.
+pub(crate) fn post(url: &str, body: &[u8]) -> Result<(u16, Vec<u8>), Error> {
+    read_response(url, ureq::post(url).send_bytes(body))
+}
+
+/// Parses the JSON body of a response. A status other than 200 is an error, with the body as the
+/// reason.
+pub(crate) fn read_json<T: for<'a> Deserialize<'a>>(
+    url: &str,
+    status: u16,
+    body: &[u8],
+) -> Result<T, Error> {
+    if status != 200 {
+        return Err(upstream_error(url, status, body));
+    }
+    serde_json::from_slice(body).map_err(Error::deser(&format!("couldn't deserialize {}", url)))
+}
+
+/// The error for a response with the given status from another service
+pub(crate) fn upstream_error(url: &str, status: u16, body: &[u8]) -> Error {
+    Error::Io(
+        format!("{} returned status {}", url, status),
+        io::Error::new(io::ErrorKind::Other, String::from_utf8_lossy(body)),
+    )
+}
+
+fn read_response(
+    url: &str,
+    res: Result<ureq::Response, ureq::Error>,
+) -> Result<(u16, Vec<u8>), Error> {
+    let response = match res {
+        Ok(response) | Err(ureq::Error::Status(_, response)) => response,
+        Err(e) => {
+            return Err(Error::Io(
+                format!("couldn't reach {}", url),
+                io::Error::new(io::ErrorKind::Other, e),
+            ))
+        }
+    };
+
+    let status = response.status();
+    let mut body = Vec::new();
+    let len = read_limited(response.into_reader(), &mut body, "couldn't read response")?;
+    if len > MAX_BODY_LEN {
+        return Err(Error::Parse(format!(
+            "response from {} is larger than {} bytes",
+            url, MAX_BODY_LEN
+        )));
+    }
+    Ok((status, body))
+}
+
+/// Reads a body into `buf`, stopping one byte past `MAX_BODY_LEN`. Returns the number of bytes
+/// read, so a result over `MAX_BODY_LEN` means the body was too big.
+fn read_limited<R: Read>(r: R, buf: &mut Vec<u8>, context: &str) -> Result<u64, Error> {
+    r.take(MAX_BODY_LEN + 1)
+        .read_to_end(buf)
+        .map(|len| len as u64)
+        .map_err(Error::io(context))
+}
@@ -0,0 +1,135 @@
+use crate::{
+    issuance::IssuancePolicy,
+    passive_auth::{check_all_steps, PassiveAuthStep},
+    passport_dump::PassportDump,
+    passport_info::date_to_u32,
+};
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+/// Everything we can learn about a passport dump without proving anything
+#[derive(Serialize)]
+pub struct DumpReport {
+    pub issuer: String,
+    pub name: String,
+    pub document_number: String,
+    pub nationality: String,
+    /// Date of birth as it appears in the MRZ, i.e., YYMMDD
+    pub date_of_birth: String,
+    /// Date of birth as parsed by the issuance checker, i.e., YYYYMMDD in base 10
+    pub parsed_date_of_birth: Option<u32>,
+    /// Expiry date as it appears in the MRZ, i.e., YYMMDD
+    pub expiry: String,
+    /// Expiry date as parsed by the issuance checker, i.e., YYYYMMDD in base 10
+    pub parsed_expiry: Option<u32>,
+    /// Hex-encoded SHA-256 hash of DG2
+    pub biometrics_hash: String,
+    pub digest_alg: String,
+    pub sig_alg: String,
+    /// The Document Signer certificate, or a description of why it couldn't be parsed
+    pub certificate: Result<CertReport, String>,
+    pub passive_auth: Vec<PassiveAuthReport>,
+}
+
+/// The parts of a Document Signer certificate worth looking at
+#[derive(Serialize)]
+pub struct CertReport {
+    pub subject: String,
+    pub issuer: String,
+    /// RFC 2822 timestamp
+    pub not_before: String,
+    /// RFC 2822 timestamp
+    pub not_after: String,
+}
+
+/// The outcome of a single step of passive authentication
+#[derive(Serialize)]
+pub struct PassiveAuthReport {
+    pub step: PassiveAuthStep,
+    /// The reason this step failed. `None` if it succeeded.
+    pub error: Option<String>,
+}
+
+impl DumpReport {
+    /// Inspects the given dump. Dates are parsed relative to the policy's date, and passive
+    /// authentication is done with respect to the policy's trust anchor.
+    pub fn new(dump: &PassportDump, policy: &IssuancePolicy) -> DumpReport {
+        let lossy = |bytes: &[u8]| String::from_utf8_lossy(bytes).into_owned();
+        let expiry_not_after = policy.today + policy.max_valid_years * 10000u32;
+
+        let certificate = x509_parser::parse_x509_certificate(&dump.cert)
+            .map(|(_, cert)| CertReport {
+                subject: cert.subject().to_string(),
+                issuer: cert.issuer().to_string(),
+                not_before: cert.validity().not_before.to_rfc2822(),
+                not_after: cert.validity().not_after.to_rfc2822(),
+            })
+            .map_err(|e| e.to_string());
+
+        let passive_auth = check_all_steps(dump, &policy.trust_anchor)
+            .into_iter()
+            .map(|(step, res)| PassiveAuthReport {
+                step,
+                error: res.err().map(|e| e.to_string()),
+            })
+            .collect();
+
+        DumpReport {
+            issuer: lossy(dump.issuer()),
+            name: lossy(dump.name()),
+            document_number: lossy(dump.document_number()),
+            nationality: lossy(dump.nationality()),
+            date_of_birth: lossy(dump.dob()),
+            parsed_date_of_birth: date_to_u32(dump.dob(), policy.today).ok(),
+            expiry: lossy(dump.expiry()),
+            parsed_expiry: date_to_u32(dump.expiry(), expiry_not_after).ok(),
+            biometrics_hash: format!("{:x}", Sha256::digest(&dump.dg2)),
+            digest_alg: dump.digest_alg.clone(),
+            sig_alg: dump.sig_alg.clone(),
+            certificate,
+            passive_auth,
+        }
+    }
+
+    /// Returns whether every step of passive authentication succeeded
+    pub fn passive_auth_ok(&self) -> bool {
+        self.passive_auth.len() == PassiveAuthStep::ALL.len()
+            && self.passive_auth.iter().all(|r| r.error.is_none())
+    }
+}
+
+/// Prints everything that isn't already in `PassportDump`'s `Debug` output
+impl std::fmt::Display for DumpReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Dates that couldn't be parsed are displayed as such
+        let date = |d: Option<u32>| d.map_or("unparseable".to_string(), |d| d.to_string());
+
+        writeln!(
+            f,
+            "parsed date of birth: {}",
+            date(self.parsed_date_of_birth)
+        )?;
+        writeln!(f, "parsed expiry: {}", date(self.parsed_expiry))?;
+        writeln!(f, "digest algorithm: {}", self.digest_alg)?;
+        writeln!(f, "signature algorithm: {}", self.sig_alg)?;
+        match &self.certificate {
+            Ok(cert) => {
+                writeln!(f, "certificate subject: {}", cert.subject)?;
+                writeln!(f, "certificate issuer: {}", cert.issuer)?;
+                writeln!(f, "certificate not before: {}", cert.not_before)?;
+                writeln!(f, "certificate not after: {}", cert.not_after)?;
+            }
+            Err(e) => writeln!(f, "certificate: unparseable ({})", e)?,
+        }
+        writeln!(f, "passive authentication:")?;
+        for r in &self.passive_auth {
+            match &r.error {
+                None => writeln!(f, "    {}: ok", r.step)?,
+                Some(e) => writeln!(f, "    {}: FAILED ({})", r.step, e)?,
+            }
+        }
+
+        Ok(())
+    }
+}
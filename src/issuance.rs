@@ -0,0 +1,276 @@
+use crate::{
+    error::Error,
+    groth16::{groth16_proof, groth16_vk},
+    issuance_checker::{issuance_nullifier, IssuanceReq, PassportHashChecker},
+    output::{Frame, ObjectType},
+    params::{
+        Fr, PassportComScheme, PassportComSchemeG, PredProvingKey, PredVerifyingKey, E,
+        ECONTENT_PREFIX_LEN, H, HASH_LEN, HG, STATE_ID_LEN,
+    },
+    passive_auth::passive_auth,
+    passport_dump::PassportDump,
+    passport_info::{PersonalInfo, PersonalInfoVar},
+    sig_verif::{load_usa_pubkey, IssuerPubkey},
+};
+
+use zkcreds::{
+    attrs::Attrs,
+    pred::{prove_birth, verify_birth, PredicateChecker},
+};
+
+use ark_ec::{msm::VariableBaseMSM, AffineCurve, PairingEngine, ProjectiveCurve};
+use ark_ff::{One, PrimeField, ToConstraintField, UniformRand, Zero};
+use ark_relations::r1cs::SynthesisError;
+use ark_std::{io::Read, rand::Rng};
+use rayon::prelude::*;
+use sha2::{Digest, Sha256};
+
+/// The rules an issuer applies when deciding whether a passport gets a credential
+#[derive(Clone, Debug, PartialEq)]
+pub struct IssuancePolicy {
+    /// The 3-letter code of the state that must have issued the passport
+    pub issuing_state: [u8; STATE_ID_LEN],
+    /// Today's date, as an integer whose base-10 representation is YYYYMMDD. Passports must
+    /// expire some time after this.
+    pub today: u32,
+    /// The longest that a passport can be valid, in years. This is used to parse two-digit years.
+    pub max_valid_years: u32,
+    /// The pubkey that passport signatures must verify under
+    pub trust_anchor: IssuerPubkey,
+    /// The key for issuance nullifiers (see `issuance_nullifier`). Issuers that use different keys
+    /// get unrelated nullifiers for the same passport.
+    pub nullifier_key: [u8; HASH_LEN],
+}
+
+impl IssuancePolicy {
+    /// Parses a hex-encoded nullifier key
+    pub fn parse_nullifier_key(hex: &str) -> Result<[u8; HASH_LEN], Error> {
+        let bad = || {
+            Error::Parse(format!(
+                "nullifier key must be {} hex-encoded bytes",
+                HASH_LEN
+            ))
+        };
+        if hex.len() != 2 * HASH_LEN || !hex.is_ascii() {
+            return Err(bad());
+        }
+
+        let mut key = [0u8; HASH_LEN];
+        for (i, byte) in key.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).map_err(|_| bad())?;
+        }
+        Ok(key)
+    }
+}
+
+impl Default for IssuancePolicy {
+    /// Sample parameters for passport validation. All passports must expire some time after
+    /// 2022-01-01, and be issued and signed by the USA
+    fn default() -> IssuancePolicy {
+        IssuancePolicy {
+            issuing_state: *b"USA",
+            today: 20220101u32,
+            max_valid_years: 10u32,
+            trust_anchor: load_usa_pubkey(),
+            nullifier_key: Sha256::digest(b"zkcreds-passport default nullifier key").into(),
+        }
+    }
+}
+
+/// Generates the issuance proving and verifying keys
+pub fn gen_issuance_crs<R: Rng>(rng: &mut R) -> Result<(PredProvingKey, PredVerifyingKey), Error> {
+    // Generate the hash checker circuit's CRS
+    let pk = zkcreds::pred::gen_pred_crs::<
+        _,
+        _,
+        E,
+        PersonalInfo,
+        PersonalInfoVar,
+        PassportComScheme,
+        PassportComSchemeG,
+        H,
+        HG,
+    >(rng, PassportHashChecker::default())?;
+
+    Ok((pk.clone(), pk.prepare_verifying_key()))
+}
+
+/// With their passport, a user constructs a `PersonalInfo` struct and requests issuance. Before
+/// proving, this checks natively that the passport passes passive authentication, since proving is
+/// slow and the issuer would reject the request anyway.
+pub fn user_req_issuance<R: Rng>(
+    rng: &mut R,
+    dump: &PassportDump,
+    issuance_pk: &PredProvingKey,
+    policy: &IssuancePolicy,
+) -> Result<(PersonalInfo, IssuanceReq), Error> {
+    passive_auth(dump, &policy.trust_anchor)?;
+
+    let my_info = PersonalInfo::from_passport(rng, dump, policy.today, policy.max_valid_years)?;
+    let attrs_com = my_info.commit();
+
+    // Make a hash checker struct using our private data
+    let hash_checker = PassportHashChecker::from_passport(
+        dump,
+        policy.issuing_state,
+        policy.today,
+        policy.max_valid_years,
+        policy.nullifier_key,
+    )?;
+
+    // Prove the passport hash is correctly computed
+    let hash_proof = prove_birth(rng, issuance_pk, hash_checker, my_info.clone())?;
+
+    // Now put together the issuance request
+    let req = IssuanceReq {
+        attrs_com,
+        econtent_hash: dump.econtent_hash().to_vec(),
+        econtent_prefix: dump.econtent[..ECONTENT_PREFIX_LEN].to_vec(),
+        nullifier: issuance_nullifier(&policy.nullifier_key, &dump.dg2_hash()).to_vec(),
+        sig: dump.sig.clone(),
+        hash_proof,
+    };
+
+    Ok((my_info, req))
+}
+
+/// An issuer reads an issuance request, in any format. Requests come from untrusted parties, so this
+/// deserializes strictly, and it checks that the request was made for the verifying key with the
+/// given fingerprint.
+pub fn read_issuance_req<R: Read>(r: &mut R, vk_fingerprint: &str) -> Result<IssuanceReq, Error> {
+    let frame = Frame::read(r, ObjectType::IssuanceRequest)?;
+    frame.check_key(vk_fingerprint)?;
+    frame.deser_untrusted()
+}
+
+/// An issuer takes an issuance request and validates it
+pub fn check_issuance(
+    birth_vk: &PredVerifyingKey,
+    req: &IssuanceReq,
+    policy: &IssuancePolicy,
+) -> Result<(), Error> {
+    // Check that the hash was computed correctly and the hash's signature is correct
+    let hash_checker = PassportHashChecker::from_issuance_req(
+        req,
+        policy.issuing_state,
+        policy.today,
+        policy.max_valid_years,
+        policy.nullifier_key,
+    )?;
+
+    if !verify_birth(birth_vk, &req.hash_proof, &hash_checker, &req.attrs_com)? {
+        return Err(Error::ProofFailure(
+            "issuance proof did not verify".to_string(),
+        ));
+    }
+    if !policy.trust_anchor.verify(&req.sig, &req.econtent_hash) {
+        return Err(Error::SignatureFailure);
+    }
+
+    Ok(())
+}
+
+/// An issuer checks many issuance requests at once. The proofs are checked together in a single
+/// randomized pairing check, and the signatures are checked in parallel. If the batch check fails,
+/// every proof is checked individually to find the bad ones. Returns the result of each request,
+/// in order. These are the same results `check_issuance` would give. Errors that aren't specific to
+/// one request, like a verifying key that doesn't fit the circuit, are returned as a whole.
+pub fn check_issuance_batch<R: Rng>(
+    rng: &mut R,
+    birth_vk: &PredVerifyingKey,
+    reqs: &[IssuanceReq],
+    policy: &IssuancePolicy,
+) -> Result<Vec<Result<(), Error>>, Error> {
+    let checkers: Vec<_> = reqs
+        .iter()
+        .map(|req| {
+            PassportHashChecker::from_issuance_req(
+                req,
+                policy.issuing_state,
+                policy.today,
+                policy.max_valid_years,
+                policy.nullifier_key,
+            )
+        })
+        .collect();
+    let sigs_ok: Vec<bool> = reqs
+        .par_iter()
+        .map(|req| policy.trust_anchor.verify(&req.sig, &req.econtent_hash))
+        .collect();
+
+    // Requests whose checker couldn't be made are already rejected, so leave them out of the batch
+    let batch: Vec<_> = reqs
+        .iter()
+        .zip(&checkers)
+        .filter_map(|(req, checker)| checker.as_ref().ok().map(|checker| (req, checker)))
+        .collect();
+    let batch_ok = batch_verify_birth(rng, birth_vk, &batch)?;
+
+    let results = reqs
+        .iter()
+        .zip(checkers)
+        .zip(sigs_ok)
+        .map(|((req, checker), sig_ok)| {
+            let checker = checker?;
+            if !batch_ok && !verify_birth(birth_vk, &req.hash_proof, &checker, &req.attrs_com)? {
+                return Err(Error::ProofFailure(
+                    "issuance proof did not verify".to_string(),
+                ));
+            }
+            if !sig_ok {
+                return Err(Error::SignatureFailure);
+            }
+
+            Ok(())
+        })
+        .collect();
+    Ok(results)
+}
+
+/// Checks many issuance proofs at once. Groth16 checks e(A, B) = e(α, β)·e(X, γ)·e(C, δ), where X
+/// is the combination of the public inputs. For random r_i, this checks
+/// Π e(r_i·A_i, B_i) = e(Σ r_i·α, β)·e(Σ r_i·X_i, γ)·e(Σ r_i·C_i, δ), which fails with
+/// overwhelming probability if any of the individual checks would. Like `verify_birth`, this fails
+/// if the public inputs don't fit the verifying key.
+pub fn batch_verify_birth<R: Rng>(
+    rng: &mut R,
+    birth_vk: &PredVerifyingKey,
+    batch: &[(&IssuanceReq, &PassportHashChecker)],
+) -> Result<bool, Error> {
+    type G1Projective = <E as PairingEngine>::G1Projective;
+    type G1Prepared = <E as PairingEngine>::G1Prepared;
+    type G2Prepared = <E as PairingEngine>::G2Prepared;
+
+    let vk = groth16_vk(birth_vk)?;
+    let mut pairs: Vec<(G1Prepared, G2Prepared)> = Vec::with_capacity(batch.len() + 3);
+    let mut r_sum = Fr::zero();
+    let mut x_sum = G1Projective::zero();
+    let mut c_sum = G1Projective::zero();
+
+    for (req, checker) in batch {
+        // zkcreds' public inputs are the attribute commitment followed by the predicate's own
+        let mut inputs = req.attrs_com.to_field_elements().unwrap();
+        inputs.extend(checker.public_inputs());
+        if inputs.len() + 1 != vk.gamma_abc_g1.len() {
+            return Err(SynthesisError::MalformedVerifyingKey.into());
+        }
+        let input_reprs: Vec<_> = inputs.iter().map(|x| x.into_repr()).collect();
+        let x = vk.gamma_abc_g1[0].into_projective()
+            + VariableBaseMSM::multi_scalar_mul(&vk.gamma_abc_g1[1..], &input_reprs);
+
+        let proof = groth16_proof(&req.hash_proof)?;
+        let r = Fr::rand(rng);
+        pairs.push((proof.a.mul(r).into_affine().into(), proof.b.into()));
+        r_sum += r;
+        x_sum += x.mul(r.into_repr());
+        c_sum += proof.c.mul(r);
+    }
+
+    pairs.push((
+        (-vk.alpha_g1.mul(r_sum)).into_affine().into(),
+        vk.beta_g2.into(),
+    ));
+    pairs.push(((-x_sum).into_affine().into(), vk.gamma_g2.into()));
+    pairs.push(((-c_sum).into_affine().into(), vk.delta_g2.into()));
+    Ok(E::product_of_pairings(&pairs).is_one())
+}
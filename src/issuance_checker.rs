@@ -1,13 +1,14 @@
 use crate::{
-    ark_sha256::Sha256Gadget,
+    ark_sha256::{native_midstate, Sha256Gadget, UInt32Ext},
+    error::Error,
     params::{
         Fr, PassportComScheme, PassportComSchemeG, PredProof, DATE_LEN, DG1_HASH_OFFSET, DG1_LEN,
-        DG2_HASH_OFFSET, DOB_OFFSET, ECONTENT_LEN, EXPIRY_OFFSET, HASH_LEN, ISSUER_OFFSET,
-        NAME_LEN, NAME_OFFSET, NATIONALITY_OFFSET, PRE_ECONTENT_HASH_OFFSET, PRE_ECONTENT_LEN,
-        SIG_HASH_LEN, STATE_ID_LEN,
+        DG2_HASH_OFFSET, DOB_OFFSET, ECONTENT_LEN, ECONTENT_PREFIX_LEN, EXPIRY_OFFSET, HASH_LEN,
+        ISSUER_OFFSET, MAX_SIG_LEN, NAME_LEN, NAME_OFFSET, NATIONALITY_OFFSET,
+        PRE_ECONTENT_HASH_OFFSET, PRE_ECONTENT_LEN, SIG_HASH_LEN, STATE_ID_LEN,
     },
     passport_dump::PassportDump,
-    passport_info::{PersonalInfo, PersonalInfoVar},
+    passport_info::{date_to_u32, PersonalInfo, PersonalInfoVar},
 };
 
 use zkcreds::{pred::PredicateChecker, Com};
@@ -15,7 +16,7 @@ use zkcreds::{pred::PredicateChecker, Com};
 use ark_ff::ToConstraintField;
 use ark_r1cs_std::{
     alloc::AllocVar,
-    bits::{uint8::UInt8, ToBitsGadget},
+    bits::{uint32::UInt32, uint8::UInt8, ToBitsGadget},
     boolean::Boolean,
     eq::EqGadget,
     fields::fp::FpVar,
@@ -30,28 +31,132 @@ use sha2::{Digest, Sha256};
 
 /// A request to issue attrs_com. This is includes a proof that opens the attrs and a signature
 /// over the corresponding passport's econtent hash
-#[derive(CanonicalSerialize, CanonicalDeserialize)]
-pub(crate) struct IssuanceReq {
-    pub(crate) attrs_com: Com<PassportComScheme>,
-    pub(crate) econtent_hash: Vec<u8>,
-    pub(crate) sig: Vec<u8>,
-    pub(crate) hash_proof: PredProof,
+#[derive(CanonicalSerialize)]
+pub struct IssuanceReq {
+    pub attrs_com: Com<PassportComScheme>,
+    pub econtent_hash: Vec<u8>,
+    /// The first `ECONTENT_PREFIX_LEN` bytes of econtent. The issuer hashes these itself, and the
+    /// proof shows that the rest of econtent continues from them to `econtent_hash`.
+    pub econtent_prefix: Vec<u8>,
+    /// The passport's issuance nullifier (see `issuance_nullifier`). The proof shows it was
+    /// computed correctly.
+    pub nullifier: Vec<u8>,
+    pub sig: Vec<u8>,
+    pub hash_proof: PredProof,
+}
+
+// Requests come from untrusted users, so deserialization bounds the lengths of the byte vectors
+// before reading them. Otherwise this is the same as the derived impl.
+impl CanonicalDeserialize for IssuanceReq {
+    fn deserialize<R: Read>(reader: R) -> Result<Self, SerializationError> {
+        IssuanceReq::deserialize_with(
+            reader,
+            Com::<PassportComScheme>::deserialize,
+            PredProof::deserialize,
+        )
+    }
+
+    fn deserialize_uncompressed<R: Read>(reader: R) -> Result<Self, SerializationError> {
+        IssuanceReq::deserialize_with(
+            reader,
+            Com::<PassportComScheme>::deserialize_uncompressed,
+            PredProof::deserialize_uncompressed,
+        )
+    }
+
+    fn deserialize_unchecked<R: Read>(reader: R) -> Result<Self, SerializationError> {
+        IssuanceReq::deserialize_with(
+            reader,
+            Com::<PassportComScheme>::deserialize_unchecked,
+            PredProof::deserialize_unchecked,
+        )
+    }
+}
+
+impl IssuanceReq {
+    /// Returns the request's nullifier, failing if it's the wrong length
+    pub fn nullifier_bytes(&self) -> Result<[u8; HASH_LEN], Error> {
+        if self.nullifier.len() != HASH_LEN {
+            return Err(Error::Parse(format!(
+                "nullifier is {} bytes long, expected {}",
+                self.nullifier.len(),
+                HASH_LEN
+            )));
+        }
+
+        let mut nullifier = [0u8; HASH_LEN];
+        nullifier.copy_from_slice(&self.nullifier);
+        Ok(nullifier)
+    }
+
+    // Deserializes a request, using the given functions for the commitment and the proof
+    fn deserialize_with<R: Read>(
+        mut reader: R,
+        read_com: fn(&mut R) -> Result<Com<PassportComScheme>, SerializationError>,
+        read_proof: fn(&mut R) -> Result<PredProof, SerializationError>,
+    ) -> Result<Self, SerializationError> {
+        Ok(IssuanceReq {
+            attrs_com: read_com(&mut reader)?,
+            econtent_hash: read_bounded_bytes(&mut reader, SIG_HASH_LEN)?,
+            econtent_prefix: read_bounded_bytes(&mut reader, ECONTENT_PREFIX_LEN)?,
+            nullifier: read_bounded_bytes(&mut reader, HASH_LEN)?,
+            sig: read_bounded_bytes(&mut reader, MAX_SIG_LEN)?,
+            hash_proof: read_proof(&mut reader)?,
+        })
+    }
+}
+
+/// Reads a serialized `Vec<u8>`, failing if it's longer than `max_len`
+fn read_bounded_bytes<R: Read>(
+    mut reader: R,
+    max_len: usize,
+) -> Result<Vec<u8>, SerializationError> {
+    let len = u64::deserialize(&mut reader)?;
+    if len > max_len as u64 {
+        return Err(SerializationError::InvalidData);
+    }
+
+    let mut buf = vec![0u8; len as usize];
+    reader.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+/// Computes a passport's issuance nullifier, the SHA-256 hash of the nullifier key and the hash of
+/// the passport's DG2. Every passport has the same nullifier no matter how many times it's used to
+/// request a credential, so an issuer that remembers nullifiers can refuse to issue twice. The DG2
+/// hash is signed, and DG2 holds the holder's photo, so unlike the document number, it can't be
+/// found by trying every possibility, even by someone who knows the key.
+pub fn issuance_nullifier(
+    nullifier_key: &[u8; HASH_LEN],
+    dg2_hash: &[u8; HASH_LEN],
+) -> [u8; HASH_LEN] {
+    Sha256::new()
+        .chain_update(nullifier_key)
+        .chain_update(dg2_hash)
+        .finalize()
+        .into()
 }
 
 /// Verifies that the given passport contents hashes to the correct `econtent_hash`, and that the
 /// provided `PersonalInfo` corresponds to its contents.
 #[derive(Clone)]
-pub(crate) struct PassportHashChecker {
+pub struct PassportHashChecker {
     // Public inputs
     econtent_hash: [u8; SIG_HASH_LEN],
     expected_issuer: [u8; STATE_ID_LEN],
     today: Fr,
     max_valid_years: Fr,
+    nullifier_key: [u8; HASH_LEN],
+    nullifier: [u8; HASH_LEN],
+    // The SHA-256 state, as big-endian words, after hashing the first ECONTENT_PREFIX_LEN bytes of
+    // econtent. The issuer computes this from the prefix in the request.
+    econtent_midstate: [u8; HASH_LEN],
 
     // Private inputs
     dg1: [u8; DG1_LEN],
     pre_econtent: [u8; PRE_ECONTENT_LEN],
-    econtent: [u8; ECONTENT_LEN],
+    // The rest of econtent
+    econtent_tail: [u8; ECONTENT_LEN - ECONTENT_PREFIX_LEN],
 }
 
 impl Default for PassportHashChecker {
@@ -61,9 +166,12 @@ impl Default for PassportHashChecker {
             expected_issuer: [0u8; STATE_ID_LEN],
             today: Fr::default(),
             max_valid_years: Fr::default(),
+            nullifier_key: [0u8; HASH_LEN],
+            nullifier: [0u8; HASH_LEN],
+            econtent_midstate: [0u8; HASH_LEN],
             dg1: [0u8; DG1_LEN],
             pre_econtent: [0u8; PRE_ECONTENT_LEN],
-            econtent: [0u8; ECONTENT_LEN],
+            econtent_tail: [0u8; ECONTENT_LEN - ECONTENT_PREFIX_LEN],
         }
     }
 }
@@ -71,56 +179,118 @@ impl Default for PassportHashChecker {
 impl PassportHashChecker {
     /// Makes an issuance checker given a passport, 3-letter issuing state, and today's date in the
     /// form YYYYMMDD in base-10 (this is to check DOB). `max_valid_years` is the longest that a
-    /// document can be valid, in years.
-    pub(crate) fn from_passport(
+    /// document can be valid, in years. `nullifier_key` is the issuer's key for nullifiers.
+    ///
+    /// Fails if the passport is malformed, or if it would not satisfy the checker, i.e., it wasn't
+    /// issued by `expected_issuer` or it's expired. The latter checks are done natively here so the
+    /// user doesn't waste time making a proof that will never verify.
+    pub fn from_passport(
         dump: &PassportDump,
         expected_issuer: [u8; STATE_ID_LEN],
         today: u32,
         max_valid_years: u32,
-    ) -> PassportHashChecker {
+        nullifier_key: [u8; HASH_LEN],
+    ) -> Result<PassportHashChecker, Error> {
+        dump.check_lengths()?;
+
+        // Check the issuer and expiry
+        let issuer = &dump.dg1[ISSUER_OFFSET..ISSUER_OFFSET + STATE_ID_LEN];
+        if issuer != expected_issuer {
+            return Err(Error::PolicyViolation(format!(
+                "passport was issued by {:?}, expected {:?}",
+                String::from_utf8_lossy(issuer),
+                String::from_utf8_lossy(&expected_issuer),
+            )));
+        }
+        let expiry_not_after = today + max_valid_years * 10000u32;
+        let expiry = date_to_u32(
+            &dump.dg1[EXPIRY_OFFSET..EXPIRY_OFFSET + DATE_LEN],
+            expiry_not_after,
+        )?;
+        if expiry <= today {
+            return Err(Error::PolicyViolation(format!(
+                "passport expired on {}",
+                expiry
+            )));
+        }
+
         let mut dg1 = [0u8; DG1_LEN];
         let mut pre_econtent = [0u8; PRE_ECONTENT_LEN];
-        let mut econtent = [0u8; ECONTENT_LEN];
+        let mut econtent_tail = [0u8; ECONTENT_LEN - ECONTENT_PREFIX_LEN];
         let mut econtent_hash = [0u8; SIG_HASH_LEN];
 
         dg1.copy_from_slice(&dump.dg1);
         pre_econtent.copy_from_slice(&dump.pre_econtent);
-        econtent.copy_from_slice(&dump.econtent);
-        econtent_hash.copy_from_slice(&Sha256::digest(econtent));
+        econtent_tail.copy_from_slice(&dump.econtent[ECONTENT_PREFIX_LEN..]);
+        econtent_hash.copy_from_slice(&dump.econtent_hash());
+        let econtent_midstate = econtent_midstate(&dump.econtent[..ECONTENT_PREFIX_LEN]);
 
-        PassportHashChecker {
+        Ok(PassportHashChecker {
             econtent_hash,
             expected_issuer,
             today: Fr::from(today),
             max_valid_years: Fr::from(max_valid_years),
+            nullifier_key,
+            nullifier: issuance_nullifier(&nullifier_key, &dump.dg2_hash()),
+            econtent_midstate,
             dg1,
             pre_econtent,
-            econtent,
-        }
+            econtent_tail,
+        })
     }
 
     /// Makes an issuance checker given an issuance request, a 3-letter issuing state, and today's
     /// date in the form YYYYMMDD in base-10 (this is to check expiry).  `max_valid_years` is the
-    /// longest that a document can be valid, in years.
-    pub(crate) fn from_issuance_req(
+    /// longest that a document can be valid, in years. `nullifier_key` is the issuer's key for
+    /// nullifiers.
+    pub fn from_issuance_req(
         req: &IssuanceReq,
         expected_issuer: [u8; STATE_ID_LEN],
         today: u32,
         max_valid_years: u32,
-    ) -> PassportHashChecker {
+        nullifier_key: [u8; HASH_LEN],
+    ) -> Result<PassportHashChecker, Error> {
+        if req.econtent_hash.len() != SIG_HASH_LEN {
+            return Err(Error::Parse(format!(
+                "econtent hash is {} bytes long, expected {}",
+                req.econtent_hash.len(),
+                SIG_HASH_LEN
+            )));
+        }
+        if req.econtent_prefix.len() != ECONTENT_PREFIX_LEN {
+            return Err(Error::Parse(format!(
+                "econtent prefix is {} bytes long, expected {}",
+                req.econtent_prefix.len(),
+                ECONTENT_PREFIX_LEN
+            )));
+        }
         let mut econtent_hash = [0u8; SIG_HASH_LEN];
         econtent_hash.copy_from_slice(&req.econtent_hash);
+        let nullifier = req.nullifier_bytes()?;
 
-        PassportHashChecker {
+        Ok(PassportHashChecker {
             econtent_hash,
             expected_issuer,
             today: Fr::from(today),
             max_valid_years: Fr::from(max_valid_years),
+            nullifier_key,
+            nullifier,
+            econtent_midstate: econtent_midstate(&req.econtent_prefix),
             ..Default::default()
-        }
+        })
     }
 }
 
+/// Hashes the first `ECONTENT_PREFIX_LEN` bytes of econtent, and returns the SHA-256 state as
+/// big-endian words
+fn econtent_midstate(prefix: &[u8]) -> [u8; HASH_LEN] {
+    let mut bytes = [0u8; HASH_LEN];
+    for (chunk, word) in bytes.chunks_mut(4).zip(native_midstate(prefix)) {
+        chunk.copy_from_slice(&word.to_be_bytes());
+    }
+    bytes
+}
+
 /// Converts a date string of the form YYMMDD to a field element whose canonical base-10
 /// representation is YYYYMMDD. `not_after` is the soonest day in the 21st century after which the
 /// input would not make sense, e.g., a birthdate wouldn't make sense if it were after today, and a
@@ -181,6 +351,13 @@ impl PredicateChecker<Fr, PersonalInfo, PersonalInfoVar, PassportComScheme, Pass
         let today = FpVar::<Fr>::new_input(ns!(cs, "DOB threshold"), || Ok(self.today))?;
         let max_valid_years =
             FpVar::<Fr>::new_input(ns!(cs, "max valid years"), || Ok(self.max_valid_years))?;
+        let nullifier_key = UInt8::new_input_vec(ns!(cs, "nullifier key"), &self.nullifier_key)?;
+        let nullifier = UInt8::new_input_vec(ns!(cs, "nullifier"), &self.nullifier)?;
+        let econtent_midstate =
+            UInt8::new_input_vec(ns!(cs, "econtent midstate"), &self.econtent_midstate)?
+                .chunks(4)
+                .map(UInt32::from_bytes_be)
+                .collect::<Result<Vec<_>, _>>()?;
 
         // The earliest time after which expiry doesn't make sense. This is used to parse the
         // underdefined date format in the passport
@@ -192,48 +369,83 @@ impl PredicateChecker<Fr, PersonalInfo, PersonalInfoVar, PassportComScheme, Pass
         // Witness private inputs
         let dg1 = UInt8::new_witness_vec(ns!(cs, "dg1"), &self.dg1)?;
         let pre_econtent = UInt8::new_witness_vec(ns!(cs, "pre-econtent"), &self.pre_econtent)?;
-        let econtent = UInt8::new_witness_vec(ns!(cs, "econtent"), &self.econtent)?;
+        let econtent_tail = UInt8::new_witness_vec(ns!(cs, "econtent tail"), &self.econtent_tail)?;
+
+        // Each check below is in its own namespace, so that constraint counts can be attributed
+        // to it (see the circuit_stats module)
 
         // Check that the issuer is the expected one, and the passport isn't expired
-        dg1[ISSUER_OFFSET..ISSUER_OFFSET + STATE_ID_LEN].enforce_equal(&expected_issuer)?;
-        let expiry = date_to_field_elem(
-            &dg1[EXPIRY_OFFSET..EXPIRY_OFFSET + DATE_LEN],
-            &expiry_not_after,
-        )?;
-        expiry.enforce_cmp(&today, core::cmp::Ordering::Greater, false)?;
+        {
+            let _ns = ns!(cs, "issuer and expiry");
+            dg1[ISSUER_OFFSET..ISSUER_OFFSET + STATE_ID_LEN].enforce_equal(&expected_issuer)?;
+            let expiry = date_to_field_elem(
+                &dg1[EXPIRY_OFFSET..EXPIRY_OFFSET + DATE_LEN],
+                &expiry_not_after,
+            )?;
+            expiry.enforce_cmp(&today, core::cmp::Ordering::Greater, false)?;
+        }
 
         // Check that the attr's name, nationality, and DOB match the passport's
-        dg1[NATIONALITY_OFFSET..NATIONALITY_OFFSET + STATE_ID_LEN]
-            .enforce_equal(&attrs.nationality.0)?;
-        dg1[NAME_OFFSET..NAME_OFFSET + NAME_LEN].enforce_equal(&attrs.name.0)?;
-        let dob = date_to_field_elem(&dg1[DOB_OFFSET..DOB_OFFSET + DATE_LEN], &dob_not_after)?;
-        dob.enforce_equal(&attrs.dob)?;
+        {
+            let _ns = ns!(cs, "attrs match dg1");
+            dg1[NATIONALITY_OFFSET..NATIONALITY_OFFSET + STATE_ID_LEN]
+                .enforce_equal(&attrs.nationality.0)?;
+            dg1[NAME_OFFSET..NAME_OFFSET + NAME_LEN].enforce_equal(&attrs.name.0)?;
+            let dob = date_to_field_elem(&dg1[DOB_OFFSET..DOB_OFFSET + DATE_LEN], &dob_not_after)?;
+            dob.enforce_equal(&attrs.dob)?;
+        }
 
         // Check pre-econtent structure, and check that the biometric hash matches the passport's
-        let dg1_hash = Sha256Gadget::digest(&dg1)?;
-        let dg2_hash = &attrs.biometric_hash.0;
-        pre_econtent[DG1_HASH_OFFSET..DG1_HASH_OFFSET + HASH_LEN].enforce_equal(&dg1_hash.0)?;
-        pre_econtent[DG2_HASH_OFFSET..DG2_HASH_OFFSET + HASH_LEN].enforce_equal(dg2_hash)?;
+        {
+            let _ns = ns!(cs, "dg1 hash");
+            let dg1_hash = Sha256Gadget::digest(&dg1)?;
+            let dg2_hash = &attrs.biometric_hash.0;
+            pre_econtent[DG1_HASH_OFFSET..DG1_HASH_OFFSET + HASH_LEN].enforce_equal(&dg1_hash.0)?;
+            pre_econtent[DG2_HASH_OFFSET..DG2_HASH_OFFSET + HASH_LEN].enforce_equal(dg2_hash)?;
+        }
 
         // Check the econtent structure
-        let pre_econtent_hash = Sha256Gadget::digest(&pre_econtent)?;
-        econtent[PRE_ECONTENT_HASH_OFFSET..PRE_ECONTENT_HASH_OFFSET + HASH_LEN]
-            .enforce_equal(&pre_econtent_hash.0)?;
+        {
+            let _ns = ns!(cs, "pre-econtent hash");
+            let pre_econtent_hash = Sha256Gadget::digest(&pre_econtent)?;
+            let offset = PRE_ECONTENT_HASH_OFFSET - ECONTENT_PREFIX_LEN;
+            econtent_tail[offset..offset + HASH_LEN].enforce_equal(&pre_econtent_hash.0)?;
+        }
 
-        // Check the econtent hash matches the passport's
-        econtent_hash.enforce_equal(&Sha256Gadget::digest(&econtent)?.0)?;
+        // Check that the nullifier is the keyed hash of the DG2 hash. The DG2 hash is bound to the
+        // signed econtent hash by the checks above and below, so it's the passport's.
+        {
+            let _ns = ns!(cs, "nullifier");
+            let preimage = [&nullifier_key[..], &attrs.biometric_hash.0[..]].concat();
+            nullifier.enforce_equal(&Sha256Gadget::digest(&preimage)?.0)?;
+        }
+
+        // Check the econtent hash matches the passport's. The issuer hashed the first block of
+        // econtent natively, so we pick up from its midstate. The midstate is a public input, so
+        // finding a tail that hashes to the signed econtent hash from it is a preimage attack on
+        // SHA-256's compression function. Nothing in the first block needs checking.
+        {
+            let _ns = ns!(cs, "econtent hash check");
+            let mut hasher = Sha256Gadget::from_midstate(econtent_midstate, 1);
+            hasher.update(&econtent_tail)?;
+            econtent_hash.enforce_equal(&hasher.finalize()?.0)?;
+        }
 
         // All done
         Ok(())
     }
 
-    // The public inputs are: econtent_hash, expected_issuer, today
+    // The public inputs are: econtent_hash, expected_issuer, today, max_valid_years,
+    // nullifier_key, nullifier, econtent_midstate
     fn public_inputs(&self) -> Vec<Fr> {
         [
             self.econtent_hash.to_field_elements().unwrap(),
             self.expected_issuer.to_field_elements().unwrap(),
             vec![self.today],
             vec![self.max_valid_years],
+            self.nullifier_key.to_field_elements().unwrap(),
+            self.nullifier.to_field_elements().unwrap(),
+            self.econtent_midstate.to_field_elements().unwrap(),
         ]
         .concat()
     }
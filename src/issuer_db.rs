@@ -0,0 +1,300 @@
+//! The issuer's on-disk database. It records every credential the issuer has granted, along with
+//! its leaf index, when it was granted, the nullifier of the passport it was granted for, and
+//! whether it's been revoked. This is an embedded [sled](https://docs.rs/sled) database, i.e., a
+//! directory that only one process can have open at a time.
+
+use crate::{
+    error::Error,
+    output::to_base64,
+    params::{ComTree, PassportComScheme, HASH_LEN},
+    tree::{empty_tree, TREE_HEIGHT},
+};
+
+use zkcreds::Com;
+
+use std::{
+    io,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use serde::Serialize;
+use sled::{
+    transaction::{ConflictableTransactionError, TransactionError},
+    Transactional,
+};
+
+// The keys of the next free leaf index and the next root statement epoch in the meta tree
+const NEXT_LEAF_INDEX_KEY: &[u8] = b"next_leaf_index";
+const NEXT_EPOCH_KEY: &[u8] = b"next_epoch";
+
+/// A credential the issuer granted
+#[derive(Clone, CanonicalSerialize, CanonicalDeserialize)]
+pub struct GrantRecord {
+    pub leaf_index: u64,
+    pub attrs_com: Com<PassportComScheme>,
+    /// When the credential was granted, in seconds since the Unix epoch
+    pub granted_at: u64,
+    /// The nullifier of the passport the credential was granted for
+    pub nullifier: Vec<u8>,
+    /// Revoked credentials are left out of the issuer's tree
+    pub revoked: bool,
+}
+
+/// A summary of the issuer's database
+#[derive(Serialize)]
+pub struct IssuerStatus {
+    /// The number of credentials granted, including revoked ones
+    pub num_granted: u64,
+    pub num_revoked: u64,
+    /// The leaf index the next credential will get
+    pub next_leaf_index: u64,
+    /// When the last credential was granted, in seconds since the Unix epoch
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_granted_at: Option<u64>,
+    /// The base64-encoded root of the issuer's tree
+    pub root: String,
+}
+
+impl std::fmt::Display for IssuerStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "granted: {}", self.num_granted)?;
+        writeln!(f, "revoked: {}", self.num_revoked)?;
+        writeln!(f, "next leaf index: {}", self.next_leaf_index)?;
+        if let Some(t) = self.last_granted_at {
+            writeln!(f, "last granted at: {}", t)?;
+        }
+        writeln!(f, "root: {}", self.root)?;
+
+        Ok(())
+    }
+}
+
+/// The issuer's database
+pub struct IssuerDb {
+    db: sled::Db,
+    // Leaf index (big-endian) -> GrantRecord
+    grants: sled::Tree,
+    // Nullifier -> leaf index (big-endian)
+    nullifiers: sled::Tree,
+    meta: sled::Tree,
+}
+
+impl IssuerDb {
+    /// Opens the database in the given directory, creating it if it doesn't exist
+    pub fn open(path: &Path) -> Result<IssuerDb, Error> {
+        let db = sled::open(path).map_err(db_err("couldn't open issuer database"))?;
+        IssuerDb::from_db(db)
+    }
+
+    /// Makes a database that's deleted when it's dropped
+    pub fn temporary() -> Result<IssuerDb, Error> {
+        let db = sled::Config::new()
+            .temporary(true)
+            .open()
+            .map_err(db_err("couldn't make temporary issuer database"))?;
+        IssuerDb::from_db(db)
+    }
+
+    fn from_db(db: sled::Db) -> Result<IssuerDb, Error> {
+        let open = |name| {
+            db.open_tree(name)
+                .map_err(db_err("couldn't open issuer database"))
+        };
+        Ok(IssuerDb {
+            grants: open("grants")?,
+            nullifiers: open("nullifiers")?,
+            meta: open("meta")?,
+            db,
+        })
+    }
+
+    /// Fails if a credential was already granted for the passport with this nullifier
+    pub fn check_nullifier(&self, nullifier: &[u8; HASH_LEN]) -> Result<(), Error> {
+        let seen = self
+            .nullifiers
+            .contains_key(nullifier)
+            .map_err(db_err("couldn't read issuer database"))?;
+        if seen {
+            return Err(already_granted());
+        }
+
+        Ok(())
+    }
+
+    /// Records a newly granted credential, and returns its leaf index. Fails if a credential was
+    /// already granted for the same passport, or the tree is full. The record is on disk by the
+    /// time this returns.
+    pub fn grant(
+        &self,
+        attrs_com: &Com<PassportComScheme>,
+        nullifier: &[u8; HASH_LEN],
+    ) -> Result<u64, Error> {
+        let granted_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+
+        // Check the nullifier, take the next leaf index, and record the grant all at once
+        let res = (&self.grants, &self.nullifiers, &self.meta).transaction(
+            |(grants, nullifiers, meta)| {
+                if nullifiers.get(nullifier)?.is_some() {
+                    return Err(ConflictableTransactionError::Abort(already_granted()));
+                }
+                let leaf_index = meta
+                    .get(NEXT_LEAF_INDEX_KEY)?
+                    .map(|v| decode_index(&v))
+                    .transpose()
+                    .map_err(ConflictableTransactionError::Abort)?
+                    .unwrap_or(0);
+                if leaf_index >= 1 << (TREE_HEIGHT - 1) {
+                    return Err(ConflictableTransactionError::Abort(Error::PolicyViolation(
+                        "the issuer's tree is full".to_string(),
+                    )));
+                }
+
+                let record = GrantRecord {
+                    leaf_index,
+                    attrs_com: attrs_com.clone(),
+                    granted_at,
+                    nullifier: nullifier.to_vec(),
+                    revoked: false,
+                };
+                let record = encode_record(&record).map_err(ConflictableTransactionError::Abort)?;
+                grants.insert(&leaf_index.to_be_bytes()[..], record)?;
+                nullifiers.insert(&nullifier[..], &leaf_index.to_be_bytes()[..])?;
+                meta.insert(NEXT_LEAF_INDEX_KEY, &(leaf_index + 1).to_be_bytes()[..])?;
+
+                Ok(leaf_index)
+            },
+        );
+        let leaf_index = res.map_err(tx_err)?;
+        self.flush()?;
+
+        Ok(leaf_index)
+    }
+
+    /// Marks the credential at the given leaf index as revoked. Its nullifier stays recorded, so
+    /// the passport can't be used to get another credential.
+    pub fn revoke(&self, leaf_index: u64) -> Result<(), Error> {
+        let mut record = self
+            .get(leaf_index)?
+            .ok_or_else(|| Error::Parse(format!("no credential at leaf index {}", leaf_index)))?;
+        record.revoked = true;
+        self.grants
+            .insert(&leaf_index.to_be_bytes()[..], encode_record(&record)?)
+            .map_err(db_err("couldn't write issuer database"))?;
+        self.flush()
+    }
+
+    /// Returns the record of the credential at the given leaf index, if there is one
+    pub fn get(&self, leaf_index: u64) -> Result<Option<GrantRecord>, Error> {
+        self.grants
+            .get(&leaf_index.to_be_bytes())
+            .map_err(db_err("couldn't read issuer database"))?
+            .map(|v| decode_record(&v))
+            .transpose()
+    }
+
+    /// Returns every record, in leaf index order
+    pub fn records(&self) -> Result<Vec<GrantRecord>, Error> {
+        self.grants
+            .iter()
+            .map(|kv| {
+                let (_, v) = kv.map_err(db_err("couldn't read issuer database"))?;
+                decode_record(&v)
+            })
+            .collect()
+    }
+
+    /// Makes the issuer's tree, which has every credential that hasn't been revoked at its leaf
+    /// index
+    pub fn tree(&self) -> Result<ComTree, Error> {
+        let mut tree = empty_tree();
+        for record in self.records()? {
+            if !record.revoked {
+                tree.insert(record.leaf_index, &record.attrs_com);
+            }
+        }
+
+        Ok(tree)
+    }
+
+    /// Takes the epoch for the next root statement the issuer publishes. Every call returns a
+    /// larger epoch than the last.
+    pub fn next_epoch(&self) -> Result<u64, Error> {
+        let prev = self
+            .meta
+            .fetch_and_update(NEXT_EPOCH_KEY, |v| match v.map(decode_index).transpose() {
+                Ok(epoch) => Some((epoch.unwrap_or(0) + 1).to_be_bytes().to_vec()),
+                // Leave a corrupted epoch as it is. Decoding it again below reports the error.
+                Err(_) => v.map(<[u8]>::to_vec),
+            })
+            .map_err(db_err("couldn't write issuer database"))?;
+        let epoch = prev.map(|v| decode_index(&v)).transpose()?.unwrap_or(0);
+        self.flush()?;
+
+        Ok(epoch)
+    }
+
+    /// Summarizes the database
+    pub fn status(&self) -> Result<IssuerStatus, Error> {
+        let records = self.records()?;
+        let root = to_base64(&self.tree()?.root())?;
+        let next_leaf_index = self
+            .meta
+            .get(NEXT_LEAF_INDEX_KEY)
+            .map_err(db_err("couldn't read issuer database"))?
+            .map(|v| decode_index(&v))
+            .transpose()?
+            .unwrap_or(0);
+
+        Ok(IssuerStatus {
+            num_granted: records.len() as u64,
+            num_revoked: records.iter().filter(|r| r.revoked).count() as u64,
+            next_leaf_index,
+            last_granted_at: records.iter().map(|r| r.granted_at).max(),
+            root,
+        })
+    }
+
+    fn flush(&self) -> Result<(), Error> {
+        self.db
+            .flush()
+            .map_err(db_err("couldn't write issuer database"))?;
+        Ok(())
+    }
+}
+
+fn already_granted() -> Error {
+    Error::PolicyViolation("a credential was already issued for this passport".to_string())
+}
+
+fn encode_record(record: &GrantRecord) -> Result<Vec<u8>, Error> {
+    let mut buf = Vec::new();
+    record.serialize(&mut buf)?;
+    Ok(buf)
+}
+
+fn decode_record(bytes: &[u8]) -> Result<GrantRecord, Error> {
+    GrantRecord::deserialize(bytes).map_err(Error::deser("couldn't deserialize grant record"))
+}
+
+fn decode_index(bytes: &[u8]) -> Result<u64, Error> {
+    bytes
+        .try_into()
+        .map(u64::from_be_bytes)
+        .map_err(|_| Error::Parse("corrupted index in issuer database".to_string()))
+}
+
+/// Returns a closure that wraps a database error with the given context
+fn db_err(context: &str) -> impl FnOnce(sled::Error) -> Error + '_ {
+    move |e| Error::Io(context.to_string(), io::Error::new(io::ErrorKind::Other, e))
+}
+
+fn tx_err(e: TransactionError<Error>) -> Error {
+    match e {
+        TransactionError::Abort(e) => e,
+        TransactionError::Storage(e) => db_err("couldn't write issuer database")(e),
+    }
+}
@@ -0,0 +1,278 @@
+//! A local HTTP service for an issuer. Users fetch the verifying key, submit issuance requests,
+//! and get their credential and leaf index back, then fetch the root and their auth path whenever
+//! they need to show the credential. The endpoints are
+//!
+//! | Method | Path                   | Response                                             |
+//! |--------|------------------------|------------------------------------------------------|
+//! | GET    | `/verifying-key`       | The issuance verifying key                           |
+//! | POST   | `/issue`               | The credential and its leaf index, or the rejection  |
+//! | GET    | `/root`                | The current root of the issuer's tree                |
+//! | GET    | `/root-statement`      | The issuer's signed statement about the current root |
+//! | GET    | `/auth-path/<index>`   | The auth path of the credential at that leaf index   |
+//! | GET    | `/tree`                | The issuer's whole tree, for verifiers               |
+//! | POST   | `/revoke`              | The issuer's signed statement about the new root     |
+//!
+//! Credentials are kept in an [`IssuerDb`]. Revoked credentials are left out of the tree, and have
+//! no auth path. The database can't be opened while the service is running, so credentials are
+//! revoked through the service instead, with a [`SignedRevocation`] signed by the issuer's key.
+//!
+//! Verifiers only accept recent root statements (see [`crate::root_statement::Freshness`]), so the
+//! statement is signed again once it's an hour old, even if the root hasn't changed.
+//!
+//! Request bodies can be in any format the CLI reads. Responses are always JSON envelopes, and
+//! errors are JSON objects with the error's `kind` and `msg`.
+
+use crate::{
+    error::Error,
+    http::{self, read_json, status_code, Response},
+    issuance::{check_issuance, read_issuance_req, IssuancePolicy},
+    issuer_db::IssuerDb,
+    output::{deser_untrusted_from_reader, fingerprint, to_frame, Envelope, ObjectType, Verdict},
+    params::{ComTree, ComTreePath, PredVerifyingKey},
+    root_statement::{IssuerSigningKey, RootStatement, SignedRevocation, SignedRootStatement},
+};
+
+// How old the root statement gets before it's signed again. This is well within the default max
+// age that verifiers accept.
+const STATEMENT_REFRESH_AGE: u64 = 60 * 60;
+
+/// An issuer's state: its keys, its policy, and its database of the credentials it has issued so
+/// far
+pub struct IssuerService {
+    vk: PredVerifyingKey,
+    vk_fingerprint: String,
+    signing_key: IssuerSigningKey,
+    policy: IssuancePolicy,
+    db: IssuerDb,
+    // The tree of every credential in the database that hasn't been revoked
+    tree: ComTree,
+    // A copy of the tree that auth paths are read from. The only way to get a leaf's auth path is
+    // to insert the leaf, which doesn't change the tree when the leaf is already there, but still
+    // takes a mutable tree. Keeping a copy means a GET never touches the live tree.
+    path_tree: ComTree,
+    // The signed statement about the tree's current root
+    statement: SignedRootStatement,
+}
+
+impl IssuerService {
+    /// Makes an issuer service that keeps its credentials in the given database. Its credentials
+    /// are loaded into the tree, and every credential issued from now on is recorded in it, so it
+    /// survives restarts and can be turned into a tree offline. A root statement is signed with
+    /// `signing_key` on startup, and again every time the root changes.
+    pub fn new(
+        vk: PredVerifyingKey,
+        signing_key: IssuerSigningKey,
+        policy: IssuancePolicy,
+        db: IssuerDb,
+    ) -> Result<IssuerService, Error> {
+        let vk_fingerprint = fingerprint(&vk)?;
+        let tree = db.tree()?;
+        // Trees don't implement Clone, so build the copy from the database too
+        let path_tree = db.tree()?;
+        let statement = sign_root(&tree, &db, &signing_key, &vk_fingerprint)?;
+
+        Ok(IssuerService {
+            vk_fingerprint,
+            vk,
+            signing_key,
+            policy,
+            db,
+            tree,
+            path_tree,
+            statement,
+        })
+    }
+
+    /// Handles a single HTTP request
+    pub fn handle(&mut self, method: &str, path: &str, body: &[u8]) -> Response {
+        let trimmed = path.trim_end_matches('/');
+        let leaf_index = trimmed.strip_prefix("/auth-path/");
+        let res = match (method, trimmed, leaf_index) {
+            ("GET", "/verifying-key", _) => self.verifying_key(),
+            ("POST", "/issue", _) => return self.issue(body),
+            ("GET", "/root", _) => self.root(),
+            ("GET", "/root-statement", _) => self.root_statement(),
+            ("GET", "/tree", _) => self.tree(),
+            ("POST", "/revoke", _) => self.revoke(body),
+            ("GET", _, Some(leaf_index)) => self.auth_path(leaf_index),
+            _ => return Response::not_found(method, path),
+        };
+
+        res.unwrap_or_else(|e| Response::error(&e))
+    }
+
+    /// Serves requests on the given address until the process is killed. Requests are handled one
+    /// at a time, in the order they arrive, so leaf indices are handed out in order.
+    pub fn serve(mut self, addr: &str) -> Result<(), Error> {
+        http::serve(addr, |method, path, body| self.handle(method, path, body))
+    }
+
+    fn verifying_key(&self) -> Result<Response, Error> {
+        let envelope = Envelope::new(ObjectType::VerifyingKey)
+            .key_fingerprint(self.vk_fingerprint.clone())
+            .payload(&self.vk)?;
+        Response::envelope(200, &envelope)
+    }
+
+    fn tree(&self) -> Result<Response, Error> {
+        // Trees don't implement Clone, so rebuild one from the database
+        let tree = self.db.tree()?;
+        let envelope = Envelope::new(ObjectType::Tree)
+            .root(&tree.root())?
+            .payload(&tree.into_wire_format())?;
+        Response::envelope(200, &envelope)
+    }
+
+    fn root(&self) -> Result<Response, Error> {
+        let root = self.tree.root();
+        let envelope = Envelope::new(ObjectType::Root)
+            .root(&root)?
+            .payload(&root)?;
+        Response::envelope(200, &envelope)
+    }
+
+    fn root_statement(&mut self) -> Result<Response, Error> {
+        if self.statement.statement.age() >= STATEMENT_REFRESH_AGE {
+            self.statement = sign_root(
+                &self.tree,
+                &self.db,
+                &self.signing_key,
+                &self.vk_fingerprint,
+            )?;
+        }
+
+        self.statement_envelope()
+    }
+
+    fn statement_envelope(&self) -> Result<Response, Error> {
+        let envelope = Envelope::new(ObjectType::RootStatement)
+            .key_fingerprint(self.vk_fingerprint.clone())
+            .root(&self.statement.statement.root)?
+            .payload(&self.statement)?;
+        Response::envelope(200, &envelope)
+    }
+
+    /// Revokes a credential on the issuer's signed order, and signs a statement about the new root
+    fn revoke(&mut self, mut body: &[u8]) -> Result<Response, Error> {
+        let revocation: SignedRevocation =
+            deser_untrusted_from_reader(&mut body, ObjectType::Revocation)?;
+        let leaf_index = match revocation.verify(&self.signing_key.public_key()) {
+            Ok(leaf_index) => leaf_index,
+            Err(e) => return Ok(Response::error_with_status(403, &e)),
+        };
+        self.db.revoke(leaf_index)?;
+
+        // Leaves can't be taken out of a tree, so rebuild both from the database
+        self.tree = self.db.tree()?;
+        self.path_tree = self.db.tree()?;
+        self.statement = sign_root(
+            &self.tree,
+            &self.db,
+            &self.signing_key,
+            &self.vk_fingerprint,
+        )?;
+        self.statement_envelope()
+    }
+
+    /// Checks the request, and on success, adds the credential to the tree. Rejections get an
+    /// issuance verdict, like `issue-grant` outputs in JSON mode.
+    fn issue(&mut self, body: &[u8]) -> Response {
+        let res = self.try_issue(body);
+        res.unwrap_or_else(|e| {
+            let envelope = Envelope::new(ObjectType::IssuanceVerdict)
+                .key_fingerprint(self.vk_fingerprint.clone())
+                .verdict(Verdict::from(&e));
+            Response::envelope(status_code(&e), &envelope).unwrap_or_else(|e| Response::error(&e))
+        })
+    }
+
+    fn try_issue(&mut self, mut body: &[u8]) -> Result<Response, Error> {
+        let req = read_issuance_req(&mut body, &self.vk_fingerprint)?;
+        // Checking the nullifier is cheap, so do it first
+        let nullifier = req.nullifier_bytes()?;
+        self.db.check_nullifier(&nullifier)?;
+        check_issuance(&self.vk, &req, &self.policy)?;
+
+        // Record the credential before putting it in the tree, so that a credential we've handed
+        // out is never lost in a restart
+        let leaf_index = self.db.grant(&req.attrs_com, &nullifier)?;
+        self.tree.insert(leaf_index, &req.attrs_com);
+        self.path_tree.insert(leaf_index, &req.attrs_com);
+        self.statement = sign_root(
+            &self.tree,
+            &self.db,
+            &self.signing_key,
+            &self.vk_fingerprint,
+        )?;
+
+        let cred = Envelope::new(ObjectType::Credential)
+            .key_fingerprint(self.vk_fingerprint.clone())
+            .payload(&req.attrs_com)?
+            .verdict(Verdict::Accepted)
+            .leaf_index(leaf_index)
+            .root(&self.tree.root())?;
+        Response::envelope(200, &cred)
+    }
+
+    fn auth_path(&mut self, leaf_index: &str) -> Result<Response, Error> {
+        let leaf_index: u64 = leaf_index
+            .parse()
+            .map_err(|_| Error::Parse(format!("malformed leaf index {:?}", leaf_index)))?;
+        let record = match self.db.get(leaf_index)? {
+            Some(record) if !record.revoked => record,
+            Some(_) => {
+                let e = Error::Parse(format!(
+                    "credential at leaf index {} was revoked",
+                    leaf_index
+                ));
+                return Ok(Response::error_with_status(404, &e));
+            }
+            None => {
+                let e = Error::Parse(format!("no credential at leaf index {}", leaf_index));
+                return Ok(Response::error_with_status(404, &e));
+            }
+        };
+
+        let path: ComTreePath = self.path_tree.insert(leaf_index, &record.attrs_com);
+        let envelope = Envelope::new(ObjectType::AuthPath)
+            .key_fingerprint(self.vk_fingerprint.clone())
+            .leaf_index(leaf_index)
+            .root(&self.path_tree.root())?
+            .payload(&path)?;
+        Response::envelope(200, &envelope)
+    }
+}
+
+/// Signs a statement about the tree's current root, under the next epoch in the database
+fn sign_root(
+    tree: &ComTree,
+    db: &IssuerDb,
+    signing_key: &IssuerSigningKey,
+    vk_fingerprint: &str,
+) -> Result<SignedRootStatement, Error> {
+    RootStatement::new(tree.root(), db.next_epoch()?, vk_fingerprint)?.sign(signing_key)
+}
+
+/// Revokes the credential at the given leaf index in the issuer service at `issuer_url`, and
+/// returns the service's signed statement about its new root. The order is signed with the
+/// issuer's signing key, so only the issuer can revoke credentials.
+pub fn revoke_remote(
+    issuer_url: &str,
+    signing_key: &IssuerSigningKey,
+    leaf_index: u64,
+) -> Result<SignedRootStatement, Error> {
+    let url = format!("{}/revoke", issuer_url.trim_end_matches('/'));
+    let revocation = SignedRevocation::new(leaf_index, signing_key);
+    let body = base64::encode(to_frame(ObjectType::Revocation, None, &revocation)?);
+    let (status, body) = http::post(&url, body.as_bytes())?;
+
+    let envelope: Envelope = read_json(&url, status, &body)?;
+    let payload = envelope
+        .payload
+        .ok_or_else(|| Error::Deserialization(format!("{} has no payload", url)))?;
+    let statement: SignedRootStatement =
+        deser_untrusted_from_reader(&mut payload.as_bytes(), ObjectType::RootStatement)?;
+    statement.verify(&signing_key.public_key())?;
+
+    Ok(statement)
+}
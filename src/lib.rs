@@ -0,0 +1,50 @@
+//! Proof-of-concept zkcreds issuance from real US passports.
+//!
+//! A user with a passport dump ([`PassportDump`]) proves in zero knowledge that a commitment to
+//! their [`PersonalInfo`] was correctly derived from a passport signed by the issuing state, and
+//! sends the resulting [`IssuanceReq`] to an issuer. The issuer checks it with [`check_issuance`]
+//! and adds the commitment to its credential tree (see [`tree`]). Credentials can then be shown
+//! using the predicates in [`preds`] (see [`show`]).
+//!
+//! The command line interface in this crate is a thin wrapper around this API.
+
+pub mod ark_sha256;
+pub mod circuit_stats;
+pub mod error;
+pub mod groth16;
+mod http;
+pub mod inspect;
+pub mod issuance;
+pub mod issuance_checker;
+pub mod issuer_db;
+pub mod issuer_service;
+pub mod mpc;
+pub mod output;
+pub mod params;
+pub mod passive_auth;
+pub mod passport_dump;
+pub mod passport_info;
+#[cfg(feature = "poseidon")]
+pub mod poseidon;
+pub mod powers_of_tau;
+pub mod preds;
+pub mod public_params;
+pub mod root_statement;
+pub mod show;
+pub mod sig_verif;
+#[cfg(feature = "bn254")]
+pub mod solidity;
+pub mod test_passport;
+pub mod tree;
+pub mod verifier_service;
+pub mod wallet;
+
+pub use error::Error;
+pub use http::Response;
+pub use issuance::{
+    check_issuance, check_issuance_batch, gen_issuance_crs, read_issuance_req, user_req_issuance,
+    IssuancePolicy,
+};
+pub use issuance_checker::{IssuanceReq, PassportHashChecker};
+pub use passport_dump::PassportDump;
+pub use passport_info::{PersonalInfo, PersonalInfoVar};
@@ -1,114 +1,85 @@
-mod ark_sha256;
-mod issuance_checker;
-mod params;
-mod passport_dump;
-mod passport_info;
-mod sig_verif;
-
-use issuance_checker::{IssuanceReq, PassportHashChecker};
-use params::{
-    ComTree, ComTreeWireFormat, PassportComScheme, PassportComSchemeG, PredProvingKey,
-    PredVerifyingKey, H, HG, MERKLE_CRH_PARAM, STATE_ID_LEN,
-};
-use passport_dump::PassportDump;
-use passport_info::{PersonalInfo, PersonalInfoVar};
-use sig_verif::load_usa_pubkey;
-
-use zkcreds::{
-    attrs::Attrs,
-    pred::{prove_birth, verify_birth},
-    Com,
+use zkcreds_passport_cli::{
+    check_issuance, check_issuance_batch,
+    circuit_stats::{circuit_stats, Circuit},
+    inspect::DumpReport,
+    issuer_db::IssuerDb,
+    issuer_service::{revoke_remote, IssuerService},
+    mpc::{crs_contribute, crs_init, gen_circuit_crs, verify_transcript, CrsTranscript},
+    output::{
+        deser_from_file, deser_untrusted_from_reader, fingerprint, Envelope, Frame, ObjectType,
+        OutputFormat, StorageFormat, Verdict,
+    },
+    params::{
+        ComTreePath, ComTreeWireFormat, Fr, PassportComScheme, PredProvingKey, PredVerifyingKey, H,
+        MERKLE_CRH_PARAM, PASSPORT_COM_PARAM,
+    },
+    powers_of_tau::{tau_contribute, tau_init, TauTranscript},
+    public_params::{export_params, verify_params, ExportedParams},
+    read_issuance_req,
+    root_statement::{
+        Freshness, IssuerPublicKey, IssuerSigningKey, RootStatement, SignedRevocation,
+        SignedRootStatement,
+    },
+    show::{
+        cached_membership_proofs, decode_challenge, encode_challenge, gen_membership_crs,
+        prove_show_with_membership, verify_show, MembershipProvingKey, MembershipVerifyingKey,
+        ShowPolicy, ShowProof,
+    },
+    sig_verif::IssuerPubkey,
+    test_passport::{gen_test_passport, TestPassportParams},
+    tree::{tree_from_creds, tree_from_wire_format},
+    user_req_issuance,
+    verifier_service::{present, VerifierService},
+    wallet::{read_passphrase, Wallet, WalletCred},
+    Error, IssuancePolicy, IssuanceReq, PassportDump, PersonalInfo,
 };
 
+#[cfg(feature = "bn254")]
+use zkcreds_passport_cli::solidity::solidity_verifier;
+
+use zkcreds::Com;
+
 use std::{
-    fs::File,
+    fs::{File, OpenOptions},
     io::{self, BufRead},
+    path::{Path, PathBuf},
+    time::Duration,
 };
 
-use ark_bls12_381::Bls12_381;
-use ark_serialize::{CanonicalDeserialize, CanonicalSerialize, Read, SerializationError, Write};
-use ark_std::rand::Rng;
-
-const LOG2_NUM_LEAVES: u32 = 31;
-const LOG2_NUM_TREES: u32 = 8;
-const TREE_HEIGHT: u32 = LOG2_NUM_LEAVES + 1 - LOG2_NUM_TREES;
-
-// Sample parameters for passport validation. All passports must expire some time after TODAY, and
-// be issued by ISSUING_STATE
-const TODAY: u32 = 20220101u32;
-const MAX_VALID_YEARS: u32 = 10u32;
-const ISSUING_STATE: [u8; STATE_ID_LEN] = *b"USA";
-
-fn gen_issuance_crs<R: Rng>(rng: &mut R) -> (PredProvingKey, PredVerifyingKey) {
-    // Generate the hash checker circuit's CRS
-    let pk = zkcreds::pred::gen_pred_crs::<
-        _,
-        _,
-        Bls12_381,
-        PersonalInfo,
-        PersonalInfoVar,
-        PassportComScheme,
-        PassportComSchemeG,
-        H,
-        HG,
-    >(rng, PassportHashChecker::default())
-    .unwrap();
-
-    (pk.clone(), pk.prepare_verifying_key())
-}
-
-/// With their passport, a user constructs a `PersonalInfo` struct and requests issuance
-fn user_req_issuance<R: Rng>(
-    rng: &mut R,
-    dump: &PassportDump,
-    issuance_pk: &PredProvingKey,
-) -> (PersonalInfo, IssuanceReq) {
-    let my_info = PersonalInfo::from_passport(rng, &dump, TODAY, MAX_VALID_YEARS);
-    let attrs_com = my_info.commit();
-
-    // Make a hash checker struct using our private data
-    let hash_checker =
-        PassportHashChecker::from_passport(&dump, ISSUING_STATE, TODAY, MAX_VALID_YEARS);
-
-    // Prove the passport hash is correctly computed
-    let hash_proof = prove_birth(rng, issuance_pk, hash_checker, my_info.clone()).unwrap();
-
-    // Now put together the issuance request
-    let req = IssuanceReq {
-        attrs_com,
-        econtent_hash: dump.econtent_hash().to_vec(),
-        sig: dump.sig.clone(),
-        hash_proof,
-    };
+use ark_crypto_primitives::crh::TwoToOneCRH;
+use ark_ff::UniformRand;
+use clap::{Args, Parser, Subcommand};
+use rand::{CryptoRng, Rng};
 
-    (my_info, req)
-}
-
-/// An issuer takes an issuance request and validates it
-#[must_use]
-fn check_issuance(birth_vk: &PredVerifyingKey, req: &IssuanceReq) -> bool {
-    // Check that the hash was computed correctly and the hash's signature is correct
-    let hash_checker =
-        PassportHashChecker::from_issuance_req(req, ISSUING_STATE, TODAY, MAX_VALID_YEARS);
-    let sig_pubkey = load_usa_pubkey();
+#[derive(Parser)]
+struct Cli {
+    /// The format of the output. `json` wraps every output in a JSON envelope with metadata.
+    /// Every command accepts inputs in either format.
+    #[clap(long, global = true, arg_enum, default_value = "human")]
+    format: OutputFormat,
 
-    verify_birth(birth_vk, &req.hash_proof, &hash_checker, &req.attrs_com).unwrap()
-        && sig_pubkey.verify(&req.sig, &req.econtent_hash)
-}
+    /// Path to a PEM-encoded pubkey that passport signatures must verify under. Defaults to the
+    /// US State Department's passport signing pubkey.
+    #[clap(long, global = true, parse(from_os_str), value_name = "FILE")]
+    trust_anchor: Option<PathBuf>,
 
-use clap::{Parser, Subcommand};
-use std::path::PathBuf;
+    /// The hex-encoded 32-byte key for issuance nullifiers. Holders and the issuer must use the
+    /// same one. Defaults to the SHA-256 hash of "zkcreds-passport default nullifier key".
+    #[clap(long, global = true, value_name = "HEX")]
+    nullifier_key: Option<String>,
 
-#[derive(Parser)]
-struct Cli {
     #[clap(subcommand)]
     command: Command,
 }
 
 #[derive(Subcommand)]
 enum Command {
-    /// Generates the CRS for passport issuance
+    /// Generates the CRS for passport issuance, or for showing a credential
     GenCrs {
+        /// The circuit to generate a CRS for
+        #[clap(long, arg_enum, default_value = "issuance")]
+        circuit: Circuit,
+
         /// Path to the outputted issuance proving key
         #[clap(short, long, parse(from_os_str), value_name = "FILE")]
         proving_key: PathBuf,
@@ -116,6 +87,28 @@ enum Command {
         /// Path to the outputted issuance verifying key
         #[clap(short, long, parse(from_os_str), value_name = "FILE")]
         verifying_key: PathBuf,
+
+        /// How to store the keys. The binary formats are a quarter smaller, and skip base64 decoding
+        /// when loaded. They're written as is, regardless of --format.
+        #[clap(long, arg_enum, default_value = "base64")]
+        key_format: StorageFormat,
+    },
+
+    /// Generates the CRS for proving that a shown credential is in the issuer's tree. Every show
+    /// needs this along with the CRS for its predicate.
+    GenMembershipCrs {
+        /// Path to the outputted membership proving key
+        #[clap(short, long, parse(from_os_str), value_name = "FILE")]
+        proving_key: PathBuf,
+
+        /// Path to the outputted membership verifying key
+        #[clap(short, long, parse(from_os_str), value_name = "FILE")]
+        verifying_key: PathBuf,
+
+        /// How to store the keys. The binary formats are a quarter smaller, and skip base64 decoding
+        /// when loaded. They're written as is, regardless of --format.
+        #[clap(long, arg_enum, default_value = "base64")]
+        key_format: StorageFormat,
     },
 
     /// Outputs to STDOUT a base64-encoded issuance request. The input is a JSON-encoded passport
@@ -128,128 +121,1552 @@ enum Command {
         /// Path to the passport dump JSON file
         #[clap(short, long, parse(from_os_str), value_name = "FILE")]
         dump_file: PathBuf,
+
+        /// Path to save the opening of the credential to. This is needed to show the credential
+        /// later, and must be kept secret, so only the file's owner can read it. Prefer
+        /// --wallet-cred, which keeps it encrypted.
+        #[clap(long, parse(from_os_str), value_name = "FILE")]
+        info_out: Option<PathBuf>,
+
+        /// The name to save the opening of the credential under in the wallet. Once the issuer
+        /// grants the credential, give it its leaf index with wallet update.
+        #[clap(long, value_name = "NAME")]
+        wallet_cred: Option<String>,
+
+        #[clap(flatten)]
+        wallet: WalletArgs,
     },
 
     /// Checks a base64-encoded IssuanceReq, given via STDIN. On verification success, outputs a
     /// base64-encoded credential to STDOUT and exits with exit code 0. On failure, exits with
-    /// nonzero exit code.
+    /// the nonzero exit code corresponding to the reason (see the README).
+    ///
+    /// With --batch, checks every request in the given file at once instead. The output has a line
+    /// for every request in the file, in order: in human mode, accepted requests get their
+    /// credential on STDOUT and rejected ones get a logfmt line on STDERR; in JSON mode, every
+    /// request gets an envelope on STDOUT. The exit code is 0 even if some requests are rejected.
+    ///
+    /// With --db, granted credentials are recorded in the issuer's database, and requests for
+    /// passports that already have a credential in it are rejected. Without it, credentials get no
+    /// leaf index, and nothing stops a passport from getting more than one.
     IssueGrant {
         /// Path to the issuance verifying key
         #[clap(short, long, parse(from_os_str), value_name = "FILE")]
         verifying_key: PathBuf,
+
+        /// Path to a file of issuance requests, one base64-encoded request per line
+        #[clap(long, parse(from_os_str), value_name = "FILE")]
+        batch: Option<PathBuf>,
+
+        /// Path to the issuer's database directory. It's created if it doesn't exist.
+        #[clap(long, parse(from_os_str), value_name = "DIR")]
+        db: Option<PathBuf>,
     },
 
-    /// Turns a list of credentials into a sparse merkle tree. Tree is outputted in base64 to
-    /// STDOUT.
+    /// Turns a list of credentials, or the credentials in the issuer's database, into a sparse
+    /// merkle tree. Tree is outputted in base64 to STDOUT.
     GenTree {
         /// Path to creds file. Every line should be a base64-encoded credential outputted by the
         /// issue command.
-        #[clap(short, long, parse(from_os_str), value_name = "FILE")]
-        creds: PathBuf,
+        #[clap(
+            short,
+            long,
+            parse(from_os_str),
+            value_name = "FILE",
+            required_unless_present = "db",
+            conflicts_with = "db"
+        )]
+        creds: Option<PathBuf>,
+
+        /// Path to the issuer's database directory. Revoked credentials are left out of the tree.
+        #[clap(long, parse(from_os_str), value_name = "DIR")]
+        db: Option<PathBuf>,
     },
 
-    /// Computes the root of the given sparse merkle tree, and outputs it in base64 to STDOUT
+    /// Computes the root of the given sparse merkle tree, or of the tree in the issuer's database,
+    /// and outputs it in base64 to STDOUT
     GetRoot {
         /// Path to tree file
+        #[clap(
+            short,
+            long,
+            parse(from_os_str),
+            value_name = "FILE",
+            required_unless_present = "db",
+            conflicts_with = "db"
+        )]
+        tree: Option<PathBuf>,
+
+        /// Path to the issuer's database directory
+        #[clap(long, parse(from_os_str), value_name = "DIR")]
+        db: Option<PathBuf>,
+    },
+
+    /// Summarizes the issuer's database: how many credentials it has granted and revoked, the leaf
+    /// index of the next one, when the last one was granted, and the root of its tree
+    IssuerStatus {
+        /// Path to the issuer's database directory
+        #[clap(long, parse(from_os_str), value_name = "DIR")]
+        db: PathBuf,
+    },
+
+    /// Revokes the credential at the given leaf index in the issuer's database. It's left out of
+    /// every tree made from then on, and its passport still can't get another credential.
+    ///
+    /// With --issuer-url, revokes it in a running issuer service instead, which holds the
+    /// database. The service takes it out of its tree right away, and outputs to STDOUT its signed
+    /// statement about the new root.
+    RevokeCred {
+        /// Path to the issuer's database directory
+        #[clap(
+            long,
+            parse(from_os_str),
+            value_name = "DIR",
+            required_unless_present = "issuer-url",
+            conflicts_with = "issuer-url"
+        )]
+        db: Option<PathBuf>,
+
+        /// The URL of the issuer service
+        #[clap(long, value_name = "URL", requires = "signing-key")]
+        issuer_url: Option<String>,
+
+        /// Path to the issuer's signing key, which the service checks the order against
+        #[clap(long, parse(from_os_str), value_name = "FILE", requires = "issuer-url")]
+        signing_key: Option<PathBuf>,
+
+        /// The leaf index of the credential to revoke
+        #[clap(long)]
+        leaf_index: u64,
+    },
+
+    /// Generates the issuer's key for signing root statements
+    GenIssuerKey {
+        /// Path to the outputted signing key. Keep this secret.
+        #[clap(long, parse(from_os_str), value_name = "FILE")]
+        signing_key: PathBuf,
+
+        /// Path to the outputted public key, for verifiers
+        #[clap(long, parse(from_os_str), value_name = "FILE")]
+        public_key: PathBuf,
+    },
+
+    /// Outputs to STDOUT a signed statement about the root of the issuer's tree, which verifiers
+    /// check the tree against. The statement has the root, an epoch, the time, the tree
+    /// parameters, and the fingerprint of the issuance verifying key.
+    PublishRoot {
+        /// Path to the issuer's signing key
+        #[clap(long, parse(from_os_str), value_name = "FILE")]
+        signing_key: PathBuf,
+
+        /// Path to the issuance verifying key
+        #[clap(short, long, parse(from_os_str), value_name = "FILE")]
+        verifying_key: PathBuf,
+
+        /// Path to the issuer's database directory. The epoch is one more than the last one
+        /// published from it.
+        #[clap(long, parse(from_os_str), value_name = "DIR")]
+        db: Option<PathBuf>,
+
+        /// Path to the tree file, to use instead of the database
+        #[clap(
+            short,
+            long,
+            parse(from_os_str),
+            value_name = "FILE",
+            required_unless_present = "db",
+            conflicts_with = "db",
+            requires = "epoch"
+        )]
+        tree: Option<PathBuf>,
+
+        /// The epoch of the statement, if --tree is given. This must go up with every statement.
+        #[clap(long, requires = "tree")]
+        epoch: Option<u64>,
+    },
+
+    /// Prints the contents of a passport dump, the dates as the issuance checker parses them, the
+    /// Document Signer certificate, and the result of each step of passive authentication. This
+    /// exits with exit code 0 even if passive authentication fails.
+    InspectDump {
+        /// Path to the passport dump JSON file
+        #[clap(short, long, parse(from_os_str), value_name = "FILE")]
+        dump_file: PathBuf,
+    },
+
+    /// Generates a synthetic passport dump signed by a throwaway Document Signer, along with the
+    /// pubkey it verifies under. Pass the pubkey to other commands via --trust-anchor.
+    GenTestPassport {
+        /// Path to the outputted passport dump JSON file
+        #[clap(short, long, parse(from_os_str), value_name = "FILE")]
+        dump_file: PathBuf,
+
+        /// Path to the outputted PEM-encoded Document Signer pubkey
+        #[clap(short, long, parse(from_os_str), value_name = "FILE")]
+        trust_anchor_out: PathBuf,
+
+        /// The holder's name in MRZ form, e.g., DOE<<JANE
+        #[clap(long, default_value = "DOE<<JANE")]
+        name: String,
+
+        /// The 3-letter code of the issuing state
+        #[clap(long, default_value = "USA")]
+        issuer: String,
+
+        /// The holder's date of birth, in the form YYMMDD
+        #[clap(long, default_value = "900101")]
+        dob: String,
+
+        /// The passport's expiry date, in the form YYMMDD
+        #[clap(long, default_value = "300101")]
+        expiry: String,
+    },
+
+    /// Synthesizes a circuit and prints its number of constraints, broken down by namespace
+    CircuitStats {
+        /// The circuit to measure
+        #[clap(long, arg_enum, default_value = "issuance")]
+        circuit: Circuit,
+
+        /// How many levels of nested namespaces to report
+        #[clap(long, default_value = "2")]
+        depth: usize,
+    },
+
+    /// Starts phase 1 of a multi-party ceremony: computing powers of tau that any circuit's CRS can
+    /// then be derived from. The powers are useless until someone contributes to them.
+    TauInit {
+        /// The base-2 log of the largest circuit size the powers will support. See crs-init.
+        #[clap(long)]
+        log_size: u32,
+
+        /// Path to the outputted powers of tau transcript
+        #[clap(short, long, parse(from_os_str), value_name = "FILE")]
+        transcript: PathBuf,
+    },
+
+    /// Adds a fresh random contribution to a powers of tau transcript, in place. Outputs to STDOUT
+    /// the hash of the contribution, which the participant should publish.
+    TauContribute {
+        /// Path to the powers of tau transcript
+        #[clap(short, long, parse(from_os_str), value_name = "FILE")]
+        transcript: PathBuf,
+    },
+
+    /// Starts phase 2 of a multi-party ceremony for a circuit's CRS, by checking a powers of tau
+    /// transcript and deriving the circuit's CRS from it. If the powers are too small for the
+    /// circuit, the error says how large they need to be.
+    CrsInit {
+        /// The circuit to generate a CRS for
+        #[clap(long, arg_enum, default_value = "issuance")]
+        circuit: Circuit,
+
+        /// Path to the powers of tau transcript
+        #[clap(long, parse(from_os_str), value_name = "FILE")]
+        powers_of_tau: PathBuf,
+
+        /// Path to the outputted ceremony transcript
+        #[clap(short, long, parse(from_os_str), value_name = "FILE")]
+        transcript: PathBuf,
+    },
+
+    /// Adds a fresh random contribution to a ceremony transcript, in place. Outputs to STDOUT the
+    /// hash of the contribution, which the participant should publish.
+    CrsContribute {
+        /// Path to the ceremony transcript
+        #[clap(short, long, parse(from_os_str), value_name = "FILE")]
+        transcript: PathBuf,
+    },
+
+    /// Checks every contribution in a ceremony transcript. On success, outputs the resulting
+    /// proving and verifying keys.
+    CrsVerifyTranscript {
+        /// The circuit the CRS is for
+        #[clap(long, arg_enum, default_value = "issuance")]
+        circuit: Circuit,
+
+        /// Path to the powers of tau transcript the CRS was derived from
+        #[clap(long, parse(from_os_str), value_name = "FILE")]
+        powers_of_tau: PathBuf,
+
+        /// Path to the ceremony transcript
+        #[clap(short, long, parse(from_os_str), value_name = "FILE")]
+        transcript: PathBuf,
+
+        /// Path to the outputted proving key
+        #[clap(short, long, parse(from_os_str), value_name = "FILE")]
+        proving_key: PathBuf,
+
+        /// Path to the outputted verifying key
+        #[clap(short, long, parse(from_os_str), value_name = "FILE")]
+        verifying_key: PathBuf,
+
+        /// How to store the keys. See gen-crs.
+        #[clap(long, arg_enum, default_value = "base64")]
+        key_format: StorageFormat,
+    },
+
+    /// Converts a file made by this tool to another storage format. In base64, the output is in
+    /// the format given by --format.
+    Convert {
+        /// Path to the file to convert
+        #[clap(short, long, parse(from_os_str), value_name = "FILE")]
+        input: PathBuf,
+
+        /// Path to the outputted file
+        #[clap(short, long, parse(from_os_str), value_name = "FILE")]
+        output: PathBuf,
+
+        /// The storage format to convert to
+        #[clap(long, arg_enum)]
+        to: StorageFormat,
+    },
+
+    /// Runs an HTTP service that checks issuance requests and hands out credentials, roots, and
+    /// auth paths. See the README for the endpoints.
+    ServeIssuer {
+        /// The address to listen on
+        #[clap(long, default_value = "127.0.0.1:8080")]
+        bind: String,
+
+        /// Path to the issuance verifying key
+        #[clap(short, long, parse(from_os_str), value_name = "FILE")]
+        verifying_key: PathBuf,
+
+        /// Path to the issuer's signing key, for signing root statements
+        #[clap(long, parse(from_os_str), value_name = "FILE")]
+        signing_key: PathBuf,
+
+        /// Path to the issuer's database directory. Its credentials are loaded on startup, and
+        /// every credential issued is recorded in it. It's created if it doesn't exist.
+        #[clap(long, parse(from_os_str), value_name = "DIR")]
+        db: PathBuf,
+    },
+
+    /// Outputs to STDOUT a fresh base64-encoded challenge, for a holder to make a show for. Unlike
+    /// serve-verifier, this doesn't keep track of which challenges have been used.
+    GenChallenge,
+
+    /// Outputs to STDOUT a base64-encoded show proof, which proves that the given credential
+    /// satisfies the given policy and is in the issuer's tree, without saying which credential it
+    /// is
+    Show {
+        /// Path to the proving key for the policy's circuit
+        #[clap(short, long, parse(from_os_str), value_name = "FILE")]
+        proving_key: PathBuf,
+
+        /// Path to the membership proving key made by gen-membership-crs
+        #[clap(long, parse(from_os_str), value_name = "FILE")]
+        membership_key: PathBuf,
+
+        #[clap(flatten)]
+        cred: CredArgs,
+
+        /// Path to the issuer's signed statement about the root the verifier checks against. The
+        /// credential's auth path must be under this root.
+        #[clap(long, parse(from_os_str), value_name = "FILE")]
+        root_statement: PathBuf,
+
+        /// The base64-encoded challenge given by the verifier
+        #[clap(long)]
+        challenge: String,
+
+        /// The date to apply the policy on, as YYYYMMDD. Defaults to the current date in UTC.
+        #[clap(long)]
+        today: Option<u32>,
+
+        #[clap(flatten)]
+        policy: ShowPolicy,
+    },
+
+    /// Checks a base64-encoded show proof, given via STDIN, against the given policy and the root
+    /// of the issuer's tree. Exits with exit code 0 on success, and the code corresponding to the
+    /// reason on failure (see the README).
+    VerifyShow {
+        /// Path to the verifying key for the policy's circuit
+        #[clap(short, long, parse(from_os_str), value_name = "FILE")]
+        verifying_key: PathBuf,
+
+        /// Path to the membership verifying key made by gen-membership-crs
+        #[clap(long, parse(from_os_str), value_name = "FILE")]
+        membership_key: PathBuf,
+
+        /// Path to the issuer's signed statement about its tree's root, made by publish-root
+        #[clap(long, parse(from_os_str), value_name = "FILE")]
+        root_statement: PathBuf,
+
+        /// Path to the issuer's public key
+        #[clap(long, parse(from_os_str), value_name = "FILE")]
+        issuer_key: PathBuf,
+
+        /// The base64-encoded challenge the show must be made for
+        #[clap(long)]
+        challenge: String,
+
+        #[clap(flatten)]
+        freshness: Freshness,
+
+        /// The date to apply the policy on, as YYYYMMDD. Defaults to the current date in UTC.
+        #[clap(long)]
+        today: Option<u32>,
+
+        #[clap(flatten)]
+        policy: ShowPolicy,
+    },
+
+    /// Runs an HTTP service that hands out challenges and checks shows against the given policy
+    /// and the root of the issuer's latest tree. The policy is applied on the current date in UTC
+    /// as of when each challenge is issued. See the README for the endpoints.
+    ServeVerifier {
+        /// The address to listen on
+        #[clap(long, default_value = "127.0.0.1:8081")]
+        bind: String,
+
+        /// Path to the verifying key for the policy's circuit
+        #[clap(short, long, parse(from_os_str), value_name = "FILE")]
+        verifying_key: PathBuf,
+
+        /// Path to the membership verifying key made by gen-membership-crs
+        #[clap(long, parse(from_os_str), value_name = "FILE")]
+        membership_key: PathBuf,
+
+        /// The base URL of the issuer service, e.g., http://127.0.0.1:8080
+        #[clap(long)]
+        issuer_url: String,
+
+        /// Path to the issuer's public key. The issuer's root is only used if it's signed with
+        /// this key.
+        #[clap(long, parse(from_os_str), value_name = "FILE")]
+        issuer_key: PathBuf,
+
+        /// How long a challenge is good for after it's issued, in seconds
+        #[clap(long, default_value = "300")]
+        challenge_ttl: u64,
+
+        #[clap(flatten)]
+        freshness: Freshness,
+
+        #[clap(flatten)]
+        policy: ShowPolicy,
+    },
+
+    /// Shows a credential to a verifier service, and outputs its verdict to STDOUT. Exits with
+    /// exit code 0 if the show is accepted, and the code corresponding to the reason otherwise.
+    Present {
+        /// The base URL of the verifier service
+        #[clap(long)]
+        url: String,
+
+        /// The base URL of the issuer service, which the credential's current auth path is fetched
+        /// from
+        #[clap(long)]
+        issuer_url: String,
+
+        /// Path to the proving key for the verifier's circuit
+        #[clap(short, long, parse(from_os_str), value_name = "FILE")]
+        proving_key: PathBuf,
+
+        /// Path to the membership proving key made by gen-membership-crs
+        #[clap(long, parse(from_os_str), value_name = "FILE")]
+        membership_key: PathBuf,
+
+        #[clap(flatten)]
+        cred: CredArgs,
+    },
+
+    /// Manages the holder's wallet, which keeps their credentials encrypted under a passphrase
+    Wallet {
+        #[clap(subcommand)]
+        command: WalletCommand,
+    },
+
+    /// Outputs to STDOUT the commitment and tree hash parameters, along with the labels they're
+    /// derived from. This is always JSON.
+    ExportParams,
+
+    /// Checks that the parameters in a file made by export-params are exactly the ones derived from
+    /// this build's labels. On success, outputs the hashes of the parameters to STDOUT.
+    VerifyParams {
+        /// Path to the exported parameters
+        #[clap(short, long, parse(from_os_str), value_name = "FILE")]
+        params: PathBuf,
+    },
+
+    /// Outputs to STDOUT a Solidity contract that verifies Groth16 proofs under the given
+    /// verifying key
+    #[cfg(feature = "bn254")]
+    ExportSolidityVerifier {
+        /// Path to the verifying key
+        #[clap(long, parse(from_os_str), value_name = "FILE")]
+        vk: PathBuf,
+    },
+}
+
+#[derive(Subcommand)]
+enum WalletCommand {
+    /// Makes a new, empty wallet
+    Init {
+        #[clap(flatten)]
+        wallet: WalletArgs,
+    },
+
+    /// Adds a credential to the wallet
+    ImportCred {
+        #[clap(flatten)]
+        wallet: WalletArgs,
+
+        /// Path to the credential opening saved by issue-req --info-out
         #[clap(short, long, parse(from_os_str), value_name = "FILE")]
-        tree: PathBuf,
+        info: PathBuf,
+
+        /// The credential's leaf index in the issuer's tree
+        #[clap(long)]
+        leaf_index: u64,
+
+        /// Path to the credential's auth path, as given by the issuer service
+        #[clap(long, parse(from_os_str), value_name = "FILE")]
+        auth_path: Option<PathBuf>,
+
+        /// The name to give the credential. Defaults to `leaf-<leaf index>`.
+        #[clap(long)]
+        name: Option<String>,
     },
+
+    /// Lists the credentials in the wallet
+    List {
+        #[clap(flatten)]
+        wallet: WalletArgs,
+    },
+
+    /// Sets the leaf index or auth path of a credential in the wallet, e.g., once the issuer grants
+    /// a credential saved by issue-req --wallet-cred
+    Update {
+        #[clap(flatten)]
+        wallet: WalletArgs,
+
+        /// The name of the credential to update. Can be omitted if the wallet has only one.
+        #[clap(long)]
+        name: Option<String>,
+
+        /// The credential's leaf index in the issuer's tree
+        #[clap(long)]
+        leaf_index: Option<u64>,
+
+        /// Path to the credential's auth path, as given by the issuer service
+        #[clap(long, parse(from_os_str), value_name = "FILE")]
+        auth_path: Option<PathBuf>,
+    },
+
+    /// Outputs to STDOUT the opening of a credential in the wallet, in the format of
+    /// issue-req --info-out
+    Export {
+        #[clap(flatten)]
+        wallet: WalletArgs,
+
+        /// The name of the credential to export. Can be omitted if the wallet has only one.
+        #[clap(long)]
+        name: Option<String>,
+    },
+}
+
+/// Where to find the holder's wallet
+#[derive(Args)]
+struct WalletArgs {
+    /// Path to the wallet
+    #[clap(
+        long,
+        parse(from_os_str),
+        value_name = "FILE",
+        default_value = "wallet.zkpp"
+    )]
+    wallet: PathBuf,
+
+    /// Path to a file whose first line is the wallet's passphrase
+    #[clap(long, parse(from_os_str), value_name = "FILE")]
+    passphrase_file: Option<PathBuf>,
+}
+
+impl WalletArgs {
+    fn passphrase(&self) -> Result<String, Error> {
+        let path = self.passphrase_file.as_ref().ok_or_else(|| {
+            Error::Parse("--passphrase-file is needed to open the wallet".to_string())
+        })?;
+        read_passphrase(path)
+    }
+
+    fn open(&self) -> Result<Wallet, Error> {
+        Wallet::open(&self.wallet, &self.passphrase()?)
+    }
 }
 
-fn deser_from_base64<R: Read, T: CanonicalDeserialize>(r: &mut R) -> Result<T, SerializationError> {
-    let b64_reader = base64::read::DecoderReader::new(r, base64::STANDARD);
-    T::deserialize_unchecked(b64_reader)
+/// The credential to show. By default, it comes from the wallet.
+#[derive(Args)]
+struct CredArgs {
+    /// Path to the credential opening saved by issue-req --info-out, to use instead of the wallet
+    #[clap(
+        short,
+        long,
+        parse(from_os_str),
+        value_name = "FILE",
+        requires = "leaf-index"
+    )]
+    info: Option<PathBuf>,
+
+    /// The credential's leaf index in the issuer's tree, if --info is given
+    #[clap(long, requires = "info")]
+    leaf_index: Option<u64>,
+
+    /// Path to the credential's auth path, fetched from the issuer service. Overrides the one in
+    /// the wallet, if any.
+    #[clap(long, parse(from_os_str), value_name = "FILE")]
+    auth_path: Option<PathBuf>,
+
+    /// The name of the credential in the wallet. Can be omitted if the wallet has only one.
+    #[clap(long, conflicts_with = "info")]
+    cred: Option<String>,
+
+    #[clap(flatten)]
+    wallet: WalletArgs,
 }
 
-fn ser_to_base64<W: Write, T: CanonicalSerialize>(
-    val: T,
-    w: &mut W,
-) -> Result<(), SerializationError> {
-    let b64_writer = base64::write::EncoderWriter::new(w, base64::STANDARD);
-    val.serialize_uncompressed(b64_writer)
+impl CredArgs {
+    /// Returns the credential. Its auth path is the one given by --auth-path, if any.
+    fn load(&self) -> Result<WalletCred, Error> {
+        let auth_path = self
+            .auth_path
+            .as_ref()
+            .map(|path| Frame::open(path, ObjectType::AuthPath)?.deser_untrusted())
+            .transpose()?;
+        match (&self.info, self.leaf_index) {
+            (Some(info), Some(leaf_index)) => Ok(WalletCred::new(
+                "",
+                deser_from_file(info, ObjectType::PersonalInfo)?,
+                Some(leaf_index),
+                auth_path,
+            )),
+            _ => {
+                let wallet = self.wallet.open()?;
+                let mut cred = wallet.get(self.cred.as_deref())?.clone();
+                cred.auth_path = auth_path.or(cred.auth_path);
+                Ok(cred)
+            }
+        }
+    }
+
+    /// Saves the credential's membership proofs, if it came from the wallet, so later shows
+    /// against the same root can reuse them
+    fn save_membership_proofs<R: Rng + CryptoRng>(
+        &self,
+        rng: &mut R,
+        cred: &WalletCred,
+    ) -> Result<(), Error> {
+        if self.info.is_some() {
+            return Ok(());
+        }
+
+        let passphrase = self.wallet.passphrase()?;
+        let mut wallet = Wallet::open(&self.wallet.wallet, &passphrase)?;
+        wallet.get_mut(self.cred.as_deref())?.membership_proofs = cred.membership_proofs.clone();
+        wallet.save(rng, &self.wallet.wallet, &passphrase)
+    }
 }
 
 fn main() {
-    let mut rng = rand::thread_rng();
     let cli = Cli::parse();
 
+    // Errors are reported as a single logfmt line on STDERR, and the exit code tells the caller
+    // what kind of error it was
+    if let Err(e) = run(cli) {
+        eprintln!("{}", e.to_logfmt());
+        std::process::exit(e.exit_code());
+    }
+}
+
+fn run(cli: Cli) -> Result<(), Error> {
+    let mut rng = rand::thread_rng();
+    let format = cli.format;
+    let mut policy = IssuancePolicy::default();
+    if let Some(path) = cli.trust_anchor {
+        let pem =
+            std::fs::read_to_string(path).map_err(Error::io("couldn't read trust anchor file"))?;
+        policy.trust_anchor = IssuerPubkey::from_pem(&pem)?;
+    }
+    if let Some(key) = cli.nullifier_key {
+        policy.nullifier_key = IssuancePolicy::parse_nullifier_key(&key)?;
+    }
+
     match cli.command {
         Command::GenCrs {
+            circuit,
             proving_key,
             verifying_key,
+            key_format,
         } => {
-            // Generate the CRS
-            let (pk, vk) = gen_issuance_crs(&mut rng);
-
-            // Write the CRS
-            let mut pk_file = File::create(proving_key).expect("couldn't create proving key file");
-            let mut vk_file =
-                File::create(verifying_key).expect("couldn't create verifying key file");
-            ser_to_base64(pk, &mut pk_file).expect("couldn't serialize proving key");
-            ser_to_base64(vk, &mut vk_file).expect("couldn't serialize verifying key");
+            // Generate and write the CRS
+            let (pk, vk) = gen_circuit_crs(&mut rng, circuit)?;
+            write_crs(format, key_format, proving_key, verifying_key, &pk, &vk)?;
+        }
+        Command::GenMembershipCrs {
+            proving_key,
+            verifying_key,
+            key_format,
+        } => {
+            let (pk, vk) = gen_membership_crs(&mut rng)?;
+            let vk_fingerprint = fingerprint(&vk)?;
+            let mut pk_file =
+                File::create(proving_key).map_err(Error::io("couldn't create proving key file"))?;
+            let mut vk_file = File::create(verifying_key)
+                .map_err(Error::io("couldn't create verifying key file"))?;
+            Envelope::new(ObjectType::MembershipProvingKey)
+                .key_fingerprint(vk_fingerprint.clone())
+                .payload_as(&pk, key_format)?
+                .write(format, &mut pk_file)?;
+            Envelope::new(ObjectType::MembershipVerifyingKey)
+                .key_fingerprint(vk_fingerprint)
+                .payload_as(&vk, key_format)?
+                .write(format, &mut vk_file)?;
         }
-
         Command::IssueReq {
             proving_key,
             dump_file,
+            info_out,
+            wallet_cred,
+            wallet: wallet_args,
         } => {
-            // Deserialize the request and verification key
-            let mut pk_file = File::open(proving_key).expect("couldn't open proving key file");
-            let mut dump_file = File::open(dump_file).expect("couldn't open passport dump file");
+            // Deserialize the request and proving key. The proving key is memory-mapped if it's
+            // stored in binary
+            let mut dump_file =
+                File::open(dump_file).map_err(Error::io("couldn't open passport dump file"))?;
             let dump: PassportDump = serde_json::from_reader(&mut dump_file)
-                .expect("passport dump deserialization failed");
-            let pk = deser_from_base64::<_, PredProvingKey>(&mut pk_file)
-                .expect("couldn't deserialize proving key");
+                .map_err(Error::deser("couldn't deserialize passport dump"))?;
+            let pk: PredProvingKey = deser_from_file(&proving_key, ObjectType::ProvingKey)?;
+
+            // Open the wallet before proving, so a wrong passphrase doesn't waste the proof
+            let wallet = match &wallet_cred {
+                Some(_) => {
+                    let passphrase = wallet_args.passphrase()?;
+                    Some((Wallet::open(&wallet_args.wallet, &passphrase)?, passphrase))
+                }
+                None => None,
+            };
 
-            let (_, req) = user_req_issuance(&mut rng, &dump, &pk);
-            ser_to_base64(req, &mut io::stdout()).expect("couldn't serialize issuance req");
+            let (info, req) = user_req_issuance(&mut rng, &dump, &pk, &policy)?;
+            if let Some(info_out) = info_out {
+                let mut info_file = create_secret_file(&info_out)
+                    .map_err(Error::io("couldn't create info file"))?;
+                Envelope::new(ObjectType::PersonalInfo)
+                    .payload(&info)?
+                    .write(format, &mut info_file)?;
+            }
+            if let (Some(name), Some((mut wallet, passphrase))) = (wallet_cred, wallet) {
+                wallet.insert(WalletCred::new(&name, info, None, None))?;
+                wallet.save(&mut rng, &wallet_args.wallet, &passphrase)?;
+            }
+            Envelope::new(ObjectType::IssuanceRequest)
+                .key_fingerprint(fingerprint(&pk.prepare_verifying_key())?)
+                .payload(&req)?
+                .write(format, &mut io::stdout())?;
             println!()
         }
 
-        Command::IssueGrant { verifying_key } => {
-            // Deserialize the request and verification key
-            let mut vk_file = File::open(verifying_key).expect("couldn't open verifying key file");
-            let req = deser_from_base64::<_, IssuanceReq>(&mut io::stdin())
-                .expect("request deserialization failed");
-            let vk = deser_from_base64::<_, PredVerifyingKey>(&mut vk_file)
-                .expect("couldn't deserialize verifying key");
+        Command::IssueGrant {
+            verifying_key,
+            batch: None,
+            db,
+        } => {
+            // Deserialize the verification key
+            let vk: PredVerifyingKey = deser_from_file(&verifying_key, ObjectType::VerifyingKey)?;
+            let vk_fingerprint = fingerprint(&vk)?;
+            let db = open_db(db)?;
 
-            // Check issuance
-            assert!(check_issuance(&vk, &req), "Issuance verification failed");
+            // Deserialize and check the request. Any failure here is a rejection of the request
+            let res = read_issuance_req(&mut io::stdin(), &vk_fingerprint).and_then(|req| {
+                let nullifier = req.nullifier_bytes()?;
+                if let Some(db) = &db {
+                    db.check_nullifier(&nullifier)?;
+                }
+                check_issuance(&vk, &req, &policy)?;
+                let leaf_index = db
+                    .as_ref()
+                    .map(|db| db.grant(&req.attrs_com, &nullifier))
+                    .transpose()?;
+                Ok((req, leaf_index))
+            });
 
-            // Now output just the credential
-            ser_to_base64(req.attrs_com, &mut io::stdout()).expect("couldn't serialize cred");
-            println!()
+            // Output the verdict. On success, this is just the credential
+            if res.is_ok() || format == OutputFormat::Json {
+                let res = res.as_ref().map(|(req, leaf_index)| (req, *leaf_index));
+                grant_envelope(res, vk_fingerprint)?.write(format, &mut io::stdout())?;
+                println!()
+            }
+
+            // Exit with the appropriate code on failure
+            res?;
         }
-        Command::GenTree { creds } => {
-            let mut tree = ComTree::empty(MERKLE_CRH_PARAM.clone(), TREE_HEIGHT);
+        Command::IssueGrant {
+            verifying_key,
+            batch: Some(batch),
+            db,
+        } => {
+            // Deserialize the verification key
+            let vk: PredVerifyingKey = deser_from_file(&verifying_key, ObjectType::VerifyingKey)?;
+            let vk_fingerprint = fingerprint(&vk)?;
+
+            // Without a database, a temporary one still keeps two requests in the batch for the
+            // same passport from both being granted. Its leaf indices mean nothing, though.
+            let persistent = db.is_some();
+            let db = match open_db(db)? {
+                Some(db) => db,
+                None => IssuerDb::temporary()?,
+            };
+
+            // Deserialize every request. A request that doesn't deserialize is rejected by itself
+            let batch_file = File::open(batch).map_err(Error::io("couldn't open batch file"))?;
+            let mut reqs = Vec::new();
+            let mut deser_errs = Vec::new();
+            for line in io::BufReader::new(batch_file).lines() {
+                let line = line.map_err(Error::io("couldn't read line"))?;
+                match read_issuance_req(&mut line.as_bytes(), &vk_fingerprint) {
+                    Ok(req) => {
+                        reqs.push(req);
+                        deser_errs.push(None);
+                    }
+                    Err(e) => deser_errs.push(Some(e)),
+                }
+            }
+
+            // Check the rest together, and line the results back up with the file
+            let mut checked = check_issuance_batch(&mut rng, &vk, &reqs, &policy)?
+                .into_iter()
+                .zip(&reqs);
+            for (i, deser_err) in deser_errs.into_iter().enumerate() {
+                // Requests that pass are granted in order, so if two are for the same passport, the
+                // first one gets the credential
+                let res = match deser_err {
+                    Some(e) => Err(e),
+                    None => {
+                        let (res, req) = checked.next().unwrap();
+                        res.and_then(|_| db.grant(&req.attrs_com, &req.nullifier_bytes()?))
+                            .map(|leaf_index| (req, persistent.then_some(leaf_index)))
+                    }
+                };
 
-            // Go through each line in the creds file and add it to the tree
-            let creds_file = File::open(creds).expect("couldn't open creds file");
+                match (&res, format) {
+                    (Err(e), OutputFormat::Human) => eprintln!("{} line={}", e.to_logfmt(), i + 1),
+                    _ => {
+                        grant_envelope(res.as_ref().copied(), vk_fingerprint.clone())?
+                            .write(format, &mut io::stdout())?;
+                        println!()
+                    }
+                }
+            }
+        }
+        Command::GenTree { creds: None, db } => {
+            let tree = IssuerDb::open(&db.unwrap())?.tree()?;
+            let root = tree.root();
+            Envelope::new(ObjectType::Tree)
+                .root(&root)?
+                .payload(&tree.into_wire_format())?
+                .write(format, &mut io::stdout())?;
+        }
+        Command::GenTree {
+            creds: Some(creds), ..
+        } => {
+            // Go through each line in the creds file and deserialize it
+            let creds_file = File::open(creds).map_err(Error::io("couldn't open creds file"))?;
             let line_reader = io::BufReader::new(creds_file);
+            let mut cred_list = Vec::new();
             for (i, line) in line_reader.lines().enumerate() {
-                let line = line.expect("couldn't read line");
+                let line = line.map_err(Error::io("couldn't read line"))?;
                 let mut line_bytes = line.as_bytes();
                 let cred: Com<PassportComScheme> =
-                    deser_from_base64(&mut line_bytes).expect("couldn't deserialize cred");
-
-                // Insert the cred into the tree
-                tree.insert(i as u64, &cred);
+                    deser_untrusted_from_reader(&mut line_bytes, ObjectType::Credential).map_err(
+                        |e| Error::Deserialization(format!("cred on line {}: {}", i + 1, e)),
+                    )?;
+                cred_list.push(cred);
             }
 
-            // Now serialize the tree
-            ser_to_base64(tree.into_wire_format(), &mut io::stdout())
-                .expect("couldn't serialize tree");
+            // Insert the creds into the tree and serialize it
+            let tree = tree_from_creds(&cred_list);
+            let root = tree.root();
+            Envelope::new(ObjectType::Tree)
+                .root(&root)?
+                .payload(&tree.into_wire_format())?
+                .write(format, &mut io::stdout())?;
         }
-        Command::GetRoot { tree } => {
-            // Deserialize the request and verification key
-            let mut tree_file = File::open(tree).expect("couldn't open tree file");
-            let raw_tree = deser_from_base64::<_, ComTreeWireFormat>(&mut tree_file)
-                .expect("couldn't deserialize tree");
-            // Add the CRH params to make it a fully fledged ComTree
-            let tree = raw_tree.into_com_tree(MERKLE_CRH_PARAM.clone());
+        Command::GetRoot { tree, db } => {
+            let tree = match (tree, db) {
+                (Some(tree), _) => {
+                    // Deserialize the tree, and add the CRH params to make it a fully fledged
+                    // ComTree
+                    let raw_tree: ComTreeWireFormat = deser_from_file(&tree, ObjectType::Tree)?;
+                    tree_from_wire_format(raw_tree)
+                }
+                (None, db) => IssuerDb::open(&db.unwrap())?.tree()?,
+            };
             // Now output the root
-            ser_to_base64(tree.root(), &mut io::stdout()).expect("couldn't serialize root");
+            let root = tree.root();
+            Envelope::new(ObjectType::Root)
+                .root(&root)?
+                .payload(&root)?
+                .write(format, &mut io::stdout())?;
+        }
+        Command::IssuerStatus { db } => {
+            let status = IssuerDb::open(&db)?.status()?;
+            match format {
+                OutputFormat::Human => print!("{}", status),
+                OutputFormat::Json => {
+                    serde_json::to_writer(io::stdout(), &status)
+                        .map_err(|e| Error::Io("couldn't write output".to_string(), e.into()))?;
+                    println!()
+                }
+            }
+        }
+        Command::RevokeCred {
+            db,
+            issuer_url,
+            signing_key,
+            leaf_index,
+        } => match issuer_url {
+            None => IssuerDb::open(&db.unwrap())?.revoke(leaf_index)?,
+            Some(issuer_url) => {
+                let key: IssuerSigningKey =
+                    deser_from_file(&signing_key.unwrap(), ObjectType::IssuerSigningKey)?;
+                let statement = revoke_remote(&issuer_url, &key, leaf_index)?;
+                Envelope::new(ObjectType::RootStatement)
+                    .key_fingerprint(statement.statement.key_fingerprint())
+                    .root(&statement.statement.root)?
+                    .payload(&statement)?
+                    .write(format, &mut io::stdout())?;
+                println!()
+            }
+        },
+        Command::GenIssuerKey {
+            signing_key,
+            public_key,
+        } => {
+            let key = IssuerSigningKey::generate(&mut rng);
+            let mut signing_key_file = create_secret_file(&signing_key)
+                .map_err(Error::io("couldn't create signing key file"))?;
+            let mut public_key_file =
+                File::create(public_key).map_err(Error::io("couldn't create public key file"))?;
+            Envelope::new(ObjectType::IssuerSigningKey)
+                .payload(&key)?
+                .write(format, &mut signing_key_file)?;
+            Envelope::new(ObjectType::IssuerPublicKey)
+                .key_fingerprint(fingerprint(&key.public_key())?)
+                .payload(&key.public_key())?
+                .write(format, &mut public_key_file)?;
+        }
+        Command::PublishRoot {
+            signing_key,
+            verifying_key,
+            db,
+            tree,
+            epoch,
+        } => {
+            let key: IssuerSigningKey =
+                deser_from_file(&signing_key, ObjectType::IssuerSigningKey)?;
+            let vk: PredVerifyingKey = deser_from_file(&verifying_key, ObjectType::VerifyingKey)?;
+            let vk_fingerprint = fingerprint(&vk)?;
+
+            let (root, epoch) = match (tree, epoch) {
+                (Some(tree), Some(epoch)) => {
+                    let raw_tree: ComTreeWireFormat = deser_from_file(&tree, ObjectType::Tree)?;
+                    (tree_from_wire_format(raw_tree).root(), epoch)
+                }
+                _ => {
+                    let db = IssuerDb::open(&db.unwrap())?;
+                    (db.tree()?.root(), db.next_epoch()?)
+                }
+            };
+            let statement = RootStatement::new(root, epoch, &vk_fingerprint)?.sign(&key)?;
+            Envelope::new(ObjectType::RootStatement)
+                .key_fingerprint(vk_fingerprint)
+                .root(&statement.statement.root)?
+                .payload(&statement)?
+                .write(format, &mut io::stdout())?;
+            println!()
+        }
+        Command::InspectDump { dump_file } => {
+            let mut dump_file =
+                File::open(dump_file).map_err(Error::io("couldn't open passport dump file"))?;
+            let dump: PassportDump = serde_json::from_reader(&mut dump_file)
+                .map_err(Error::deser("couldn't deserialize passport dump"))?;
+            let report = DumpReport::new(&dump, &policy);
+
+            match format {
+                OutputFormat::Human => print!("{:#?}\n{}", dump, report),
+                OutputFormat::Json => {
+                    serde_json::to_writer(io::stdout(), &report)
+                        .map_err(|e| Error::Io("couldn't write output".to_string(), e.into()))?;
+                    println!()
+                }
+            }
+        }
+        Command::GenTestPassport {
+            dump_file,
+            trust_anchor_out,
+            name,
+            issuer,
+            dob,
+            expiry,
+        } => {
+            let params = TestPassportParams {
+                issuer: issuer.clone(),
+                nationality: issuer,
+                name,
+                dob,
+                expiry,
+                ..Default::default()
+            };
+            let passport = gen_test_passport(&mut rng, &params)?;
+
+            let dump_file =
+                File::create(dump_file).map_err(Error::io("couldn't create passport dump file"))?;
+            serde_json::to_writer_pretty(dump_file, &passport.dump)
+                .map_err(|e| Error::Io("couldn't write passport dump".to_string(), e.into()))?;
+            std::fs::write(trust_anchor_out, passport.trust_anchor.to_pem())
+                .map_err(Error::io("couldn't write trust anchor file"))?;
+        }
+        Command::CircuitStats { circuit, depth } => {
+            let stats = circuit_stats(circuit, depth)?;
+
+            match format {
+                OutputFormat::Human => print!("{}", stats),
+                OutputFormat::Json => {
+                    serde_json::to_writer(io::stdout(), &stats)
+                        .map_err(|e| Error::Io("couldn't write output".to_string(), e.into()))?;
+                    println!()
+                }
+            }
+        }
+        Command::TauInit {
+            log_size,
+            transcript,
+        } => {
+            let init = tau_init(log_size)?;
+            write_tau_transcript(format, transcript, &init)?;
+        }
+        Command::TauContribute { transcript } => {
+            let mut powers = read_tau_transcript(&transcript)?;
+            let contribution_hash = tau_contribute(&mut rng, &mut powers)?;
+            write_tau_transcript(format, transcript, &powers)?;
+            println!("{}", contribution_hash);
+        }
+        Command::CrsInit {
+            circuit,
+            powers_of_tau,
+            transcript,
+        } => {
+            let powers = read_tau_transcript(&powers_of_tau)?;
+            let init = crs_init(&mut rng, &powers, circuit)?;
+            write_transcript(format, transcript, &init)?;
+        }
+        Command::CrsContribute { transcript } => {
+            let mut transcript_file =
+                File::open(&transcript).map_err(Error::io("couldn't open transcript file"))?;
+            let mut ceremony: CrsTranscript =
+                deser_untrusted_from_reader(&mut transcript_file, ObjectType::CrsTranscript)?;
+            let contribution_hash = crs_contribute(&mut rng, &mut ceremony)?;
+            write_transcript(format, transcript, &ceremony)?;
+            println!("{}", contribution_hash);
+        }
+        Command::CrsVerifyTranscript {
+            circuit,
+            powers_of_tau,
+            transcript,
+            proving_key,
+            verifying_key,
+            key_format,
+        } => {
+            let powers = read_tau_transcript(&powers_of_tau)?;
+            let mut transcript_file =
+                File::open(&transcript).map_err(Error::io("couldn't open transcript file"))?;
+            let ceremony: CrsTranscript =
+                deser_untrusted_from_reader(&mut transcript_file, ObjectType::CrsTranscript)?;
+            let (pk, vk) = verify_transcript(&mut rng, &ceremony, &powers, circuit)?;
+            write_crs(format, key_format, proving_key, verifying_key, &pk, &vk)?;
+        }
+        Command::Convert { input, output, to } => {
+            // Read the object back in, whatever it is, and write it out in the new format. Objects
+            // from other parties get the untrusted treatment, same as everywhere else
+            let frame = Frame::open_any(&input)?;
+            let mut envelope = Envelope::new(frame.ty);
+            if let Some(fingerprint) = frame.key_fingerprint.clone() {
+                envelope = envelope.key_fingerprint(fingerprint);
+            }
+            let envelope = match frame.ty {
+                ObjectType::ProvingKey => {
+                    envelope.payload_as(&frame.deser::<PredProvingKey>()?, to)
+                }
+                ObjectType::VerifyingKey => {
+                    envelope.payload_as(&frame.deser::<PredVerifyingKey>()?, to)
+                }
+                ObjectType::IssuanceRequest => {
+                    envelope.payload_as(&frame.deser_untrusted::<IssuanceReq>()?, to)
+                }
+                ObjectType::Credential => {
+                    envelope.payload_as(&frame.deser_untrusted::<Com<PassportComScheme>>()?, to)
+                }
+                ObjectType::Tree => {
+                    let tree = tree_from_wire_format(frame.deser::<ComTreeWireFormat>()?);
+                    envelope
+                        .root(&tree.root())?
+                        .payload_as(&tree.into_wire_format(), to)
+                }
+                ObjectType::Root => {
+                    let root: <H as TwoToOneCRH>::Output = frame.deser()?;
+                    envelope.root(&root)?.payload_as(&root, to)
+                }
+                ObjectType::CrsTranscript => {
+                    envelope.payload_as(&frame.deser_untrusted::<CrsTranscript>()?, to)
+                }
+                ObjectType::TauTranscript => {
+                    envelope.payload_as(&frame.deser_untrusted::<TauTranscript>()?, to)
+                }
+                ObjectType::MembershipProvingKey => {
+                    envelope.payload_as(&frame.deser::<MembershipProvingKey>()?, to)
+                }
+                ObjectType::MembershipVerifyingKey => {
+                    envelope.payload_as(&frame.deser::<MembershipVerifyingKey>()?, to)
+                }
+                ObjectType::AuthPath => {
+                    envelope.payload_as(&frame.deser_untrusted::<ComTreePath>()?, to)
+                }
+                ObjectType::ShowProof => {
+                    envelope.payload_as(&frame.deser_untrusted::<ShowProof>()?, to)
+                }
+                ObjectType::PersonalInfo => {
+                    envelope.payload_as(&frame.deser::<PersonalInfo>()?, to)
+                }
+                ObjectType::IssuanceVerdict => {
+                    return Err(Error::Parse(
+                        "issuance verdicts have no payload to convert".to_string(),
+                    ))
+                }
+                ObjectType::IssuerSigningKey => {
+                    envelope.payload_as(&frame.deser::<IssuerSigningKey>()?, to)
+                }
+                ObjectType::IssuerPublicKey => {
+                    envelope.payload_as(&frame.deser_untrusted::<IssuerPublicKey>()?, to)
+                }
+                ObjectType::RootStatement => {
+                    let statement: SignedRootStatement = frame.deser_untrusted()?;
+                    envelope
+                        .root(&statement.statement.root)?
+                        .payload_as(&statement, to)
+                }
+                ObjectType::Revocation => {
+                    envelope.payload_as(&frame.deser_untrusted::<SignedRevocation>()?, to)
+                }
+                ObjectType::Wallet => {
+                    return Err(Error::Parse("wallets can't be converted".to_string()))
+                }
+            }?;
+
+            let mut out_file =
+                File::create(output).map_err(Error::io("couldn't create output file"))?;
+            envelope.write(format, &mut out_file)?;
+        }
+        Command::ServeIssuer {
+            bind,
+            verifying_key,
+            signing_key,
+            db,
+        } => {
+            let vk: PredVerifyingKey = deser_from_file(&verifying_key, ObjectType::VerifyingKey)?;
+            let signing_key: IssuerSigningKey =
+                deser_from_file(&signing_key, ObjectType::IssuerSigningKey)?;
+            IssuerService::new(vk, signing_key, policy, IssuerDb::open(&db)?)?.serve(&bind)?;
+        }
+        Command::GenChallenge => println!("{}", encode_challenge(&Fr::rand(&mut rng))?),
+        Command::Show {
+            proving_key,
+            membership_key,
+            cred,
+            root_statement,
+            challenge,
+            today,
+            mut policy,
+        } => {
+            policy.today = today;
+            let pk: PredProvingKey = deser_from_file(&proving_key, ObjectType::ProvingKey)?;
+            let membership_pk: MembershipProvingKey =
+                deser_from_file(&membership_key, ObjectType::MembershipProvingKey)?;
+            let mut held = cred.load()?;
+            let auth_path = held.auth_path.clone().ok_or_else(|| {
+                Error::Parse(
+                    "the credential has no auth path; fetch one from the issuer service and pass \
+                     it with --auth-path"
+                        .to_string(),
+                )
+            })?;
+            let statement: SignedRootStatement =
+                Frame::open(&root_statement, ObjectType::RootStatement)?.deser_untrusted()?;
+            let challenge = decode_challenge(&challenge)?;
+
+            let cached_root = held.membership_proofs.as_ref().map(|proofs| proofs.root);
+            let membership = cached_membership_proofs(
+                &mut rng,
+                &membership_pk,
+                &held.info,
+                &auth_path,
+                &statement.statement.root,
+                &mut held.membership_proofs,
+            )?;
+            let show = prove_show_with_membership(
+                &mut rng,
+                &pk,
+                &membership_pk,
+                &held.info,
+                &auth_path,
+                membership,
+                &policy,
+                challenge,
+            )?;
+            if cached_root != Some(show.root) {
+                cred.save_membership_proofs(&mut rng, &held)?;
+            }
+            Envelope::new(ObjectType::ShowProof)
+                .key_fingerprint(fingerprint(&pk.prepare_verifying_key())?)
+                .root(&show.root)?
+                .payload(&show)?
+                .write(format, &mut io::stdout())?;
+            println!()
+        }
+        Command::VerifyShow {
+            verifying_key,
+            membership_key,
+            root_statement,
+            issuer_key,
+            challenge,
+            freshness,
+            today,
+            mut policy,
+        } => {
+            policy.today = today;
+            let vk: PredVerifyingKey = deser_from_file(&verifying_key, ObjectType::VerifyingKey)?;
+            let membership_vk: MembershipVerifyingKey =
+                deser_from_file(&membership_key, ObjectType::MembershipVerifyingKey)?;
+            let challenge = decode_challenge(&challenge)?;
+
+            // Only use the root if the issuer vouches for it, and did so recently enough
+            let issuer_key: IssuerPublicKey =
+                deser_from_file(&issuer_key, ObjectType::IssuerPublicKey)?;
+            let statement: SignedRootStatement =
+                Frame::open(&root_statement, ObjectType::RootStatement)?.deser_untrusted()?;
+            let root = statement.verify_fresh(&issuer_key, &freshness)?.root;
+
+            // Shows come from the holder, so they get the untrusted treatment
+            let frame = Frame::read(&mut io::stdin(), ObjectType::ShowProof)?;
+            frame.check_key(&fingerprint(&vk)?)?;
+            let show: ShowProof = frame.deser_untrusted()?;
+            verify_show(&vk, &membership_vk, &show, &policy, &root, challenge)?;
+        }
+        Command::ServeVerifier {
+            bind,
+            verifying_key,
+            membership_key,
+            issuer_url,
+            issuer_key,
+            challenge_ttl,
+            freshness,
+            policy,
+        } => {
+            let vk: PredVerifyingKey = deser_from_file(&verifying_key, ObjectType::VerifyingKey)?;
+            let membership_vk: MembershipVerifyingKey =
+                deser_from_file(&membership_key, ObjectType::MembershipVerifyingKey)?;
+            let issuer_key: IssuerPublicKey =
+                deser_from_file(&issuer_key, ObjectType::IssuerPublicKey)?;
+            let challenge_ttl = Duration::from_secs(challenge_ttl);
+            VerifierService::new(
+                vk,
+                membership_vk,
+                policy,
+                &issuer_url,
+                issuer_key,
+                freshness,
+                challenge_ttl,
+            )?
+            .serve(&bind)?;
+        }
+        Command::Present {
+            url,
+            issuer_url,
+            proving_key,
+            membership_key,
+            cred,
+        } => {
+            let pk: PredProvingKey = deser_from_file(&proving_key, ObjectType::ProvingKey)?;
+            let membership_pk: MembershipProvingKey =
+                deser_from_file(&membership_key, ObjectType::MembershipProvingKey)?;
+            let mut held = cred.load()?;
+            let leaf_index = held.leaf_index.ok_or_else(|| {
+                Error::Parse(format!(
+                    "the credential {:?} has no leaf index yet; set it with wallet update once \
+                     the issuer grants it",
+                    held.name()
+                ))
+            })?;
+            let cached_root = held.membership_proofs.as_ref().map(|proofs| proofs.root);
+            let verdict = present(
+                &mut rng,
+                &url,
+                &issuer_url,
+                &pk,
+                &membership_pk,
+                &held.info,
+                leaf_index,
+                &mut held.membership_proofs,
+            )?;
+            if held.membership_proofs.as_ref().map(|proofs| proofs.root) != cached_root {
+                cred.save_membership_proofs(&mut rng, &held)?;
+            }
+
+            match (format, &verdict.verdict) {
+                (OutputFormat::Json, _) => {
+                    verdict.write(format, &mut io::stdout())?;
+                    println!()
+                }
+                (OutputFormat::Human, Some(Verdict::Accepted)) => println!("accepted"),
+                _ => {}
+            }
+
+            // Exit with the appropriate code if the show was rejected
+            if let Some(Verdict::Rejected { kind, reason }) = verdict.verdict {
+                return Err(Error::ProofFailure(format!(
+                    "verifier rejected the show ({}): {}",
+                    kind, reason
+                )));
+            }
+        }
+        Command::Wallet { command } => run_wallet(&mut rng, format, command)?,
+        Command::ExportParams => {
+            let exported = export_params(&PASSPORT_COM_PARAM, &MERKLE_CRH_PARAM)?;
+            let res = match format {
+                OutputFormat::Human => serde_json::to_writer_pretty(io::stdout(), &exported),
+                OutputFormat::Json => serde_json::to_writer(io::stdout(), &exported),
+            };
+            res.map_err(|e| Error::Io("couldn't write output".to_string(), e.into()))?;
+            println!()
+        }
+        Command::VerifyParams { params } => {
+            let params_file =
+                File::open(params).map_err(Error::io("couldn't open parameters file"))?;
+            let exported: ExportedParams = serde_json::from_reader(params_file)
+                .map_err(Error::deser("couldn't deserialize parameters"))?;
+            verify_params(&exported)?;
+
+            match format {
+                OutputFormat::Human => {
+                    println!("commitment: {}", exported.commitment.sha256);
+                    println!("tree hash: {}", exported.tree_hash.sha256);
+                }
+                OutputFormat::Json => println!(
+                    "{}",
+                    serde_json::json!({
+                        "commitment_sha256": exported.commitment.sha256,
+                        "tree_hash_sha256": exported.tree_hash.sha256,
+                    })
+                ),
+            }
+        }
+        #[cfg(feature = "bn254")]
+        Command::ExportSolidityVerifier { vk } => {
+            let vk: PredVerifyingKey = deser_from_file(&vk, ObjectType::VerifyingKey)?;
+            print!("{}", solidity_verifier(&vk)?);
         }
     }
+
+    Ok(())
+}
+
+/// Runs a wallet subcommand
+fn run_wallet<R: Rng + CryptoRng>(
+    rng: &mut R,
+    format: OutputFormat,
+    command: WalletCommand,
+) -> Result<(), Error> {
+    match command {
+        WalletCommand::Init { wallet } => Wallet::init(rng, &wallet.wallet, &wallet.passphrase()?),
+        WalletCommand::ImportCred {
+            wallet: wallet_args,
+            info,
+            leaf_index,
+            auth_path,
+            name,
+        } => {
+            let info: PersonalInfo = deser_from_file(&info, ObjectType::PersonalInfo)?;
+            let auth_path: Option<ComTreePath> = auth_path
+                .map(|path| Frame::open(&path, ObjectType::AuthPath)?.deser_untrusted())
+                .transpose()?;
+            let name = name.unwrap_or_else(|| format!("leaf-{}", leaf_index));
+
+            let passphrase = wallet_args.passphrase()?;
+            let mut wallet = Wallet::open(&wallet_args.wallet, &passphrase)?;
+            wallet.insert(WalletCred::new(&name, info, Some(leaf_index), auth_path))?;
+            wallet.save(rng, &wallet_args.wallet, &passphrase)
+        }
+        WalletCommand::Update {
+            wallet: wallet_args,
+            name,
+            leaf_index,
+            auth_path,
+        } => {
+            let auth_path: Option<ComTreePath> = auth_path
+                .map(|path| Frame::open(&path, ObjectType::AuthPath)?.deser_untrusted())
+                .transpose()?;
+
+            let passphrase = wallet_args.passphrase()?;
+            let mut wallet = Wallet::open(&wallet_args.wallet, &passphrase)?;
+            let cred = wallet.get_mut(name.as_deref())?;
+            if let Some(leaf_index) = leaf_index {
+                // Proofs for another leaf are no use
+                if cred.leaf_index != Some(leaf_index) {
+                    cred.membership_proofs = None;
+                }
+                cred.leaf_index = Some(leaf_index);
+            }
+            if auth_path.is_some() {
+                cred.auth_path = auth_path;
+            }
+            wallet.save(rng, &wallet_args.wallet, &passphrase)
+        }
+        WalletCommand::List { wallet } => {
+            let wallet = wallet.open()?;
+            for cred in &wallet.creds {
+                match format {
+                    OutputFormat::Human => println!(
+                        "{} leaf_index={} auth_path={} membership_proofs={}",
+                        cred.name(),
+                        cred.leaf_index
+                            .map_or_else(|| "none".to_string(), |i| i.to_string()),
+                        cred.auth_path.is_some(),
+                        cred.membership_proofs.is_some()
+                    ),
+                    OutputFormat::Json => println!(
+                        "{}",
+                        serde_json::json!({
+                            "name": cred.name(),
+                            "leaf_index": cred.leaf_index,
+                            "auth_path": cred.auth_path.is_some(),
+                            "membership_proofs": cred.membership_proofs.is_some(),
+                        })
+                    ),
+                }
+            }
+            Ok(())
+        }
+        WalletCommand::Export { wallet, name } => {
+            let wallet = wallet.open()?;
+            let cred = wallet.get(name.as_deref())?;
+            let mut envelope = Envelope::new(ObjectType::PersonalInfo);
+            envelope.leaf_index = cred.leaf_index;
+            envelope
+                .payload(&cred.info)?
+                .write(format, &mut io::stdout())?;
+            println!();
+            Ok(())
+        }
+    }
+}
+
+/// The output of issue-grant for a single request. On success, the payload is the credential.
+fn grant_envelope(
+    res: Result<(&IssuanceReq, Option<u64>), &Error>,
+    vk_fingerprint: String,
+) -> Result<Envelope, Error> {
+    match res {
+        Ok((req, leaf_index)) => {
+            let mut envelope = Envelope::new(ObjectType::Credential)
+                .key_fingerprint(vk_fingerprint)
+                .verdict(Verdict::Accepted);
+            envelope.leaf_index = leaf_index;
+            envelope.payload(&req.attrs_com)
+        }
+        Err(e) => Ok(Envelope::new(ObjectType::IssuanceVerdict)
+            .key_fingerprint(vk_fingerprint)
+            .verdict(Verdict::from(e))),
+    }
+}
+
+/// Writes a proving and verifying key to the given paths, in the given storage format
+fn write_crs(
+    format: OutputFormat,
+    key_format: StorageFormat,
+    pk_path: PathBuf,
+    vk_path: PathBuf,
+    pk: &PredProvingKey,
+    vk: &PredVerifyingKey,
+) -> Result<(), Error> {
+    let vk_fingerprint = fingerprint(vk)?;
+    let mut pk_file =
+        File::create(pk_path).map_err(Error::io("couldn't create proving key file"))?;
+    let mut vk_file =
+        File::create(vk_path).map_err(Error::io("couldn't create verifying key file"))?;
+    Envelope::new(ObjectType::ProvingKey)
+        .key_fingerprint(vk_fingerprint.clone())
+        .payload_as(pk, key_format)?
+        .write(format, &mut pk_file)?;
+    Envelope::new(ObjectType::VerifyingKey)
+        .key_fingerprint(vk_fingerprint)
+        .payload_as(vk, key_format)?
+        .write(format, &mut vk_file)
+}
+
+/// Opens the issuer's database, if there is one
+fn open_db(path: Option<PathBuf>) -> Result<Option<IssuerDb>, Error> {
+    path.map(|path| IssuerDb::open(&path)).transpose()
+}
+
+/// Creates a file that only its owner can read or write, replacing whatever was there
+fn create_secret_file(path: &Path) -> io::Result<File> {
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::{
+            fs::Permissions,
+            os::unix::fs::{OpenOptionsExt, PermissionsExt},
+        };
+
+        options.mode(0o600);
+        let file = options.open(path)?;
+        // The mode only applies if the file is new, so restrict an existing one too
+        file.set_permissions(Permissions::from_mode(0o600))?;
+        Ok(file)
+    }
+    #[cfg(not(unix))]
+    options.open(path)
+}
+
+/// Writes a ceremony transcript to the given path, replacing whatever was there
+fn write_transcript(
+    format: OutputFormat,
+    path: PathBuf,
+    transcript: &CrsTranscript,
+) -> Result<(), Error> {
+    let mut file = File::create(path).map_err(Error::io("couldn't create transcript file"))?;
+    Envelope::new(ObjectType::CrsTranscript)
+        .payload(transcript)?
+        .write(format, &mut file)
+}
+
+/// Reads a powers of tau transcript from the given path
+fn read_tau_transcript(path: &Path) -> Result<TauTranscript, Error> {
+    let mut file = File::open(path).map_err(Error::io("couldn't open powers of tau file"))?;
+    deser_untrusted_from_reader(&mut file, ObjectType::TauTranscript)
+}
+
+/// Writes a powers of tau transcript to the given path, replacing whatever was there
+fn write_tau_transcript(
+    format: OutputFormat,
+    path: PathBuf,
+    transcript: &TauTranscript,
+) -> Result<(), Error> {
+    let mut file = File::create(path).map_err(Error::io("couldn't create powers of tau file"))?;
+    Envelope::new(ObjectType::TauTranscript)
+        .payload(transcript)?
+        .write(format, &mut file)
 }
@@ -0,0 +1,487 @@
+//! A multi-party ceremony for the Groth16 CRSs in this crate, in the style of the phase 2 of Bowe,
+//! Gabizon, and Miers. `crs_init` derives a circuit's CRS from the outcome of phase 1 (see
+//! `powers_of_tau`), with δ = 1. Every participant then multiplies δ by a secret of their own,
+//! publishing a proof that they know it. As long as one participant in each phase discards their
+//! secrets, nobody knows τ, α, β, or δ.
+
+use crate::{
+    circuit_stats::Circuit,
+    error::Error,
+    groth16::{join_pk, split_pk},
+    issuance::gen_issuance_crs,
+    issuance_checker::PassportHashChecker,
+    params::{
+        Fr, PassportComScheme, PassportComSchemeG, PredProvingKey, PredVerifyingKey, E, H, HG,
+    },
+    passport_info::{PersonalInfo, PersonalInfoVar},
+    powers_of_tau::{verify_tau_transcript, PowersOfTau, TauTranscript},
+    preds::{
+        AgeAndExpiryChecker, AgeChecker, AgeFaceExpiryChecker, AgeMultishowExpiryChecker,
+        ExpiryChecker, FaceChecker, ShowChecker,
+    },
+};
+
+use zkcreds::{attrs::AttrsVar, pred::PredicateChecker, Com};
+
+use ark_crypto_primitives::commitment::CommitmentGadget;
+use ark_ec::{msm::VariableBaseMSM, AffineCurve, PairingEngine, ProjectiveCurve};
+use ark_ff::{Field, PrimeField, UniformRand, Zero};
+use ark_groth16::{ProvingKey, VerifyingKey};
+use ark_poly::{DomainCoeff, EvaluationDomain, GeneralEvaluationDomain};
+use ark_r1cs_std::{alloc::AllocVar, bits::ToBytesGadget, eq::EqGadget};
+use ark_relations::{
+    ns,
+    r1cs::{
+        ConstraintMatrices, ConstraintSynthesizer, ConstraintSystem, ConstraintSystemRef,
+        OptimizationGoal, SynthesisError, SynthesisMode,
+    },
+};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize, Read, SerializationError, Write};
+use ark_std::rand::{CryptoRng, Rng, RngCore, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use sha2::{Digest, Sha256};
+
+type G1Affine = <E as PairingEngine>::G1Affine;
+type G2Affine = <E as PairingEngine>::G2Affine;
+type G1Projective = <E as PairingEngine>::G1Projective;
+type G2Projective = <E as PairingEngine>::G2Projective;
+type PassportComVar = <PassportComSchemeG as CommitmentGadget<PassportComScheme, Fr>>::OutputVar;
+
+// Domain separator for the hash of the transcript that each contribution's proof is bound to
+const CONTRIBUTION_DOMAIN: &[u8] = b"zkcreds-passport-cli crs contribution";
+
+/// One participant's contribution. The participant picked a secret δ' and multiplied δ by it. The
+/// other fields prove they know δ'.
+#[derive(Clone, CanonicalSerialize, CanonicalDeserialize)]
+pub struct Contribution {
+    /// δ·G1 after this contribution
+    pub delta_after: G1Affine,
+    /// A random point s
+    pub s: G1Affine,
+    /// s·δ'
+    pub s_delta: G1Affine,
+    /// r·δ', where r is hashed to G2 from the preceding contributions, s, and s·δ'
+    pub r_delta: G2Affine,
+}
+
+/// The full record of a ceremony. Anyone can check it with `verify_transcript` before trusting the
+/// resulting CRS. The initial CRS isn't recorded, since it's determined by the circuit and the
+/// powers of tau.
+#[derive(Clone, CanonicalSerialize, CanonicalDeserialize)]
+pub struct CrsTranscript {
+    /// Every contribution so far, in order
+    pub contributions: Vec<Contribution>,
+    /// The CRS after all the contributions
+    pub current: PredProvingKey,
+}
+
+/// Starts a ceremony for the given circuit, after checking the phase 1 transcript it's derived from
+pub fn crs_init<R: Rng>(
+    rng: &mut R,
+    powers: &TauTranscript,
+    circuit: Circuit,
+) -> Result<CrsTranscript, Error> {
+    verify_tau_transcript(rng, powers)?;
+    Ok(CrsTranscript {
+        contributions: Vec::new(),
+        current: derive_crs(&powers.current, circuit)?,
+    })
+}
+
+/// The base-2 log of the size of the powers of tau that the given circuit needs
+pub fn tau_log_size(circuit: Circuit) -> Result<u32, Error> {
+    let matrices = circuit_matrices(circuit)?;
+    let domain = circuit_domain(&matrices)?;
+    Ok(domain.log_size_of_group() as u32)
+}
+
+/// Generates the proving and verifying keys for the given circuit in one go, with a single party
+/// trusted to discard the randomness. This is what `crs_init` starts a ceremony from.
+pub fn gen_circuit_crs<R: Rng>(
+    rng: &mut R,
+    circuit: Circuit,
+) -> Result<(PredProvingKey, PredVerifyingKey), Error> {
+    let pk = match circuit {
+        Circuit::Issuance => gen_issuance_crs(rng)?.0,
+        Circuit::Age => gen_crs(rng, AgeChecker::default())?,
+        Circuit::Expiry => gen_crs(rng, ExpiryChecker::default())?,
+        Circuit::Face => gen_crs(rng, FaceChecker::default())?,
+        Circuit::AgeFaceExpiry => gen_crs(rng, AgeFaceExpiryChecker::default())?,
+        Circuit::AgeAndExpiry => gen_crs(rng, AgeAndExpiryChecker::default())?,
+        Circuit::AgeMultishowExpiry => gen_crs(rng, AgeMultishowExpiryChecker::default())?,
+        Circuit::ShowAge => gen_crs(rng, ShowChecker::<AgeChecker>::default())?,
+        Circuit::ShowExpiry => gen_crs(rng, ShowChecker::<ExpiryChecker>::default())?,
+        Circuit::ShowAgeAndExpiry => gen_crs(rng, ShowChecker::<AgeAndExpiryChecker>::default())?,
+    };
+
+    let vk = pk.prepare_verifying_key();
+    Ok((pk, vk))
+}
+
+fn gen_crs<R, P>(rng: &mut R, pred: P) -> Result<PredProvingKey, Error>
+where
+    R: Rng,
+    P: PredicateChecker<Fr, PersonalInfo, PersonalInfoVar, PassportComScheme, PassportComSchemeG>,
+{
+    let pk = zkcreds::pred::gen_pred_crs::<
+        _,
+        _,
+        E,
+        PersonalInfo,
+        PersonalInfoVar,
+        PassportComScheme,
+        PassportComSchemeG,
+        H,
+        HG,
+    >(rng, pred)?;
+    Ok(pk)
+}
+
+/// The circuit that zkcreds proves for a predicate: the attributes open the public commitment, and
+/// satisfy the predicate. This must synthesize exactly the constraints of zkcreds' own version,
+/// since the keys derived from it are used with `prove_birth` and `verify_birth`.
+struct BirthCircuit<P> {
+    pred: P,
+}
+
+impl<P> ConstraintSynthesizer<Fr> for BirthCircuit<P>
+where
+    P: PredicateChecker<Fr, PersonalInfo, PersonalInfoVar, PassportComScheme, PassportComSchemeG>,
+{
+    fn generate_constraints(self, cs: ConstraintSystemRef<Fr>) -> Result<(), SynthesisError> {
+        let attrs_com = PassportComVar::new_input(ns!(cs, "attrs com var"), || {
+            Ok(Com::<PassportComScheme>::default())
+        })?;
+        let attrs =
+            PersonalInfoVar::new_witness(ns!(cs, "attrs var"), || Ok(PersonalInfo::default()))?;
+
+        // Check that the attributes open the commitment
+        let computed_com = PassportComSchemeG::commit(
+            &attrs.get_com_param()?,
+            &attrs.to_bytes()?,
+            &attrs.get_com_nonce()?,
+        )?;
+        attrs_com.enforce_equal(&computed_com)?;
+
+        // Check the predicate
+        self.pred.pred(cs, &attrs)
+    }
+}
+
+/// Synthesizes the given circuit the way ark-groth16's key generation does, and returns its
+/// constraint matrices
+fn circuit_matrices(circuit: Circuit) -> Result<ConstraintMatrices<Fr>, Error> {
+    fn synthesize<P>(pred: P) -> Result<ConstraintMatrices<Fr>, Error>
+    where
+        P: PredicateChecker<
+            Fr,
+            PersonalInfo,
+            PersonalInfoVar,
+            PassportComScheme,
+            PassportComSchemeG,
+        >,
+    {
+        let cs = ConstraintSystem::<Fr>::new_ref();
+        cs.set_optimization_goal(OptimizationGoal::Constraints);
+        cs.set_mode(SynthesisMode::Setup);
+        BirthCircuit { pred }.generate_constraints(cs.clone())?;
+        cs.finalize();
+        Ok(cs.to_matrices().unwrap())
+    }
+
+    match circuit {
+        Circuit::Issuance => synthesize(PassportHashChecker::default()),
+        Circuit::Age => synthesize(AgeChecker::default()),
+        Circuit::Expiry => synthesize(ExpiryChecker::default()),
+        Circuit::Face => synthesize(FaceChecker::default()),
+        Circuit::AgeFaceExpiry => synthesize(AgeFaceExpiryChecker::default()),
+        Circuit::AgeAndExpiry => synthesize(AgeAndExpiryChecker::default()),
+        Circuit::AgeMultishowExpiry => synthesize(AgeMultishowExpiryChecker::default()),
+        Circuit::ShowAge => synthesize(ShowChecker::<AgeChecker>::default()),
+        Circuit::ShowExpiry => synthesize(ShowChecker::<ExpiryChecker>::default()),
+        Circuit::ShowAgeAndExpiry => synthesize(ShowChecker::<AgeAndExpiryChecker>::default()),
+    }
+}
+
+/// The evaluation domain of the circuit's QAP. As in ark-groth16, there's a point for every
+/// constraint, and one for every instance variable.
+fn circuit_domain(matrices: &ConstraintMatrices<Fr>) -> Result<GeneralEvaluationDomain<Fr>, Error> {
+    GeneralEvaluationDomain::new(matrices.num_constraints + matrices.num_instance_variables)
+        .ok_or_else(|| Error::Parse("circuit is too large for the scalar field".to_string()))
+}
+
+/// Derives the given circuit's CRS from the powers of tau, with γ = δ = 1. This is what
+/// ark-groth16's key generation computes for the same circuit, with τ, α, and β taken from the
+/// powers rather than sampled.
+fn derive_crs(powers: &PowersOfTau, circuit: Circuit) -> Result<PredProvingKey, Error> {
+    let matrices = circuit_matrices(circuit)?;
+    let domain = circuit_domain(&matrices)?;
+    let n = domain.size();
+    if powers.tau_g2.len() < n {
+        return Err(Error::Parse(format!(
+            "circuit needs powers of tau of log size {}, but these have log size {}",
+            domain.log_size_of_group(),
+            powers.tau_g2.len().trailing_zeros(),
+        )));
+    }
+
+    // The Lagrange polynomials of the domain evaluated at τ, in the exponent. These are the inverse
+    // FFT of the powers of τ.
+    let lagrange_g1 = lagrange(&domain, &powers.tau_g1[..n]);
+    let lagrange_g2 = lagrange(&domain, &powers.tau_g2[..n]);
+    let alpha_lagrange_g1 = lagrange(&domain, &powers.alpha_tau_g1[..n]);
+    let beta_lagrange_g1 = lagrange(&domain, &powers.beta_tau_g1[..n]);
+
+    // Evaluate every variable's QAP polynomials at τ. The k-th constraint is the k-th point of the
+    // domain, and the i-th instance variable also gets the point after the last constraint plus i.
+    let num_instance = matrices.num_instance_variables;
+    let num_vars = num_instance + matrices.num_witness_variables;
+    let mut a_g1 = vec![G1Projective::zero(); num_vars];
+    let mut b_g1 = vec![G1Projective::zero(); num_vars];
+    let mut b_g2 = vec![G2Projective::zero(); num_vars];
+    // β·A + α·B + C, for gamma_abc_g1 and l_query
+    let mut abc_g1 = vec![G1Projective::zero(); num_vars];
+    for i in 0..num_instance {
+        let k = matrices.num_constraints + i;
+        a_g1[i].add_assign_mixed(&lagrange_g1[k]);
+        abc_g1[i].add_assign_mixed(&beta_lagrange_g1[k]);
+    }
+    for (k, ((a_row, b_row), c_row)) in matrices
+        .a
+        .iter()
+        .zip(&matrices.b)
+        .zip(&matrices.c)
+        .enumerate()
+    {
+        for &(coeff, j) in a_row {
+            a_g1[j] += lagrange_g1[k].mul(coeff);
+            abc_g1[j] += beta_lagrange_g1[k].mul(coeff);
+        }
+        for &(coeff, j) in b_row {
+            b_g1[j] += lagrange_g1[k].mul(coeff);
+            b_g2[j] += lagrange_g2[k].mul(coeff);
+            abc_g1[j] += alpha_lagrange_g1[k].mul(coeff);
+        }
+        for &(coeff, j) in c_row {
+            abc_g1[j] += lagrange_g1[k].mul(coeff);
+        }
+    }
+
+    // τ^i·Z(τ) for i in 0..n-1, where Z(X) = X^n - 1 vanishes on the domain
+    let h_g1: Vec<_> = (0..n - 1)
+        .map(|i| powers.tau_g1[i + n].into_projective() - powers.tau_g1[i].into_projective())
+        .collect();
+
+    let abc_g1 = G1Projective::batch_normalization_into_affine(&abc_g1);
+    let g1 = G1Affine::prime_subgroup_generator();
+    let g2 = G2Affine::prime_subgroup_generator();
+    let pk = ProvingKey {
+        vk: VerifyingKey {
+            alpha_g1: powers.alpha_tau_g1[0],
+            beta_g2: powers.beta_g2,
+            gamma_g2: g2,
+            delta_g2: g2,
+            gamma_abc_g1: abc_g1[..num_instance].to_vec(),
+        },
+        beta_g1: powers.beta_tau_g1[0],
+        delta_g1: g1,
+        a_query: G1Projective::batch_normalization_into_affine(&a_g1),
+        b_g1_query: G1Projective::batch_normalization_into_affine(&b_g1),
+        b_g2_query: G2Projective::batch_normalization_into_affine(&b_g2),
+        h_query: G1Projective::batch_normalization_into_affine(&h_g1),
+        l_query: abc_g1[num_instance..].to_vec(),
+    };
+
+    // zkcreds serializes nothing after the Groth16 key, so there's nothing else to fill in. If that
+    // ever changes, this fails rather than making a malformed key.
+    join_pk(&pk, &[])
+}
+
+/// Evaluates the domain's Lagrange polynomials at τ, given the first n powers of τ
+fn lagrange<G>(domain: &GeneralEvaluationDomain<Fr>, powers: &[G]) -> Vec<G>
+where
+    G: AffineCurve<ScalarField = Fr>,
+    G::Projective: DomainCoeff<Fr>,
+{
+    let powers: Vec<_> = powers.iter().map(AffineCurve::into_projective).collect();
+    G::Projective::batch_normalization_into_affine(&domain.ifft(&powers))
+}
+
+/// Adds a contribution to the transcript. Returns the hex-encoded hash of the contribution, which
+/// the participant should publish so others can find it in the final transcript.
+pub fn crs_contribute<R: RngCore + CryptoRng>(
+    rng: &mut R,
+    transcript: &mut CrsTranscript,
+) -> Result<String, Error> {
+    let (mut pk, rest) = split_pk(&transcript.current)?;
+
+    // Multiply δ by our secret, and divide everything that has δ in its denominator by it
+    let delta = Fr::rand(rng);
+    let delta_inv = delta.inverse().unwrap();
+    let delta_before = pk.delta_g1;
+    pk.delta_g1 = pk.delta_g1.mul(delta).into_affine();
+    pk.vk.delta_g2 = pk.vk.delta_g2.mul(delta).into_affine();
+    pk.h_query = scale(&pk.h_query, delta_inv);
+    pk.l_query = scale(&pk.l_query, delta_inv);
+
+    // Prove knowledge of our secret
+    let s = <E as PairingEngine>::G1Projective::rand(rng).into_affine();
+    let s_delta = s.mul(delta).into_affine();
+    let r = hash_to_g2(&contribution_digest(
+        &transcript.contributions,
+        &delta_before,
+        &s,
+        &s_delta,
+    )?);
+    let contribution = Contribution {
+        delta_after: pk.delta_g1,
+        s,
+        s_delta,
+        r_delta: r.mul(delta).into_affine(),
+    };
+
+    let mut buf = Vec::new();
+    contribution.serialize_uncompressed(&mut buf)?;
+    transcript.contributions.push(contribution);
+    transcript.current = join_pk(&pk, &rest)?;
+
+    Ok(format!("{:x}", Sha256::digest(&buf)))
+}
+
+/// Checks every contribution in the transcript, and that the current CRS is the one `crs_init`
+/// derives for the circuit from the given powers of tau, with δ re-randomized by exactly those
+/// contributions. On success, returns the current CRS.
+pub fn verify_transcript<R: Rng>(
+    rng: &mut R,
+    transcript: &CrsTranscript,
+    powers: &TauTranscript,
+    circuit: Circuit,
+) -> Result<(PredProvingKey, PredVerifyingKey), Error> {
+    let fail = |msg: &str| {
+        Err(Error::ProofFailure(format!(
+            "invalid CRS transcript: {}",
+            msg
+        )))
+    };
+    let initial_pk = crs_init(rng, powers, circuit)?.current;
+    let (initial, initial_rest) = split_pk(&initial_pk)?;
+    let (current, current_rest) = split_pk(&transcript.current)?;
+
+    // δ starts out as 1, so someone has to have contributed
+    if transcript.contributions.is_empty() {
+        return fail("there are no contributions");
+    }
+    let g1 = G1Affine::prime_subgroup_generator();
+    let g2 = G2Affine::prime_subgroup_generator();
+
+    // δ must be the same in G1 and G2
+    for pk in [&initial, &current] {
+        if !same_ratio((g1, pk.delta_g1), (g2, pk.vk.delta_g2)) {
+            return fail("δ in G1 doesn't match δ in G2");
+        }
+    }
+
+    // Nothing but δ may change
+    if initial.vk.alpha_g1 != current.vk.alpha_g1
+        || initial.vk.beta_g2 != current.vk.beta_g2
+        || initial.vk.gamma_g2 != current.vk.gamma_g2
+        || initial.vk.gamma_abc_g1 != current.vk.gamma_abc_g1
+        || initial.beta_g1 != current.beta_g1
+        || initial.a_query != current.a_query
+        || initial.b_g1_query != current.b_g1_query
+        || initial.b_g2_query != current.b_g2_query
+        || initial.h_query.len() != current.h_query.len()
+        || initial.l_query.len() != current.l_query.len()
+        || initial_rest != current_rest
+    {
+        return fail("the CRS was modified outside of δ");
+    }
+
+    // Every contribution must prove knowledge of the secret it multiplied δ by
+    let mut delta = initial.delta_g1;
+    for (i, c) in transcript.contributions.iter().enumerate() {
+        let r = hash_to_g2(&contribution_digest(
+            &transcript.contributions[..i],
+            &delta,
+            &c.s,
+            &c.s_delta,
+        )?);
+        if c.s.is_zero()
+            || c.delta_after.is_zero()
+            || !same_ratio((c.s, c.s_delta), (r, c.r_delta))
+            || !same_ratio((delta, c.delta_after), (r, c.r_delta))
+        {
+            return fail(&format!("contribution {} is invalid", i + 1));
+        }
+        delta = c.delta_after;
+    }
+    if delta != current.delta_g1 {
+        return fail("the current δ isn't the result of the contributions");
+    }
+
+    // The H and L queries must have been divided by whatever δ was multiplied by. We check this
+    // for random linear combinations of them.
+    for (init_query, cur_query) in [
+        (&initial.h_query, &current.h_query),
+        (&initial.l_query, &current.l_query),
+    ] {
+        let coeffs: Vec<_> = (0..init_query.len())
+            .map(|_| Fr::rand(rng).into_repr())
+            .collect();
+        let init_comb = VariableBaseMSM::multi_scalar_mul(init_query, &coeffs).into_affine();
+        let cur_comb = VariableBaseMSM::multi_scalar_mul(cur_query, &coeffs).into_affine();
+        if !same_ratio(
+            (cur_comb, init_comb),
+            (initial.vk.delta_g2, current.vk.delta_g2),
+        ) {
+            return fail("the H and L queries don't match δ");
+        }
+    }
+
+    let pk = transcript.current.clone();
+    let vk = pk.prepare_verifying_key();
+    Ok((pk, vk))
+}
+
+/// Returns whether `b1 = k·a1` and `b2 = k·a2` for the same `k`
+pub(crate) fn same_ratio((a1, b1): (G1Affine, G1Affine), (a2, b2): (G2Affine, G2Affine)) -> bool {
+    E::pairing(a1, b2) == E::pairing(b1, a2)
+}
+
+/// Multiplies every point by the given scalar
+fn scale(points: &[G1Affine], scalar: Fr) -> Vec<G1Affine> {
+    let scaled: Vec<_> = points.iter().map(|p| p.mul(scalar)).collect();
+    <E as PairingEngine>::G1Projective::batch_normalization_into_affine(&scaled)
+}
+
+/// The hash that a contribution's r is derived from. It covers every preceding contribution, so a
+/// proof of knowledge can't be replayed elsewhere in the transcript.
+fn contribution_digest(
+    preceding: &[Contribution],
+    delta_before: &G1Affine,
+    s: &G1Affine,
+    s_delta: &G1Affine,
+) -> Result<[u8; 32], Error> {
+    let mut buf = CONTRIBUTION_DOMAIN.to_vec();
+    preceding.serialize_uncompressed(&mut buf)?;
+    delta_before.serialize_uncompressed(&mut buf)?;
+    s.serialize_uncompressed(&mut buf)?;
+    s_delta.serialize_uncompressed(&mut buf)?;
+    Ok(Sha256::digest(&buf).into())
+}
+
+/// Hashes to a point in G2 whose discrete log nobody knows, by trying random x-coordinates drawn
+/// from a PRG seeded with the digest
+pub(crate) fn hash_to_g2(digest: &[u8; 32]) -> G2Affine {
+    let mut rng = ChaCha20Rng::from_seed(*digest);
+    loop {
+        let mut bytes = [0u8; 128];
+        rng.fill_bytes(&mut bytes);
+        if let Some(p) = G2Affine::from_random_bytes(&bytes) {
+            let p = p.mul_by_cofactor();
+            if !p.is_zero() {
+                return p;
+            }
+        }
+    }
+}
@@ -0,0 +1,631 @@
+use crate::{
+    error::Error,
+    params::{PARAM_SET_ID, PARAM_SET_NAMES},
+};
+
+use std::{
+    fs::File,
+    io::{Read, Write},
+    path::Path,
+};
+
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use clap::ArgEnum;
+use memmap2::Mmap;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// The version of the JSON envelope format. This is bumped whenever a field changes meaning.
+pub const ENVELOPE_VERSION: u32 = 2;
+
+/// The most that the body of a frame read from a stream may decompress to. Only keys get anywhere
+/// near this, and keys are read from files, which aren't limited.
+pub const MAX_DECOMPRESSED_LEN: usize = 64 * 1024 * 1024;
+
+/// The magic bytes at the start of every frame
+pub const FRAME_MAGIC: [u8; 4] = *b"ZKPP";
+
+/// The version of the frame format. This is bumped whenever the header changes, or whenever objects
+/// made by an older version stop working with this one. Version 2 changed how the commitment and
+/// tree hash parameters are generated. Version 3 added the nullifier to issuance requests, and
+/// derives it from the passport's DG2 hash.
+pub const FRAME_VERSION: u8 = 3;
+
+/// How a command should write its output
+#[derive(Clone, Copy, PartialEq, Eq, ArgEnum)]
+pub enum OutputFormat {
+    /// Raw base64 of the framed object
+    Human,
+    /// A JSON envelope containing metadata and the base64 of the framed object
+    Json,
+}
+
+/// The kinds of objects this CLI reads and writes
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ObjectType {
+    ProvingKey,
+    VerifyingKey,
+    IssuanceRequest,
+    IssuanceVerdict,
+    Credential,
+    Tree,
+    Root,
+    CrsTranscript,
+    AuthPath,
+    ShowProof,
+    PersonalInfo,
+    TauTranscript,
+    MembershipProvingKey,
+    MembershipVerifyingKey,
+    Wallet,
+    IssuerSigningKey,
+    IssuerPublicKey,
+    RootStatement,
+    Revocation,
+}
+
+impl ObjectType {
+    const ALL: [ObjectType; 19] = [
+        ObjectType::ProvingKey,
+        ObjectType::VerifyingKey,
+        ObjectType::IssuanceRequest,
+        ObjectType::IssuanceVerdict,
+        ObjectType::Credential,
+        ObjectType::Tree,
+        ObjectType::Root,
+        ObjectType::CrsTranscript,
+        ObjectType::AuthPath,
+        ObjectType::ShowProof,
+        ObjectType::PersonalInfo,
+        ObjectType::TauTranscript,
+        ObjectType::MembershipProvingKey,
+        ObjectType::MembershipVerifyingKey,
+        ObjectType::Wallet,
+        ObjectType::IssuerSigningKey,
+        ObjectType::IssuerPublicKey,
+        ObjectType::RootStatement,
+        ObjectType::Revocation,
+    ];
+
+    /// The byte identifying this type in a frame header
+    fn id(self) -> u8 {
+        ObjectType::ALL.iter().position(|&ty| ty == self).unwrap() as u8 + 1
+    }
+
+    fn from_id(id: u8) -> Option<ObjectType> {
+        ObjectType::ALL.get((id as usize).checked_sub(1)?).copied()
+    }
+
+    /// A human-readable name for error messages
+    pub fn name(self) -> &'static str {
+        match self {
+            ObjectType::ProvingKey => "proving key",
+            ObjectType::VerifyingKey => "verifying key",
+            ObjectType::IssuanceRequest => "issuance request",
+            ObjectType::IssuanceVerdict => "issuance verdict",
+            ObjectType::Credential => "credential",
+            ObjectType::Tree => "tree",
+            ObjectType::Root => "root",
+            ObjectType::CrsTranscript => "CRS transcript",
+            ObjectType::AuthPath => "auth path",
+            ObjectType::ShowProof => "show proof",
+            ObjectType::PersonalInfo => "personal info",
+            ObjectType::TauTranscript => "powers of tau transcript",
+            ObjectType::MembershipProvingKey => "membership proving key",
+            ObjectType::MembershipVerifyingKey => "membership verifying key",
+            ObjectType::Wallet => "wallet",
+            ObjectType::IssuerSigningKey => "issuer signing key",
+            ObjectType::IssuerPublicKey => "issuer public key",
+            ObjectType::RootStatement => "root statement",
+            ObjectType::Revocation => "revocation",
+        }
+    }
+}
+
+/// The outcome of checking an issuance request
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum Verdict {
+    Accepted,
+    Rejected {
+        /// The `Error::kind()` of the reason for rejection
+        kind: String,
+        reason: String,
+    },
+}
+
+impl From<&Error> for Verdict {
+    fn from(e: &Error) -> Verdict {
+        Verdict::Rejected {
+            kind: e.kind().to_string(),
+            reason: e.to_string(),
+        }
+    }
+}
+
+/// A self-describing wrapper around a serialized object. Fields that don't make sense for a given
+/// object are omitted.
+#[derive(Serialize, Deserialize)]
+pub struct Envelope {
+    #[serde(rename = "type")]
+    pub ty: ObjectType,
+    pub version: u32,
+    /// Hex-encoded SHA-256 hash of the issuance verifying key this object relates to
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub key_fingerprint: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub leaf_index: Option<u64>,
+    /// Base64-encoded tree root
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub root: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub verdict: Option<Verdict>,
+    /// Base64-encoded serialized object. This is the entirety of the output in human mode.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub payload: Option<String>,
+    /// The raw frame, if the object is stored in a binary format. This is written as is,
+    /// regardless of the output format.
+    #[serde(skip)]
+    binary: Option<Vec<u8>>,
+}
+
+impl Envelope {
+    pub fn new(ty: ObjectType) -> Envelope {
+        Envelope {
+            ty,
+            version: ENVELOPE_VERSION,
+            key_fingerprint: None,
+            leaf_index: None,
+            root: None,
+            verdict: None,
+            payload: None,
+            binary: None,
+        }
+    }
+
+    /// Sets the payload to the base64 of `val` in a frame. The frame records this envelope's type
+    /// and key fingerprint, so those must be set first.
+    pub fn payload<T: CanonicalSerialize>(mut self, val: &T) -> Result<Envelope, Error> {
+        let frame = to_frame(self.ty, self.key_fingerprint.as_deref(), val)?;
+        self.payload = Some(base64::encode(frame));
+        Ok(self)
+    }
+
+    /// Like `payload`, but stores the object in the given format
+    pub fn payload_as<T: CanonicalSerialize>(
+        mut self,
+        val: &T,
+        storage: StorageFormat,
+    ) -> Result<Envelope, Error> {
+        if storage == StorageFormat::Base64 {
+            return self.payload(val);
+        }
+
+        let frame = to_frame_as(self.ty, self.key_fingerprint.as_deref(), val, storage)?;
+        self.binary = Some(frame);
+        Ok(self)
+    }
+
+    /// Sets the root to the base64 serialization of `root`
+    pub fn root<T: CanonicalSerialize>(mut self, root: &T) -> Result<Envelope, Error> {
+        self.root = Some(to_base64(root)?);
+        Ok(self)
+    }
+
+    pub fn leaf_index(mut self, leaf_index: u64) -> Envelope {
+        self.leaf_index = Some(leaf_index);
+        self
+    }
+
+    pub fn key_fingerprint(mut self, fingerprint: String) -> Envelope {
+        self.key_fingerprint = Some(fingerprint);
+        self
+    }
+
+    pub fn verdict(mut self, verdict: Verdict) -> Envelope {
+        self.verdict = Some(verdict);
+        self
+    }
+
+    /// Writes this envelope in the given format. In human mode, only the payload is written. If the
+    /// payload is stored in a binary format, only the payload is written, in either mode.
+    pub fn write<W: Write>(&self, format: OutputFormat, w: &mut W) -> Result<(), Error> {
+        if let Some(frame) = &self.binary {
+            return w
+                .write_all(frame)
+                .map_err(Error::io("couldn't write output"));
+        }
+
+        match format {
+            OutputFormat::Human => {
+                if let Some(payload) = &self.payload {
+                    w.write_all(payload.as_bytes())
+                        .map_err(Error::io("couldn't write output"))?;
+                }
+            }
+            OutputFormat::Json => {
+                serde_json::to_writer(&mut *w, self)
+                    .map_err(|e| Error::Io("couldn't write output".to_string(), e.into()))?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns the hex-encoded SHA-256 hash of the serialized verifying key
+pub fn fingerprint<T: CanonicalSerialize>(vk: &T) -> Result<String, Error> {
+    let mut buf = Vec::new();
+    vk.serialize_uncompressed(&mut buf)?;
+    Ok(format!("{:x}", Sha256::digest(&buf)))
+}
+
+/// Serializes the given value and base64-encodes it
+pub fn to_base64<T: CanonicalSerialize>(val: &T) -> Result<String, Error> {
+    let mut buf = Vec::new();
+    val.serialize_uncompressed(&mut buf)?;
+    Ok(base64::encode(buf))
+}
+
+/// Reads an input produced by this CLI in any format, and returns the frame. Binary frames are
+/// detected by their magic bytes, and JSON envelopes by their leading `{`. Anything else is taken
+/// to be base64.
+pub fn read_frame_bytes(buf: &[u8]) -> Result<Vec<u8>, Error> {
+    if buf.starts_with(&FRAME_MAGIC) {
+        return Ok(buf.to_vec());
+    }
+
+    // Ignore leading and trailing whitespace, e.g., the newline at the end of a request
+    let start = buf
+        .iter()
+        .position(|b| !b.is_ascii_whitespace())
+        .unwrap_or(buf.len());
+    let end = buf
+        .iter()
+        .rposition(|b| !b.is_ascii_whitespace())
+        .map_or(start, |i| i + 1);
+    let trimmed = &buf[start..end];
+
+    let payload = if trimmed.starts_with(b"{") {
+        let envelope: Envelope = serde_json::from_slice(trimmed)
+            .map_err(Error::deser("couldn't deserialize JSON envelope"))?;
+        envelope
+            .payload
+            .map(String::into_bytes)
+            .ok_or_else(|| Error::Deserialization("JSON envelope has no payload".to_string()))?
+    } else {
+        trimmed.to_vec()
+    };
+    base64::decode(&payload).map_err(Error::deser("couldn't decode base64"))
+}
+
+/// How an object is stored. Base64 is what the output formats use. The binary formats are for
+/// keys, which are big: they're raw frames, so they're a quarter smaller and don't have to be
+/// decoded, and binary files are memory-mapped when loaded. They're never wrapped in JSON. Points
+/// are uncompressed in every format, since decompressing a point means checking it, and that would
+/// dominate the time it takes to load a key.
+#[derive(Clone, Copy, PartialEq, Eq, ArgEnum)]
+pub enum StorageFormat {
+    /// A base64-encoded frame
+    Base64,
+    /// A raw frame
+    Binary,
+    /// A raw frame, with its body compressed with zstd
+    BinaryZstd,
+}
+
+// The bits of the frame header's flags byte
+const FLAG_HAS_KEY: u8 = 1;
+const FLAG_BINARY: u8 = 2;
+const FLAG_ZSTD: u8 = 4;
+
+// The zstd compression level for binary frames. Curve points are close to random, so higher levels
+// aren't worth the time.
+const ZSTD_LEVEL: i32 = 3;
+
+// The length of the frame header without the key hash
+const HEADER_LEN: usize = 8;
+
+/// Serializes `val` into a base64-style frame. See `to_frame_as`.
+pub fn to_frame<T: CanonicalSerialize>(
+    ty: ObjectType,
+    key_fingerprint: Option<&str>,
+    val: &T,
+) -> Result<Vec<u8>, Error> {
+    to_frame_as(ty, key_fingerprint, val, StorageFormat::Base64)
+}
+
+/// Serializes `val` into a frame. A frame is a header followed by the serialized object. The header
+/// is the magic bytes, the frame version, the object type, the parameter set ID, a byte of flags,
+/// and optionally the SHA-256 hash of the verifying key the object is bound to:
+///
+/// | magic (4) | version (1) | type (1) | param set (1) | flags (1) | key hash (0 or 32) |
+///
+/// The flags say whether the key hash is present, whether the frame is stored raw rather than in
+/// base64, and whether the body is compressed with zstd.
+pub fn to_frame_as<T: CanonicalSerialize>(
+    ty: ObjectType,
+    key_fingerprint: Option<&str>,
+    val: &T,
+    storage: StorageFormat,
+) -> Result<Vec<u8>, Error> {
+    let mut flags = match storage {
+        StorageFormat::Base64 => 0,
+        StorageFormat::Binary => FLAG_BINARY,
+        StorageFormat::BinaryZstd => FLAG_BINARY | FLAG_ZSTD,
+    };
+    if key_fingerprint.is_some() {
+        flags |= FLAG_HAS_KEY;
+    }
+
+    let mut buf = FRAME_MAGIC.to_vec();
+    buf.extend_from_slice(&[FRAME_VERSION, ty.id(), PARAM_SET_ID, flags]);
+    if let Some(fingerprint) = key_fingerprint {
+        buf.extend_from_slice(&decode_fingerprint(fingerprint)?);
+    }
+
+    match storage {
+        StorageFormat::Base64 | StorageFormat::Binary => val.serialize_uncompressed(&mut buf)?,
+        StorageFormat::BinaryZstd => {
+            let mut body = Vec::new();
+            val.serialize_uncompressed(&mut body)?;
+            let compressed = zstd::encode_all(body.as_slice(), ZSTD_LEVEL)
+                .map_err(Error::io("couldn't compress output"))?;
+            buf.extend_from_slice(&compressed);
+        }
+    }
+
+    Ok(buf)
+}
+
+// The bytes of a frame. Binary files are memory-mapped rather than read.
+enum FrameBytes {
+    Owned(Vec<u8>),
+    Mapped(Mmap),
+}
+
+impl FrameBytes {
+    fn as_slice(&self) -> &[u8] {
+        match self {
+            FrameBytes::Owned(bytes) => bytes,
+            FrameBytes::Mapped(map) => map,
+        }
+    }
+}
+
+/// A framed object whose header has been checked, but whose body hasn't been deserialized yet
+pub struct Frame {
+    pub ty: ObjectType,
+    /// Hex-encoded SHA-256 hash of the verifying key the object is bound to
+    pub key_fingerprint: Option<String>,
+    /// How the frame was stored
+    pub storage: StorageFormat,
+    bytes: FrameBytes,
+    body_offset: usize,
+}
+
+impl Frame {
+    /// Reads a frame, in any format, and checks that it holds an object of the expected type made
+    /// with this build's parameter set
+    pub fn read<R: Read>(r: &mut R, expected: ObjectType) -> Result<Frame, Error> {
+        Frame::read_inner(r, Some(expected))
+    }
+
+    /// Like `read`, but accepts objects of any type
+    pub fn read_any<R: Read>(r: &mut R) -> Result<Frame, Error> {
+        Frame::read_inner(r, None)
+    }
+
+    /// Like `read`, but for a file. Binary files are memory-mapped, so big keys are deserialized
+    /// straight from the file rather than read into a buffer first.
+    pub fn open(path: &Path, expected: ObjectType) -> Result<Frame, Error> {
+        Frame::open_inner(path, Some(expected))
+    }
+
+    /// Like `open`, but accepts objects of any type
+    pub fn open_any(path: &Path) -> Result<Frame, Error> {
+        Frame::open_inner(path, None)
+    }
+
+    fn read_inner<R: Read>(r: &mut R, expected: Option<ObjectType>) -> Result<Frame, Error> {
+        let mut buf = Vec::new();
+        r.read_to_end(&mut buf)
+            .map_err(Error::io("couldn't read input"))?;
+        Frame::parse(
+            FrameBytes::Owned(read_frame_bytes(&buf)?),
+            expected,
+            Some(MAX_DECOMPRESSED_LEN),
+        )
+    }
+
+    fn open_inner(path: &Path, expected: Option<ObjectType>) -> Result<Frame, Error> {
+        let file = File::open(path).map_err(Error::io("couldn't open input file"))?;
+        // Safety: the map is read-only, and we don't support files changing while we read them
+        let map = unsafe { Mmap::map(&file) }.map_err(Error::io("couldn't map input file"))?;
+
+        if map.starts_with(&FRAME_MAGIC) {
+            Frame::parse(FrameBytes::Mapped(map), expected, None)
+        } else {
+            Frame::parse(FrameBytes::Owned(read_frame_bytes(&map)?), expected, None)
+        }
+    }
+
+    // Checks the header, and decompresses the body if need be. If there's a limit on the size of the
+    // decompressed body, bodies that exceed it are rejected without decompressing the rest.
+    fn parse(
+        bytes: FrameBytes,
+        expected: Option<ObjectType>,
+        max_decompressed_len: Option<usize>,
+    ) -> Result<Frame, Error> {
+        let buf = bytes.as_slice();
+        let bad = |msg: String| {
+            Error::Deserialization(match expected {
+                Some(ty) => format!("expected a {}, but {}", ty.name(), msg),
+                None => format!("malformed input: {}", msg),
+            })
+        };
+
+        if buf.len() < HEADER_LEN || buf[..4] != FRAME_MAGIC {
+            return Err(bad("the input isn't a file made by this tool".to_string()));
+        }
+        let (version, ty_id, param_set, flags) = (buf[4], buf[5], buf[6], buf[7]);
+        if version != FRAME_VERSION {
+            return Err(bad(format!(
+                "the input has format version {}, and this build only reads version {}",
+                version, FRAME_VERSION
+            )));
+        }
+        let ty = ObjectType::from_id(ty_id)
+            .ok_or_else(|| bad(format!("the input has unknown object type {}", ty_id)))?;
+        if expected.map_or(false, |expected| ty != expected) {
+            return Err(bad(format!("the input is a {}", ty.name())));
+        }
+        if param_set != PARAM_SET_ID {
+            let name = PARAM_SET_NAMES
+                .get(param_set as usize)
+                .unwrap_or(&"unknown");
+            return Err(bad(format!(
+                "the input uses the {} parameter set, and this build uses {}",
+                name, PARAM_SET_NAMES[PARAM_SET_ID as usize]
+            )));
+        }
+        if flags & !(FLAG_HAS_KEY | FLAG_BINARY | FLAG_ZSTD) != 0 {
+            return Err(bad("the input's header is malformed".to_string()));
+        }
+
+        let mut body_offset = HEADER_LEN;
+        let mut key_fingerprint = None;
+        if flags & FLAG_HAS_KEY != 0 {
+            let hash = buf
+                .get(HEADER_LEN..HEADER_LEN + 32)
+                .ok_or_else(|| bad("the input's header is malformed".to_string()))?;
+            key_fingerprint = Some(hash.iter().map(|b| format!("{:02x}", b)).collect());
+            body_offset += 32;
+        }
+
+        let storage = match (flags & FLAG_BINARY != 0, flags & FLAG_ZSTD != 0) {
+            (false, false) => StorageFormat::Base64,
+            (true, false) => StorageFormat::Binary,
+            (true, true) => StorageFormat::BinaryZstd,
+            (false, true) => return Err(bad("the input's header is malformed".to_string())),
+        };
+
+        // Decompress the body up front, so the rest of the frame doesn't have to care
+        let (bytes, body_offset) = match storage {
+            StorageFormat::BinaryZstd => {
+                let didnt_decompress =
+                    |e: std::io::Error| bad(format!("the input didn't decompress: {}", e));
+                let decoder = zstd::stream::read::Decoder::new(&buf[body_offset..])
+                    .map_err(didnt_decompress)?;
+                let limit = max_decompressed_len.map_or(u64::MAX, |len| len as u64 + 1);
+                let mut body = Vec::new();
+                decoder
+                    .take(limit)
+                    .read_to_end(&mut body)
+                    .map_err(didnt_decompress)?;
+                if let Some(len) = max_decompressed_len.filter(|&len| body.len() > len) {
+                    return Err(bad(format!(
+                        "the input decompresses to more than {} bytes",
+                        len
+                    )));
+                }
+                (FrameBytes::Owned(body), 0)
+            }
+            _ => (bytes, body_offset),
+        };
+
+        Ok(Frame {
+            ty,
+            key_fingerprint,
+            storage,
+            bytes,
+            body_offset,
+        })
+    }
+
+    fn body(&self) -> &[u8] {
+        &self.bytes.as_slice()[self.body_offset..]
+    }
+
+    /// Fails unless this object is bound to the verifying key with the given fingerprint
+    pub fn check_key(&self, fingerprint: &str) -> Result<(), Error> {
+        match &self.key_fingerprint {
+            Some(f) if f == fingerprint => Ok(()),
+            Some(f) => Err(Error::ProofFailure(format!(
+                "{} was made for verifying key {}, but this verifying key is {}",
+                self.ty.name(),
+                f,
+                fingerprint
+            ))),
+            None => Err(Error::ProofFailure(format!(
+                "{} isn't bound to a verifying key",
+                self.ty.name()
+            ))),
+        }
+    }
+
+    /// Deserializes the object without checking its points. This is only for inputs we made
+    /// ourselves, like keys, since the checks are slow for large objects. Anything that came from
+    /// someone else must go through `deser_untrusted`.
+    pub fn deser<T: CanonicalDeserialize>(&self) -> Result<T, Error> {
+        T::deserialize_unchecked(self.body()).map_err(Error::deser(&format!(
+            "couldn't deserialize {}",
+            self.ty.name()
+        )))
+    }
+
+    /// Deserializes an object from an untrusted party. This rejects points that aren't on the
+    /// curve or aren't in the prime-order subgroup, as well as trailing data.
+    pub fn deser_untrusted<T: CanonicalDeserialize>(&self) -> Result<T, Error> {
+        let context = format!("couldn't deserialize {}", self.ty.name());
+        let mut reader = self.body();
+        let val = T::deserialize_uncompressed(&mut reader).map_err(Error::deser(&context))?;
+        if !reader.is_empty() {
+            return Err(Error::Deserialization(format!(
+                "{}: {} bytes of trailing data",
+                context,
+                reader.len()
+            )));
+        }
+
+        Ok(val)
+    }
+}
+
+/// Reads a framed object of the given type, in any format, decompressing it if need be. See
+/// `Frame::deser` for when this is appropriate.
+pub fn deser_from_reader<R: Read, T: CanonicalDeserialize>(
+    r: &mut R,
+    ty: ObjectType,
+) -> Result<T, Error> {
+    Frame::read(r, ty)?.deser()
+}
+
+/// Like `deser_from_reader`, but for inputs from untrusted parties. See `Frame::deser_untrusted`.
+pub fn deser_untrusted_from_reader<R: Read, T: CanonicalDeserialize>(
+    r: &mut R,
+    ty: ObjectType,
+) -> Result<T, Error> {
+    Frame::read(r, ty)?.deser_untrusted()
+}
+
+/// Reads a framed object of the given type from a file, in any format. Binary files are
+/// memory-mapped. See `Frame::deser` for when this is appropriate.
+pub fn deser_from_file<T: CanonicalDeserialize>(path: &Path, ty: ObjectType) -> Result<T, Error> {
+    Frame::open(path, ty)?.deser()
+}
+
+/// Parses a hex-encoded SHA-256 hash
+fn decode_fingerprint(fingerprint: &str) -> Result<[u8; 32], Error> {
+    let bad = || Error::Parse(format!("malformed key fingerprint {:?}", fingerprint));
+    if fingerprint.len() != 64 || !fingerprint.is_ascii() {
+        return Err(bad());
+    }
+
+    let mut bytes = [0u8; 32];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&fingerprint[2 * i..2 * i + 2], 16).map_err(|_| bad())?;
+    }
+    Ok(bytes)
+}
@@ -1,21 +1,27 @@
-use crate::passport_info::{PersonalInfo, PersonalInfoVar};
+use crate::{
+    passport_info::{PersonalInfo, PersonalInfoVar},
+    public_params::{gen_com_param, gen_crh_param, COM_PARAM_LABEL, CRH_PARAM_LABEL},
+};
 
-use zkcreds::proof_data_structures::{
-    PredProof as ZkcredsPredProof, PredProvingKey as ZkcredsPredPk,
-    PredVerifyingKey as ZkcredsPredVk,
+use zkcreds::{
+    link::{
+        LinkProof as ZkcredsLinkProof, LinkProofCtx as ZkcredsLinkProofCtx,
+        LinkVerifyingKey as ZkcredsLinkVk,
+    },
+    proof_data_structures::{
+        ForestProof as ZkcredsForestProof, ForestProvingKey as ZkcredsForestPk,
+        ForestVerifyingKey as ZkcredsForestVk, PredProof as ZkcredsPredProof,
+        PredProvingKey as ZkcredsPredPk, PredVerifyingKey as ZkcredsPredVk,
+        TreeProof as ZkcredsTreeProof, TreeProvingKey as ZkcredsTreePk,
+        TreeVerifyingKey as ZkcredsTreeVk,
+    },
 };
 
-use ark_bls12_381::Bls12_381;
 use ark_crypto_primitives::{
     commitment::CommitmentScheme,
-    crh::{bowe_hopwood, pedersen, TwoToOneCRH},
+    crh::{pedersen, TwoToOneCRH},
 };
 use ark_ec::PairingEngine;
-use ark_ed_on_bls12_381::{constraints::FqVar, EdwardsParameters};
-use ark_std::{
-    io::Write,
-    rand::{rngs::StdRng, SeedableRng},
-};
 use lazy_static::lazy_static;
 
 // Our passport info is Data Group 1 (DG1) of the Essential Files (EF) of Logical Data Structure 1
@@ -23,69 +29,144 @@ use lazy_static::lazy_static;
 // used is TD3 (as opposed to TD1 or TD2).
 // The lengths of the fields below are derived from or directly given in ICAO doc 9303, part 10,
 // §4.7.1.3, which can be found at https://www.icao.int/publications/Documents/9303_p10_cons_en.pdf
-pub(crate) const NAME_LEN: usize = 39;
-pub(crate) const DATE_LEN: usize = 6;
-pub(crate) const STATE_ID_LEN: usize = 3;
-pub(crate) const DOCUMENT_NUMBER_LEN: usize = 9;
-pub(crate) const DG1_LEN: usize = 93;
-pub(crate) const ISSUER_OFFSET: usize = 7;
-pub(crate) const NAME_OFFSET: usize = ISSUER_OFFSET + STATE_ID_LEN;
-pub(crate) const DOCUMENT_NUMBER_OFFSET: usize = NAME_OFFSET + NAME_LEN;
-pub(crate) const NATIONALITY_OFFSET: usize = DOCUMENT_NUMBER_OFFSET + DOCUMENT_NUMBER_LEN + 1;
-pub(crate) const DOB_OFFSET: usize = NATIONALITY_OFFSET + STATE_ID_LEN;
-pub(crate) const EXPIRY_OFFSET: usize = DOB_OFFSET + DATE_LEN + 2;
+pub const NAME_LEN: usize = 39;
+pub const DATE_LEN: usize = 6;
+pub const STATE_ID_LEN: usize = 3;
+pub const DOCUMENT_NUMBER_LEN: usize = 9;
+pub const DG1_LEN: usize = 93;
+pub const ISSUER_OFFSET: usize = 7;
+pub const NAME_OFFSET: usize = ISSUER_OFFSET + STATE_ID_LEN;
+pub const DOCUMENT_NUMBER_OFFSET: usize = NAME_OFFSET + NAME_LEN;
+pub const NATIONALITY_OFFSET: usize = DOCUMENT_NUMBER_OFFSET + DOCUMENT_NUMBER_LEN + 1;
+pub const DOB_OFFSET: usize = NATIONALITY_OFFSET + STATE_ID_LEN;
+pub const EXPIRY_OFFSET: usize = DOB_OFFSET + DATE_LEN + 2;
 
 // The following values are specific to US passports, or possibly even just my US passport.
 
 // US passports use SHA-256 for their internal hash calculations, and they also use SHA-256 for the
 // final signature (RSA-PKCS1v1.5-SHA256)
-pub(crate) const HASH_LEN: usize = 32;
-pub(crate) const SIG_HASH_LEN: usize = 32;
+pub const HASH_LEN: usize = 32;
+pub const SIG_HASH_LEN: usize = 32;
+// The longest passport signature we accept, in bytes. This is enough for a 4096-bit RSA key.
+pub const MAX_SIG_LEN: usize = 512;
 
 // These are intermediate values computed in the calculation of a passport's signature
-pub(crate) const PRE_ECONTENT_LEN: usize = 180;
-pub(crate) const ECONTENT_LEN: usize = 104;
+pub const PRE_ECONTENT_LEN: usize = 180;
+pub const ECONTENT_LEN: usize = 104;
 // The location of the DG1 hash inside pre-econtent
-pub(crate) const DG1_HASH_OFFSET: usize = 31;
+pub const DG1_HASH_OFFSET: usize = 31;
 // The location of the DG2 hash inside pre-econtent
-pub(crate) const DG2_HASH_OFFSET: usize = 70;
+pub const DG2_HASH_OFFSET: usize = 70;
 // The location of the pre-econtent hash inside econtent
-pub(crate) const PRE_ECONTENT_HASH_OFFSET: usize = 72;
+pub const PRE_ECONTENT_HASH_OFFSET: usize = 72;
+// The length of the leading part of econtent that's hashed outside the circuit. This is exactly one
+// SHA-256 block, and it ends before the pre-econtent hash, so the circuit has nothing to check in it
+pub const ECONTENT_PREFIX_LEN: usize = 64;
 
 #[derive(Clone)]
-pub(crate) struct Window9x128;
+pub struct Window9x128;
 impl pedersen::Window for Window9x128 {
     const WINDOW_SIZE: usize = 128;
     const NUM_WINDOWS: usize = 11;
 }
 
 #[derive(Clone)]
-pub(crate) struct Window9x63;
+pub struct Window9x63;
 impl pedersen::Window for Window9x63 {
     const WINDOW_SIZE: usize = 63;
     const NUM_WINDOWS: usize = 9;
 }
 
-// Pick a pairing engine and a curve defined over E::Fr
-pub(crate) type E = Bls12_381;
-pub(crate) type Fr = <E as PairingEngine>::Fr;
+// Pick a pairing engine and a curve defined over E::Fr. By default, these are BLS12-381 and
+// Jubjub. With the `bn254` feature, they're BN254 and Baby Jubjub, so that proofs can be checked by
+// the EVM's pairing precompiles.
+#[cfg(not(feature = "bn254"))]
+pub type E = ark_bls12_381::Bls12_381;
+#[cfg(not(feature = "bn254"))]
+pub type EmbeddedCurve = ark_ed_on_bls12_381::EdwardsParameters;
+#[cfg(not(feature = "bn254"))]
+pub type EmbeddedFqVar = ark_ed_on_bls12_381::constraints::FqVar;
+
+#[cfg(feature = "bn254")]
+pub type E = ark_bn254::Bn254;
+#[cfg(feature = "bn254")]
+pub type EmbeddedCurve = ark_ed_on_bn254::EdwardsParameters;
+#[cfg(feature = "bn254")]
+pub type EmbeddedFqVar = ark_ed_on_bn254::constraints::FqVar;
+
+pub type Fr = <E as PairingEngine>::Fr;
+
+// Pick a two-to-one CRH and a commitment scheme. By default, these are Pedersen-based. With the
+// `poseidon` feature, both are Poseidon, which is much cheaper in-circuit. Credentials, trees, and
+// keys made with one choice are incompatible with the other.
+#[cfg(not(feature = "poseidon"))]
+pub type H = ark_crypto_primitives::crh::bowe_hopwood::CRH<EmbeddedCurve, Window9x63>;
+#[cfg(not(feature = "poseidon"))]
+pub type HG =
+    ark_crypto_primitives::crh::bowe_hopwood::constraints::CRHGadget<EmbeddedCurve, EmbeddedFqVar>;
+#[cfg(not(feature = "poseidon"))]
+pub type PassportComScheme = zkcreds::compressed_pedersen::Commitment<EmbeddedCurve, Window9x128>;
+#[cfg(not(feature = "poseidon"))]
+pub type PassportComSchemeG = zkcreds::compressed_pedersen::constraints::CommGadget<
+    EmbeddedCurve,
+    EmbeddedFqVar,
+    Window9x128,
+>;
 
-// Pick a two-to-one CRH
-pub(crate) type H = bowe_hopwood::CRH<EdwardsParameters, Window9x63>;
-pub(crate) type HG = bowe_hopwood::constraints::CRHGadget<EdwardsParameters, FqVar>;
+#[cfg(feature = "poseidon")]
+pub type H = crate::poseidon::PoseidonTwoToOneCrh;
+#[cfg(feature = "poseidon")]
+pub type HG = crate::poseidon::PoseidonTwoToOneCrhGadget;
+#[cfg(feature = "poseidon")]
+pub type PassportComScheme = crate::poseidon::PoseidonCom;
+#[cfg(feature = "poseidon")]
+pub type PassportComSchemeG = crate::poseidon::PoseidonComGadget;
 
-// Pick a commitment scheme
-pub(crate) type PassportComScheme =
-    zkcreds::compressed_pedersen::Commitment<EdwardsParameters, Window9x128>;
-pub(crate) type PassportComSchemeG =
-    zkcreds::compressed_pedersen::constraints::CommGadget<EdwardsParameters, FqVar, Window9x128>;
+// Identifies which of the combinations of curve and hash above this build uses. Objects made with
+// one are useless with another, so every file we write records it.
+pub const PARAM_SET_ID: u8 = 2 * cfg!(feature = "bn254") as u8 + cfg!(feature = "poseidon") as u8;
+pub const PARAM_SET_NAMES: [&str; 4] = [
+    "bls12-381/pedersen",
+    "bls12-381/poseidon",
+    "bn254/pedersen",
+    "bn254/poseidon",
+];
 
-pub(crate) type ComTree = zkcreds::com_tree::ComTree<Fr, H, PassportComScheme>;
-pub(crate) type ComTreeWireFormat = zkcreds::com_tree::ComTreeWireFormat<Fr, H, PassportComScheme>;
+pub type ComTree = zkcreds::com_tree::ComTree<Fr, H, PassportComScheme>;
+pub type ComTreeWireFormat = zkcreds::com_tree::ComTreeWireFormat<Fr, H, PassportComScheme>;
+pub type ComTreePath = zkcreds::com_tree::ComTreePath<Fr, H, PassportComScheme>;
+pub type ComTreeRoot = <H as TwoToOneCRH>::Output;
+pub type ComForestRoots = zkcreds::com_forest::ComForestRoots<Fr, H>;
 
 /// Type aliases for Groth16 stuff
-pub(crate) type PredProof = ZkcredsPredProof<
-    Bls12_381,
+pub type PredProof = ZkcredsPredProof<
+    E,
+    PersonalInfo,
+    PersonalInfoVar,
+    PassportComScheme,
+    PassportComSchemeG,
+    H,
+    HG,
+>;
+pub type PredProvingKey =
+    ZkcredsPredPk<E, PersonalInfo, PersonalInfoVar, PassportComScheme, PassportComSchemeG, H, HG>;
+pub type PredVerifyingKey =
+    ZkcredsPredVk<E, PersonalInfo, PersonalInfoVar, PassportComScheme, PassportComSchemeG, H, HG>;
+pub type TreeProvingKey =
+    ZkcredsTreePk<E, PersonalInfo, PersonalInfoVar, PassportComScheme, PassportComSchemeG, H, HG>;
+pub type TreeVerifyingKey =
+    ZkcredsTreeVk<E, PersonalInfo, PersonalInfoVar, PassportComScheme, PassportComSchemeG, H, HG>;
+pub type TreeProof = ZkcredsTreeProof<
+    E,
+    PersonalInfo,
+    PersonalInfoVar,
+    PassportComScheme,
+    PassportComSchemeG,
+    H,
+    HG,
+>;
+pub type ForestProof = ZkcredsForestProof<
+    E,
     PersonalInfo,
     PersonalInfoVar,
     PassportComScheme,
@@ -93,8 +174,12 @@ pub(crate) type PredProof = ZkcredsPredProof<
     H,
     HG,
 >;
-pub(crate) type PredProvingKey = ZkcredsPredPk<
-    Bls12_381,
+pub type ForestProvingKey =
+    ZkcredsForestPk<E, PersonalInfo, PersonalInfoVar, PassportComScheme, PassportComSchemeG, H, HG>;
+pub type ForestVerifyingKey =
+    ZkcredsForestVk<E, PersonalInfo, PersonalInfoVar, PassportComScheme, PassportComSchemeG, H, HG>;
+pub type LinkProof = ZkcredsLinkProof<
+    E,
     PersonalInfo,
     PersonalInfoVar,
     PassportComScheme,
@@ -102,8 +187,8 @@ pub(crate) type PredProvingKey = ZkcredsPredPk<
     H,
     HG,
 >;
-pub(crate) type PredVerifyingKey = ZkcredsPredVk<
-    Bls12_381,
+pub type LinkProofCtx = ZkcredsLinkProofCtx<
+    E,
     PersonalInfo,
     PersonalInfoVar,
     PassportComScheme,
@@ -111,25 +196,13 @@ pub(crate) type PredVerifyingKey = ZkcredsPredVk<
     H,
     HG,
 >;
+pub type LinkVerifyingKey =
+    ZkcredsLinkVk<E, PersonalInfo, PersonalInfoVar, PassportComScheme, PassportComSchemeG, H, HG>;
 
-// Set params
+// Set params. These are derived from fixed labels; see `public_params` for how.
 lazy_static! {
-    pub(crate) static ref PASSPORT_COM_PARAM: <PassportComScheme as CommitmentScheme>::Parameters = {
-        let mut rng = {
-            let mut seed = [0u8; 32];
-            let mut writer = &mut seed[..];
-            writer.write_all(b"zkcreds-commitment-param").unwrap();
-            StdRng::from_seed(seed)
-        };
-        PassportComScheme::setup(&mut rng).unwrap()
-    };
-    pub(crate) static ref MERKLE_CRH_PARAM: <H as TwoToOneCRH>::Parameters = {
-        let mut rng = {
-            let mut seed = [0u8; 32];
-            let mut writer = &mut seed[..];
-            writer.write_all(b"zkcreds-merkle-param").unwrap();
-            StdRng::from_seed(seed)
-        };
-        <H as TwoToOneCRH>::setup(&mut rng).unwrap()
-    };
+    pub static ref PASSPORT_COM_PARAM: <PassportComScheme as CommitmentScheme>::Parameters =
+        gen_com_param(COM_PARAM_LABEL);
+    pub static ref MERKLE_CRH_PARAM: <H as TwoToOneCRH>::Parameters =
+        gen_crh_param(CRH_PARAM_LABEL);
 }
@@ -0,0 +1,127 @@
+use crate::{
+    error::Error,
+    params::{DG1_HASH_OFFSET, DG2_HASH_OFFSET, HASH_LEN, PRE_ECONTENT_HASH_OFFSET},
+    passport_dump::PassportDump,
+    sig_verif::IssuerPubkey,
+};
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+/// The steps of passive authentication, i.e., checking that a passport's contents are signed by
+/// its issuer. These are the same checks the issuance circuit and the issuer make, done natively.
+/// See ICAO doc 9303, part 11, §5.1.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PassiveAuthStep {
+    /// DG1, pre-econtent, and econtent have the lengths we expect
+    Lengths,
+    /// The hash of DG1 appears in pre-econtent
+    Dg1Hash,
+    /// The hash of DG2 appears in pre-econtent
+    Dg2Hash,
+    /// The hash of pre-econtent appears in the messageDigest attribute of econtent
+    MessageDigest,
+    /// The signature over the hash of econtent verifies under the issuer's pubkey
+    Signature,
+}
+
+impl PassiveAuthStep {
+    /// All the steps, in the order they're checked
+    pub const ALL: [PassiveAuthStep; 5] = [
+        PassiveAuthStep::Lengths,
+        PassiveAuthStep::Dg1Hash,
+        PassiveAuthStep::Dg2Hash,
+        PassiveAuthStep::MessageDigest,
+        PassiveAuthStep::Signature,
+    ];
+}
+
+impl std::fmt::Display for PassiveAuthStep {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            PassiveAuthStep::Lengths => "lengths",
+            PassiveAuthStep::Dg1Hash => "DG1 hash",
+            PassiveAuthStep::Dg2Hash => "DG2 hash",
+            PassiveAuthStep::MessageDigest => "messageDigest",
+            PassiveAuthStep::Signature => "signature",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// Runs a single step of passive authentication. The steps that look for a hash return a `Parse`
+/// error if the dump is too short to hold it, so it's best to check `Lengths` first.
+pub fn check_step(
+    dump: &PassportDump,
+    pubkey: &IssuerPubkey,
+    step: PassiveAuthStep,
+) -> Result<(), Error> {
+    // Returns an error if the hash of `data` doesn't appear at `offset` in `container`
+    let check_hash_at = |data: &[u8], container: &[u8], offset: usize| {
+        let hash = Sha256::digest(data);
+        let found = container.get(offset..offset + HASH_LEN).ok_or_else(|| {
+            Error::Parse(format!(
+                "{} check needs {} bytes, but the container is only {} bytes",
+                step,
+                offset + HASH_LEN,
+                container.len()
+            ))
+        })?;
+        if *found == hash[..] {
+            Ok(())
+        } else {
+            Err(Error::PassiveAuthFailure(
+                step,
+                format!("hash {:x} not found at offset {}", hash, offset),
+            ))
+        }
+    };
+
+    match step {
+        PassiveAuthStep::Lengths => dump
+            .check_lengths()
+            .map_err(|e| Error::PassiveAuthFailure(step, e.to_string())),
+        PassiveAuthStep::Dg1Hash => check_hash_at(&dump.dg1, &dump.pre_econtent, DG1_HASH_OFFSET),
+        PassiveAuthStep::Dg2Hash => check_hash_at(&dump.dg2, &dump.pre_econtent, DG2_HASH_OFFSET),
+        PassiveAuthStep::MessageDigest => {
+            check_hash_at(&dump.pre_econtent, &dump.econtent, PRE_ECONTENT_HASH_OFFSET)
+        }
+        PassiveAuthStep::Signature => {
+            if pubkey.verify(&dump.sig, &dump.econtent_hash()) {
+                Ok(())
+            } else {
+                Err(Error::SignatureFailure)
+            }
+        }
+    }
+}
+
+/// Runs every step of passive authentication and returns the outcome of each. If the lengths are
+/// wrong, the remaining steps are skipped and do not appear in the output.
+pub fn check_all_steps(
+    dump: &PassportDump,
+    pubkey: &IssuerPubkey,
+) -> Vec<(PassiveAuthStep, Result<(), Error>)> {
+    let mut results = Vec::new();
+    for step in PassiveAuthStep::ALL {
+        let res = check_step(dump, pubkey, step);
+        let lengths_failed = step == PassiveAuthStep::Lengths && res.is_err();
+        results.push((step, res));
+
+        // Every other step assumes the lengths are right, so its failure wouldn't tell us anything
+        if lengths_failed {
+            break;
+        }
+    }
+
+    results
+}
+
+/// Checks that the passport's contents are correctly signed by the holder of `pubkey`. On
+/// failure, returns an error saying which step failed.
+pub fn passive_auth(dump: &PassportDump, pubkey: &IssuerPubkey) -> Result<(), Error> {
+    PassiveAuthStep::ALL
+        .iter()
+        .try_for_each(|&step| check_step(dump, pubkey, step))
+}
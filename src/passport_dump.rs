@@ -1,32 +1,102 @@
-use crate::params::HASH_LEN;
+use crate::{
+    error::Error,
+    params::{
+        DATE_LEN, DG1_LEN, DOB_OFFSET, DOCUMENT_NUMBER_LEN, DOCUMENT_NUMBER_OFFSET, ECONTENT_LEN,
+        EXPIRY_OFFSET, HASH_LEN, ISSUER_OFFSET, NAME_LEN, NAME_OFFSET, NATIONALITY_OFFSET,
+        PRE_ECONTENT_LEN, STATE_ID_LEN,
+    },
+};
 
-use serde::{de::Error as SError, Deserialize, Deserializer};
+use serde::{de::Error as SError, Deserialize, Deserializer, Serialize, Serializer};
 use sha2::{Digest, Sha256};
 
-#[derive(Default, Deserialize)]
+#[derive(Default, Deserialize, Serialize)]
 pub struct PassportDump {
-    #[serde(deserialize_with = "bytes_from_b64")]
-    pub(crate) dg1: Vec<u8>,
-    #[serde(deserialize_with = "bytes_from_b64")]
-    pub(crate) dg2: Vec<u8>,
-    #[serde(rename = "pre-econtent", deserialize_with = "bytes_from_b64")]
-    pub(crate) pre_econtent: Vec<u8>,
-    #[serde(deserialize_with = "bytes_from_b64")]
-    pub(crate) econtent: Vec<u8>,
-    #[serde(deserialize_with = "bytes_from_b64")]
-    pub(crate) sig: Vec<u8>,
-    #[serde(rename = "cert", deserialize_with = "bytes_from_b64")]
-    pub(crate) _cert: Vec<u8>,
+    #[serde(serialize_with = "bytes_to_b64", deserialize_with = "bytes_from_b64")]
+    pub dg1: Vec<u8>,
+    #[serde(serialize_with = "bytes_to_b64", deserialize_with = "bytes_from_b64")]
+    pub dg2: Vec<u8>,
+    #[serde(
+        rename = "pre-econtent",
+        serialize_with = "bytes_to_b64",
+        deserialize_with = "bytes_from_b64"
+    )]
+    pub pre_econtent: Vec<u8>,
+    #[serde(serialize_with = "bytes_to_b64", deserialize_with = "bytes_from_b64")]
+    pub econtent: Vec<u8>,
+    #[serde(serialize_with = "bytes_to_b64", deserialize_with = "bytes_from_b64")]
+    pub sig: Vec<u8>,
+    /// The DER-encoded Document Signer certificate
+    #[serde(serialize_with = "bytes_to_b64", deserialize_with = "bytes_from_b64")]
+    pub cert: Vec<u8>,
     #[serde(rename = "digest-alg")]
-    pub(crate) _digest_alg: String,
+    pub digest_alg: String,
     #[serde(rename = "sig-alg")]
-    pub(crate) _sig_alg: String,
+    pub sig_alg: String,
 }
 
 impl PassportDump {
-    pub(crate) fn econtent_hash(&self) -> [u8; HASH_LEN] {
+    /// Returns the bytes of DG1 at the given offset and length. This is empty if DG1 is too short.
+    fn dg1_field(&self, offset: usize, len: usize) -> &[u8] {
+        self.dg1.get(offset..offset + len).unwrap_or(&[])
+    }
+
+    /// The 3-letter code of the issuing state
+    pub fn issuer(&self) -> &[u8] {
+        self.dg1_field(ISSUER_OFFSET, STATE_ID_LEN)
+    }
+
+    /// The holder's name, padded with `<`
+    pub fn name(&self) -> &[u8] {
+        self.dg1_field(NAME_OFFSET, NAME_LEN)
+    }
+
+    pub fn document_number(&self) -> &[u8] {
+        self.dg1_field(DOCUMENT_NUMBER_OFFSET, DOCUMENT_NUMBER_LEN)
+    }
+
+    /// The 3-letter code of the holder's nationality
+    pub fn nationality(&self) -> &[u8] {
+        self.dg1_field(NATIONALITY_OFFSET, STATE_ID_LEN)
+    }
+
+    /// The holder's date of birth, in the form YYMMDD
+    pub fn dob(&self) -> &[u8] {
+        self.dg1_field(DOB_OFFSET, DATE_LEN)
+    }
+
+    /// The passport's expiry date, in the form YYMMDD
+    pub fn expiry(&self) -> &[u8] {
+        self.dg1_field(EXPIRY_OFFSET, DATE_LEN)
+    }
+
+    pub fn econtent_hash(&self) -> [u8; HASH_LEN] {
         Sha256::digest(&self.econtent).into()
     }
+
+    pub fn dg2_hash(&self) -> [u8; HASH_LEN] {
+        Sha256::digest(&self.dg2).into()
+    }
+
+    /// Checks that the fixed-length fields of this dump have the lengths we expect. Everything
+    /// that slices into a dump assumes this has been checked.
+    pub fn check_lengths(&self) -> Result<(), Error> {
+        let fields = [
+            ("DG1", self.dg1.len(), DG1_LEN),
+            ("pre-econtent", self.pre_econtent.len(), PRE_ECONTENT_LEN),
+            ("econtent", self.econtent.len(), ECONTENT_LEN),
+        ];
+        for (name, len, expected_len) in fields {
+            if len != expected_len {
+                return Err(Error::Parse(format!(
+                    "{} is {} bytes long, expected {}",
+                    name, len, expected_len
+                )));
+            }
+        }
+
+        Ok(())
+    }
 }
 
 // Tells serde how to deserialize bytes from base64
@@ -38,41 +108,28 @@ where
     base64::decode(b64_str.as_bytes()).map_err(|e| SError::custom(format!("{:?}", e)))
 }
 
+// Tells serde how to serialize bytes to base64
+fn bytes_to_b64<S>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.serialize_str(&base64::encode(bytes))
+}
+
 /// Prints all the information stored in a passport's machine-readable zone (MRZ), plus the hash of
 /// the biometrics
 impl std::fmt::Debug for PassportDump {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
-        use crate::params::*;
-
         f.debug_struct("PassportDump")
-            .field(
-                "issuer",
-                &String::from_utf8_lossy(&self.dg1[ISSUER_OFFSET..ISSUER_OFFSET + STATE_ID_LEN]),
-            )
-            .field(
-                "name",
-                &String::from_utf8_lossy(&self.dg1[NAME_OFFSET..NAME_OFFSET + NAME_LEN]),
-            )
+            .field("issuer", &String::from_utf8_lossy(self.issuer()))
+            .field("name", &String::from_utf8_lossy(self.name()))
             .field(
                 "document number",
-                &String::from_utf8_lossy(
-                    &self.dg1[DOCUMENT_NUMBER_OFFSET..DOCUMENT_NUMBER_OFFSET + DOCUMENT_NUMBER_LEN],
-                ),
-            )
-            .field(
-                "nationality",
-                &String::from_utf8_lossy(
-                    &self.dg1[NATIONALITY_OFFSET..NATIONALITY_OFFSET + STATE_ID_LEN],
-                ),
-            )
-            .field(
-                "date of birth",
-                &String::from_utf8_lossy(&self.dg1[DOB_OFFSET..DOB_OFFSET + DATE_LEN]),
-            )
-            .field(
-                "expiry",
-                &String::from_utf8_lossy(&self.dg1[EXPIRY_OFFSET..EXPIRY_OFFSET + DATE_LEN]),
+                &String::from_utf8_lossy(self.document_number()),
             )
+            .field("nationality", &String::from_utf8_lossy(self.nationality()))
+            .field("date of birth", &String::from_utf8_lossy(self.dob()))
+            .field("expiry", &String::from_utf8_lossy(self.expiry()))
             .field(
                 "biometrics hash",
                 &format_args!("{:x}", Sha256::digest(&self.dg2)),
@@ -1,4 +1,5 @@
 use crate::{
+    error::Error,
     params::{
         Fr, PassportComScheme, PassportComSchemeG, DATE_LEN, DOB_OFFSET, EXPIRY_OFFSET, HASH_LEN,
         NAME_LEN, NAME_OFFSET, NATIONALITY_OFFSET, PASSPORT_COM_PARAM, STATE_ID_LEN,
@@ -26,11 +27,17 @@ use ark_relations::{
     ns,
     r1cs::{Namespace, SynthesisError},
 };
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize, Read, SerializationError, Write};
 use ark_std::rand::Rng;
 
+#[cfg(not(feature = "poseidon"))]
+use crate::params::EmbeddedCurve;
+#[cfg(not(feature = "poseidon"))]
+use ark_ec::models::ModelParameters;
+
 /// Simple blob containing user's biometrics
 #[derive(Clone, Default)]
-pub(crate) struct Biometrics(Vec<u8>);
+pub struct Biometrics(Vec<u8>);
 
 impl Biometrics {
     pub fn hash(&self) -> [u8; HASH_LEN] {
@@ -40,14 +47,14 @@ impl Biometrics {
 
 /// Stores a subset of the info found in data groups 1 and 2 of a passport
 #[derive(Clone)]
-pub(crate) struct PersonalInfo {
+pub struct PersonalInfo {
     nonce: ComNonce<PassportComScheme>,
-    pub(crate) seed: Fr,
-    pub(crate) nationality: [u8; STATE_ID_LEN],
-    pub(crate) name: [u8; NAME_LEN],
-    pub(crate) dob: u32,
-    pub(crate) passport_expiry: u32,
-    pub(crate) biometrics: Biometrics,
+    pub seed: Fr,
+    pub nationality: [u8; STATE_ID_LEN],
+    pub name: [u8; NAME_LEN],
+    pub dob: u32,
+    pub passport_expiry: u32,
+    pub biometrics: Biometrics,
 }
 
 impl Default for PersonalInfo {
@@ -64,24 +71,99 @@ impl Default for PersonalInfo {
     }
 }
 
+// zkcreds doesn't make commitment nonces serializable, but the nonce of either of our commitment
+// schemes is a single scalar. These convert between the two.
+#[cfg(not(feature = "poseidon"))]
+type NonceRepr = <EmbeddedCurve as ModelParameters>::ScalarField;
+#[cfg(feature = "poseidon")]
+type NonceRepr = Fr;
+
+#[cfg(not(feature = "poseidon"))]
+fn nonce_to_repr(nonce: &ComNonce<PassportComScheme>) -> NonceRepr {
+    nonce.0
+}
+#[cfg(not(feature = "poseidon"))]
+fn nonce_from_repr(repr: NonceRepr) -> ComNonce<PassportComScheme> {
+    ark_crypto_primitives::commitment::pedersen::Randomness(repr)
+}
+
+#[cfg(feature = "poseidon")]
+fn nonce_to_repr(nonce: &ComNonce<PassportComScheme>) -> NonceRepr {
+    *nonce
+}
+#[cfg(feature = "poseidon")]
+fn nonce_from_repr(repr: NonceRepr) -> ComNonce<PassportComScheme> {
+    repr
+}
+
+// The serialization is the opening of the holder's credential, so it's only ever read back by the
+// holder. Fixed-size fields are written as is.
+impl CanonicalSerialize for PersonalInfo {
+    fn serialize<W: Write>(&self, mut writer: W) -> Result<(), SerializationError> {
+        nonce_to_repr(&self.nonce).serialize(&mut writer)?;
+        self.seed.serialize(&mut writer)?;
+        writer.write_all(&self.nationality)?;
+        writer.write_all(&self.name)?;
+        self.dob.serialize(&mut writer)?;
+        self.passport_expiry.serialize(&mut writer)?;
+        self.biometrics.0.serialize(&mut writer)
+    }
+
+    fn serialized_size(&self) -> usize {
+        nonce_to_repr(&self.nonce).serialized_size()
+            + self.seed.serialized_size()
+            + STATE_ID_LEN
+            + NAME_LEN
+            + self.dob.serialized_size()
+            + self.passport_expiry.serialized_size()
+            + self.biometrics.0.serialized_size()
+    }
+}
+
+impl CanonicalDeserialize for PersonalInfo {
+    fn deserialize<R: Read>(mut reader: R) -> Result<Self, SerializationError> {
+        let nonce = nonce_from_repr(NonceRepr::deserialize(&mut reader)?);
+        let seed = Fr::deserialize(&mut reader)?;
+        let mut nationality = [0u8; STATE_ID_LEN];
+        reader.read_exact(&mut nationality)?;
+        let mut name = [0u8; NAME_LEN];
+        reader.read_exact(&mut name)?;
+
+        Ok(PersonalInfo {
+            nonce,
+            seed,
+            nationality,
+            name,
+            dob: u32::deserialize(&mut reader)?,
+            passport_expiry: u32::deserialize(&mut reader)?,
+            biometrics: Biometrics(Vec::deserialize(&mut reader)?),
+        })
+    }
+}
+
 /// Stores a subset of the info found in data groups 1 and 2 of a passport
 #[derive(Clone)]
-pub(crate) struct PersonalInfoVar {
+pub struct PersonalInfoVar {
     nonce: ComNonceVar<PassportComScheme, PassportComSchemeG, Fr>,
-    pub(crate) seed: FpVar<Fr>,
-    pub(crate) nationality: Bytestring<Fr>,
-    pub(crate) name: Bytestring<Fr>,
-    pub(crate) dob: FpVar<Fr>,
-    pub(crate) passport_expiry: FpVar<Fr>,
-    pub(crate) biometric_hash: Bytestring<Fr>,
+    pub seed: FpVar<Fr>,
+    pub nationality: Bytestring<Fr>,
+    pub name: Bytestring<Fr>,
+    pub dob: FpVar<Fr>,
+    pub passport_expiry: FpVar<Fr>,
+    pub biometric_hash: Bytestring<Fr>,
 }
 
 /// Converts a date string of the form YYMMDD to a u32 whose base-10 representation is YYYYMMDD.
 /// `not_after` is the soonest day in the 21st century after which the input would not make sense,
 /// e.g., a birthdate wouldn't make sense if it were after today, and a document expiry date
 /// wouldn't be 20 years in the future.
-fn date_to_u32(date: &[u8], not_after: u32) -> u32 {
-    assert_eq!(date.len(), DATE_LEN);
+pub fn date_to_u32(date: &[u8], not_after: u32) -> Result<u32, Error> {
+    if date.len() != DATE_LEN || !date.iter().all(u8::is_ascii_digit) {
+        return Err(Error::Parse(format!(
+            "invalid date {:?}, expected YYMMDD",
+            String::from_utf8_lossy(date)
+        )));
+    }
 
     let century = 1000000;
     let twenty_first_century = 20 * century;
@@ -107,7 +189,7 @@ fn date_to_u32(date: &[u8], not_after: u32) -> u32 {
         d -= century;
     }
 
-    d
+    Ok(d)
 }
 
 impl PersonalInfo {
@@ -119,7 +201,9 @@ impl PersonalInfo {
         dump: &PassportDump,
         today: u32,
         max_valid_years: u32,
-    ) -> PersonalInfo {
+    ) -> Result<PersonalInfo, Error> {
+        dump.check_lengths()?;
+
         // Create an empty info struct that we'll fill with data
         let mut info = PersonalInfo {
             nonce: ComNonce::<PassportComScheme>::rand(rng),
@@ -137,14 +221,14 @@ impl PersonalInfo {
             .copy_from_slice(&dump.dg1[NATIONALITY_OFFSET..NATIONALITY_OFFSET + STATE_ID_LEN]);
         info.name
             .copy_from_slice(&dump.dg1[NAME_OFFSET..NAME_OFFSET + NAME_LEN]);
-        info.dob = date_to_u32(&dump.dg1[DOB_OFFSET..DOB_OFFSET + DATE_LEN], today);
+        info.dob = date_to_u32(&dump.dg1[DOB_OFFSET..DOB_OFFSET + DATE_LEN], today)?;
         info.passport_expiry = date_to_u32(
             &dump.dg1[EXPIRY_OFFSET..EXPIRY_OFFSET + DATE_LEN],
             expiry_not_after,
-        );
+        )?;
         info.biometrics.0 = dump.dg2.clone();
 
-        info
+        Ok(info)
     }
 }
 
@@ -0,0 +1,199 @@
+//! Poseidon-based replacements for the Pedersen commitment and the Bowe-Hopwood tree hash. These
+//! are used instead of the defaults when the `poseidon` feature is enabled. Both hash field
+//! elements natively, so they cost a few hundred constraints per call rather than thousands.
+
+use crate::params::Fr;
+
+use arkworks_native_gadgets::poseidon::{
+    sbox::PoseidonSbox, FieldHasher, Poseidon, PoseidonParameters,
+};
+use arkworks_r1cs_gadgets::poseidon::{FieldHasherGadget, PoseidonGadget};
+use arkworks_utils::{
+    bytes_matrix_to_f, bytes_vec_to_f, poseidon_params::setup_poseidon_params, Curve,
+};
+
+use core::borrow::Borrow;
+
+use ark_crypto_primitives::{
+    commitment::{CommitmentGadget, CommitmentScheme},
+    crh::{TwoToOneCRH, TwoToOneCRHGadget},
+    Error as CryptoError,
+};
+use ark_ff::ToConstraintField;
+use ark_r1cs_std::{
+    alloc::{AllocVar, AllocationMode},
+    fields::fp::FpVar,
+    uint8::UInt8,
+    ToConstraintFieldGadget,
+};
+use ark_relations::r1cs::{Namespace, SynthesisError};
+use ark_std::rand::Rng;
+
+// The S-box exponent and state width of our Poseidon instance. A width of 5 means each
+// permutation absorbs 4 field elements.
+const POSEIDON_EXP: i8 = 5;
+const POSEIDON_WIDTH: u8 = 5;
+const POSEIDON_RATE: usize = POSEIDON_WIDTH as usize - 1;
+
+// The curve whose scalar field the parameters are for. This must match params::E.
+#[cfg(not(feature = "bn254"))]
+const POSEIDON_CURVE: Curve = Curve::Bls381;
+#[cfg(feature = "bn254")]
+const POSEIDON_CURVE: Curve = Curve::Bn254;
+
+/// Returns the standard Poseidon parameters for the scalar field of `params::E`, with x^5 S-boxes
+/// and width 5
+pub fn poseidon_params() -> PoseidonParameters<Fr> {
+    let data = setup_poseidon_params(POSEIDON_CURVE, POSEIDON_EXP, POSEIDON_WIDTH).unwrap();
+    PoseidonParameters {
+        mds_matrix: bytes_matrix_to_f(&data.mds),
+        round_keys: bytes_vec_to_f(&data.rounds),
+        full_rounds: data.full_rounds,
+        partial_rounds: data.partial_rounds,
+        sbox: PoseidonSbox(data.exp),
+        width: data.width,
+    }
+}
+
+/// Packs bytes into field elements, 31 bytes at a time. The in-circuit packing via
+/// `ToConstraintFieldGadget` is identical.
+fn pack_bytes(bytes: &[u8]) -> Vec<Fr> {
+    bytes.to_field_elements().unwrap()
+}
+
+/// Hashes an arbitrary number of field elements by chaining permutations. Each permutation
+/// absorbs the previous output and the next `POSEIDON_RATE - 1` inputs.
+fn chained_hash(hasher: &Poseidon<Fr>, init: Fr, inputs: &[Fr]) -> Result<Fr, CryptoError> {
+    inputs
+        .chunks(POSEIDON_RATE - 1)
+        .try_fold(init, |acc, chunk| {
+            let block = [&[acc][..], chunk].concat();
+            hasher.hash(&block).map_err(|e| Box::new(e) as CryptoError)
+        })
+}
+
+// The in-circuit analogue of chained_hash
+fn chained_hash_gadget(
+    hasher: &PoseidonGadget<Fr>,
+    init: FpVar<Fr>,
+    inputs: &[FpVar<Fr>],
+) -> Result<FpVar<Fr>, SynthesisError> {
+    inputs
+        .chunks(POSEIDON_RATE - 1)
+        .try_fold(init, |acc, chunk| {
+            let block = [&[acc][..], chunk].concat();
+            hasher.hash(&block)
+        })
+}
+
+/// A commitment to bytes, computed as a chained Poseidon hash of the randomness, the number of
+/// bytes, and the packed bytes. The length comes first so that no input, not even the empty one,
+/// commits to the randomness alone, and so that inputs that differ only in trailing zero bytes,
+/// which pack to the same field elements, get different commitments.
+pub struct PoseidonCom;
+
+impl CommitmentScheme for PoseidonCom {
+    type Output = Fr;
+    type Parameters = PoseidonParameters<Fr>;
+    type Randomness = Fr;
+
+    // The parameters are fixed, so there's nothing random to set up
+    fn setup<R: Rng>(_: &mut R) -> Result<Self::Parameters, CryptoError> {
+        Ok(poseidon_params())
+    }
+
+    fn commit(
+        parameters: &Self::Parameters,
+        input: &[u8],
+        r: &Self::Randomness,
+    ) -> Result<Self::Output, CryptoError> {
+        let hasher = Poseidon::new(parameters.clone());
+        let inputs = [vec![Fr::from(input.len() as u64)], pack_bytes(input)].concat();
+        chained_hash(&hasher, *r, &inputs)
+    }
+}
+
+/// A two-to-one hash for the credential tree. Each side is packed into field elements and the
+/// result is a chained Poseidon hash of the left side followed by the right.
+pub struct PoseidonTwoToOneCrh;
+
+impl TwoToOneCRH for PoseidonTwoToOneCrh {
+    // Inputs are serialized field elements
+    const LEFT_INPUT_SIZE_BITS: usize = 256;
+    const RIGHT_INPUT_SIZE_BITS: usize = 256;
+
+    type Output = Fr;
+    type Parameters = PoseidonParameters<Fr>;
+
+    fn setup<R: Rng>(_: &mut R) -> Result<Self::Parameters, CryptoError> {
+        Ok(poseidon_params())
+    }
+
+    fn evaluate(
+        parameters: &Self::Parameters,
+        left_input: &[u8],
+        right_input: &[u8],
+    ) -> Result<Self::Output, CryptoError> {
+        let hasher = Poseidon::new(parameters.clone());
+        let inputs = [pack_bytes(left_input), pack_bytes(right_input)].concat();
+        chained_hash(&hasher, Fr::from(0u8), &inputs)
+    }
+}
+
+/// The Poseidon parameters as circuit constants
+#[derive(Clone)]
+pub struct PoseidonParamsVar(PoseidonGadget<Fr>);
+
+impl AllocVar<PoseidonParameters<Fr>, Fr> for PoseidonParamsVar {
+    // The parameters are public and fixed, so they're always constants, regardless of `mode`
+    fn new_variable<T: Borrow<PoseidonParameters<Fr>>>(
+        cs: impl Into<Namespace<Fr>>,
+        f: impl FnOnce() -> Result<T, SynthesisError>,
+        _mode: AllocationMode,
+    ) -> Result<Self, SynthesisError> {
+        let mut cs = cs.into().cs();
+        let params = f()?.borrow().clone();
+        PoseidonGadget::from_native(&mut cs, Poseidon::new(params)).map(PoseidonParamsVar)
+    }
+}
+
+/// The in-circuit version of `PoseidonCom`
+pub struct PoseidonComGadget;
+
+impl CommitmentGadget<PoseidonCom, Fr> for PoseidonComGadget {
+    type OutputVar = FpVar<Fr>;
+    type ParametersVar = PoseidonParamsVar;
+    type RandomnessVar = FpVar<Fr>;
+
+    fn commit(
+        parameters: &Self::ParametersVar,
+        input: &[UInt8<Fr>],
+        r: &Self::RandomnessVar,
+    ) -> Result<Self::OutputVar, SynthesisError> {
+        // The length is fixed by the circuit's shape, so it's a constant
+        let len = FpVar::Constant(Fr::from(input.len() as u64));
+        let inputs = [vec![len], input.to_constraint_field()?].concat();
+        chained_hash_gadget(&parameters.0, r.clone(), &inputs)
+    }
+}
+
+/// The in-circuit version of `PoseidonTwoToOneCrh`
+pub struct PoseidonTwoToOneCrhGadget;
+
+impl TwoToOneCRHGadget<PoseidonTwoToOneCrh, Fr> for PoseidonTwoToOneCrhGadget {
+    type OutputVar = FpVar<Fr>;
+    type ParametersVar = PoseidonParamsVar;
+
+    fn evaluate(
+        parameters: &Self::ParametersVar,
+        left_input: &[UInt8<Fr>],
+        right_input: &[UInt8<Fr>],
+    ) -> Result<Self::OutputVar, SynthesisError> {
+        let inputs = [
+            left_input.to_constraint_field()?,
+            right_input.to_constraint_field()?,
+        ]
+        .concat();
+        chained_hash_gadget(&parameters.0, FpVar::Constant(Fr::from(0u8)), &inputs)
+    }
+}
@@ -0,0 +1,341 @@
+//! Phase 1 of the CRS ceremony: a multi-party computation of the powers of a secret τ, in the style
+//! of Bowe, Gabizon, and Miers. The powers start out from τ = α = β = 1, and every participant then
+//! multiplies τ, α, and β by secrets of their own, publishing proofs that they know them. The
+//! result doesn't depend on any circuit. `mpc::crs_init` derives a circuit's CRS from it, and phase
+//! 2 then re-randomizes δ. As long as one participant in each phase discards their secrets, nobody
+//! knows the toxic waste.
+
+use crate::{
+    error::Error,
+    mpc::{hash_to_g2, same_ratio},
+    params::{Fr, E},
+};
+
+use ark_ec::{msm::VariableBaseMSM, AffineCurve, PairingEngine, ProjectiveCurve};
+use ark_ff::{FftParameters, One, PrimeField, UniformRand, Zero};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize, Read, SerializationError, Write};
+use ark_std::rand::{CryptoRng, Rng, RngCore};
+use rayon::prelude::*;
+use sha2::{Digest, Sha256};
+
+type G1Affine = <E as PairingEngine>::G1Affine;
+type G2Affine = <E as PairingEngine>::G2Affine;
+
+// Domain separator for the hash that each proof of knowledge is bound to
+const CONTRIBUTION_DOMAIN: &[u8] = b"zkcreds-passport-cli powers of tau contribution";
+
+// Labels that keep the proofs for τ, α, and β in a contribution from standing in for each other
+const SECRET_LABELS: [&[u8]; 3] = [b"tau", b"alpha", b"beta"];
+
+/// The largest supported log size. Circuits are evaluated over a multiplicative subgroup of the
+/// scalar field, and this is the largest one of power-of-two size.
+pub const MAX_LOG_SIZE: u32 = <Fr as PrimeField>::Params::TWO_ADICITY;
+
+/// The powers of τ for circuits of up to n = 2^log_size constraints, in the exponent
+#[derive(Clone, CanonicalSerialize, CanonicalDeserialize)]
+pub struct PowersOfTau {
+    /// τ^i·G1 for i in 0..2n-1
+    pub tau_g1: Vec<G1Affine>,
+    /// τ^i·G2 for i in 0..n
+    pub tau_g2: Vec<G2Affine>,
+    /// ατ^i·G1 for i in 0..n
+    pub alpha_tau_g1: Vec<G1Affine>,
+    /// βτ^i·G1 for i in 0..n
+    pub beta_tau_g1: Vec<G1Affine>,
+    /// β·G2
+    pub beta_g2: G2Affine,
+}
+
+/// A proof that the prover knows the x with `after = x·before`, for some `before` and `after` in G1
+#[derive(Clone, CanonicalSerialize, CanonicalDeserialize)]
+pub struct KnowledgeProof {
+    /// A random point s
+    pub s: G1Affine,
+    /// s·x
+    pub s_x: G1Affine,
+    /// r·x, where r is hashed to G2 from the preceding contributions, s, and s·x
+    pub r_x: G2Affine,
+}
+
+/// One participant's contribution. The participant picked secrets τ', α', and β', and multiplied
+/// τ, α, and β by them.
+#[derive(Clone, CanonicalSerialize, CanonicalDeserialize)]
+pub struct TauContribution {
+    /// τ·G1 after this contribution
+    pub tau_after: G1Affine,
+    /// α·G1 after this contribution
+    pub alpha_after: G1Affine,
+    /// β·G1 after this contribution
+    pub beta_after: G1Affine,
+    /// Proves knowledge of τ'
+    pub tau_proof: KnowledgeProof,
+    /// Proves knowledge of α'
+    pub alpha_proof: KnowledgeProof,
+    /// Proves knowledge of β'
+    pub beta_proof: KnowledgeProof,
+}
+
+impl TauContribution {
+    fn afters(&self) -> [G1Affine; 3] {
+        [self.tau_after, self.alpha_after, self.beta_after]
+    }
+
+    fn proofs(&self) -> [&KnowledgeProof; 3] {
+        [&self.tau_proof, &self.alpha_proof, &self.beta_proof]
+    }
+}
+
+/// The full record of phase 1. Anyone can check it with `verify_tau_transcript`.
+#[derive(Clone, CanonicalSerialize, CanonicalDeserialize)]
+pub struct TauTranscript {
+    /// The base-2 log of the largest circuit size the powers support
+    pub log_size: u32,
+    /// Every contribution so far, in order
+    pub contributions: Vec<TauContribution>,
+    /// The powers after all the contributions
+    pub current: PowersOfTau,
+}
+
+impl PowersOfTau {
+    /// The τ, α, and β parts of the powers, each multiplied by G1
+    fn secrets_g1(&self) -> [G1Affine; 3] {
+        [self.tau_g1[1], self.alpha_tau_g1[0], self.beta_tau_g1[0]]
+    }
+}
+
+/// Starts phase 1 for circuits of up to 2^log_size constraints. The initial powers are those of
+/// τ = α = β = 1, so they're useless until someone contributes.
+pub fn tau_init(log_size: u32) -> Result<TauTranscript, Error> {
+    if log_size == 0 || log_size > MAX_LOG_SIZE {
+        return Err(Error::Parse(format!(
+            "log size must be between 1 and {}",
+            MAX_LOG_SIZE
+        )));
+    }
+
+    let n = 1usize << log_size;
+    let g1 = G1Affine::prime_subgroup_generator();
+    let g2 = G2Affine::prime_subgroup_generator();
+    Ok(TauTranscript {
+        log_size,
+        contributions: Vec::new(),
+        current: PowersOfTau {
+            tau_g1: vec![g1; 2 * n - 1],
+            tau_g2: vec![g2; n],
+            alpha_tau_g1: vec![g1; n],
+            beta_tau_g1: vec![g1; n],
+            beta_g2: g2,
+        },
+    })
+}
+
+/// Adds a contribution to the transcript. Returns the hex-encoded hash of the contribution, which
+/// the participant should publish so others can find it in the final transcript.
+pub fn tau_contribute<R: RngCore + CryptoRng>(
+    rng: &mut R,
+    transcript: &mut TauTranscript,
+) -> Result<String, Error> {
+    let tau = Fr::rand(rng);
+    let alpha = Fr::rand(rng);
+    let beta = Fr::rand(rng);
+    let before = transcript.current.secrets_g1();
+
+    // Multiply the i-th power of τ by τ'^i, and the α and β ones by α' and β' as well
+    let cur = &mut transcript.current;
+    let powers: Vec<Fr> = core::iter::successors(Some(Fr::one()), |p| Some(*p * tau))
+        .take(cur.tau_g1.len())
+        .collect();
+    cur.tau_g1 = scale_powers(&cur.tau_g1, &powers, Fr::one());
+    cur.tau_g2 = scale_powers(&cur.tau_g2, &powers, Fr::one());
+    cur.alpha_tau_g1 = scale_powers(&cur.alpha_tau_g1, &powers, alpha);
+    cur.beta_tau_g1 = scale_powers(&cur.beta_tau_g1, &powers, beta);
+    cur.beta_g2 = cur.beta_g2.mul(beta).into_affine();
+
+    // Prove knowledge of each of our secrets
+    let prove = |rng: &mut R, label, before, secret| {
+        prove_knowledge(
+            rng,
+            transcript.log_size,
+            &transcript.contributions,
+            label,
+            before,
+            secret,
+        )
+    };
+    let [tau_after, alpha_after, beta_after] = transcript.current.secrets_g1();
+    let contribution = TauContribution {
+        tau_after,
+        alpha_after,
+        beta_after,
+        tau_proof: prove(rng, SECRET_LABELS[0], &before[0], tau)?,
+        alpha_proof: prove(rng, SECRET_LABELS[1], &before[1], alpha)?,
+        beta_proof: prove(rng, SECRET_LABELS[2], &before[2], beta)?,
+    };
+
+    let mut buf = Vec::new();
+    contribution.serialize_uncompressed(&mut buf)?;
+    transcript.contributions.push(contribution);
+
+    Ok(format!("{:x}", Sha256::digest(&buf)))
+}
+
+/// Checks every contribution in the transcript, and that the current powers are well-formed and
+/// the result of exactly those contributions
+pub fn verify_tau_transcript<R: Rng>(rng: &mut R, transcript: &TauTranscript) -> Result<(), Error> {
+    let fail = |msg: &str| {
+        Err(Error::ProofFailure(format!(
+            "invalid powers of tau transcript: {}",
+            msg
+        )))
+    };
+    let cur = &transcript.current;
+    let g1 = G1Affine::prime_subgroup_generator();
+    let g2 = G2Affine::prime_subgroup_generator();
+
+    if transcript.log_size == 0 || transcript.log_size > MAX_LOG_SIZE {
+        return fail("the log size is out of range");
+    }
+    let n = 1usize << transcript.log_size;
+    if cur.tau_g1.len() != 2 * n - 1
+        || cur.tau_g2.len() != n
+        || cur.alpha_tau_g1.len() != n
+        || cur.beta_tau_g1.len() != n
+    {
+        return fail("the powers are the wrong length");
+    }
+
+    // The initial powers are public, so someone has to have contributed
+    if transcript.contributions.is_empty() {
+        return fail("there are no contributions");
+    }
+
+    // Every contribution must prove knowledge of the secrets it multiplied τ, α, and β by
+    let mut before = [g1; 3];
+    for (i, c) in transcript.contributions.iter().enumerate() {
+        let preceding = &transcript.contributions[..i];
+        for (((label, before), after), proof) in SECRET_LABELS
+            .iter()
+            .zip(&before)
+            .zip(c.afters())
+            .zip(c.proofs())
+        {
+            if !check_knowledge(transcript.log_size, preceding, label, before, &after, proof)? {
+                return fail(&format!("contribution {} is invalid", i + 1));
+            }
+        }
+        before = c.afters();
+    }
+    if before != cur.secrets_g1() {
+        return fail("the current powers aren't the result of the contributions");
+    }
+
+    // The powers must start at 1, and β must be the same in G1 and G2
+    if cur.tau_g1[0] != g1 || cur.tau_g2[0] != g2 {
+        return fail("the powers don't start at 1");
+    }
+    if !same_ratio((g1, cur.beta_tau_g1[0]), (g2, cur.beta_g2)) {
+        return fail("β in G1 doesn't match β in G2");
+    }
+
+    // Each sequence must go up by a factor of τ at every step. We check this for random linear
+    // combinations of the sequences and their shifts.
+    let tau_g2 = cur.tau_g2[1];
+    for powers in [&cur.tau_g1, &cur.alpha_tau_g1, &cur.beta_tau_g1] {
+        let (lo, hi) = shifted_combinations(rng, powers);
+        if !same_ratio((lo, hi), (g2, tau_g2)) {
+            return fail("the G1 powers aren't consecutive powers of τ");
+        }
+    }
+    let (lo, hi) = shifted_combinations(rng, &cur.tau_g2);
+    if !same_ratio((g1, cur.tau_g1[1]), (lo, hi)) {
+        return fail("the G2 powers aren't consecutive powers of τ");
+    }
+
+    Ok(())
+}
+
+/// Multiplies the i-th point by the i-th power, and all of them by `factor`
+fn scale_powers<G>(points: &[G], powers: &[Fr], factor: Fr) -> Vec<G>
+where
+    G: AffineCurve<ScalarField = Fr>,
+{
+    let scaled: Vec<_> = points
+        .par_iter()
+        .zip(powers)
+        .map(|(p, x)| p.mul(*x * factor))
+        .collect();
+    G::Projective::batch_normalization_into_affine(&scaled)
+}
+
+/// Returns random linear combinations of `points[..len-1]` and `points[1..]`, with the same
+/// coefficients
+fn shifted_combinations<R, G>(rng: &mut R, points: &[G]) -> (G, G)
+where
+    R: Rng,
+    G: AffineCurve<ScalarField = Fr>,
+{
+    let len = points.len() - 1;
+    let coeffs: Vec<_> = (0..len).map(|_| Fr::rand(rng).into_repr()).collect();
+    let lo = VariableBaseMSM::multi_scalar_mul(&points[..len], &coeffs).into_affine();
+    let hi = VariableBaseMSM::multi_scalar_mul(&points[1..], &coeffs).into_affine();
+    (lo, hi)
+}
+
+/// Proves knowledge of `secret`, which `before` was multiplied by
+fn prove_knowledge<R: Rng>(
+    rng: &mut R,
+    log_size: u32,
+    preceding: &[TauContribution],
+    label: &[u8],
+    before: &G1Affine,
+    secret: Fr,
+) -> Result<KnowledgeProof, Error> {
+    let s = <E as PairingEngine>::G1Projective::rand(rng).into_affine();
+    let s_x = s.mul(secret).into_affine();
+    let r = hash_to_g2(&knowledge_digest(
+        log_size, preceding, label, before, &s, &s_x,
+    )?);
+    Ok(KnowledgeProof {
+        s,
+        s_x,
+        r_x: r.mul(secret).into_affine(),
+    })
+}
+
+/// Returns whether the proof shows knowledge of the x with `after = x·before`
+fn check_knowledge(
+    log_size: u32,
+    preceding: &[TauContribution],
+    label: &[u8],
+    before: &G1Affine,
+    after: &G1Affine,
+    proof: &KnowledgeProof,
+) -> Result<bool, Error> {
+    let r = hash_to_g2(&knowledge_digest(
+        log_size, preceding, label, before, &proof.s, &proof.s_x,
+    )?);
+    Ok(!proof.s.is_zero()
+        && !after.is_zero()
+        && same_ratio((proof.s, proof.s_x), (r, proof.r_x))
+        && same_ratio((*before, *after), (r, proof.r_x)))
+}
+
+/// The hash that a proof of knowledge's r is derived from. It covers the log size and every
+/// preceding contribution, so a proof can't be replayed elsewhere in the transcript.
+fn knowledge_digest(
+    log_size: u32,
+    preceding: &[TauContribution],
+    label: &[u8],
+    before: &G1Affine,
+    s: &G1Affine,
+    s_x: &G1Affine,
+) -> Result<[u8; 32], Error> {
+    let mut buf = CONTRIBUTION_DOMAIN.to_vec();
+    log_size.serialize(&mut buf)?;
+    preceding.serialize_uncompressed(&mut buf)?;
+    buf.extend_from_slice(label);
+    before.serialize_uncompressed(&mut buf)?;
+    s.serialize_uncompressed(&mut buf)?;
+    s_x.serialize_uncompressed(&mut buf)?;
+    Ok(Sha256::digest(&buf).into())
+}
@@ -13,8 +13,8 @@ use ark_relations::{
 };
 
 #[derive(Clone, Default)]
-pub(crate) struct AgeChecker {
-    pub(crate) threshold_dob: Fr,
+pub struct AgeChecker {
+    pub threshold_dob: Fr,
 }
 
 impl PredicateChecker<Fr, PersonalInfo, PersonalInfoVar, PassportComScheme, PassportComSchemeG>
@@ -42,8 +42,8 @@ impl PredicateChecker<Fr, PersonalInfo, PersonalInfoVar, PassportComScheme, Pass
 }
 
 #[derive(Clone, Default)]
-pub(crate) struct ExpiryChecker {
-    pub(crate) threshold_expiry: Fr,
+pub struct ExpiryChecker {
+    pub threshold_expiry: Fr,
 }
 
 impl PredicateChecker<Fr, PersonalInfo, PersonalInfoVar, PassportComScheme, PassportComSchemeG>
@@ -71,8 +71,8 @@ impl PredicateChecker<Fr, PersonalInfo, PersonalInfoVar, PassportComScheme, Pass
 }
 
 #[derive(Clone, Default)]
-pub(crate) struct FaceChecker {
-    pub(crate) face_hash: [u8; HASH_LEN],
+pub struct FaceChecker {
+    pub face_hash: [u8; HASH_LEN],
 }
 
 impl PredicateChecker<Fr, PersonalInfo, PersonalInfoVar, PassportComScheme, PassportComSchemeG>
@@ -97,10 +97,10 @@ impl PredicateChecker<Fr, PersonalInfo, PersonalInfoVar, PassportComScheme, Pass
 }
 
 #[derive(Clone, Default)]
-pub(crate) struct AgeFaceExpiryChecker {
-    pub(crate) age_checker: AgeChecker,
-    pub(crate) face_checker: FaceChecker,
-    pub(crate) expiry_checker: ExpiryChecker,
+pub struct AgeFaceExpiryChecker {
+    pub age_checker: AgeChecker,
+    pub face_checker: FaceChecker,
+    pub expiry_checker: ExpiryChecker,
 }
 
 impl PredicateChecker<Fr, PersonalInfo, PersonalInfoVar, PassportComScheme, PassportComSchemeG>
@@ -132,9 +132,9 @@ impl PredicateChecker<Fr, PersonalInfo, PersonalInfoVar, PassportComScheme, Pass
 }
 
 #[derive(Clone, Default)]
-pub(crate) struct AgeAndExpiryChecker {
-    pub(crate) age_checker: AgeChecker,
-    pub(crate) expiry_checker: ExpiryChecker,
+pub struct AgeAndExpiryChecker {
+    pub age_checker: AgeChecker,
+    pub expiry_checker: ExpiryChecker,
 }
 
 impl PredicateChecker<Fr, PersonalInfo, PersonalInfoVar, PassportComScheme, PassportComSchemeG>
@@ -164,10 +164,10 @@ impl PredicateChecker<Fr, PersonalInfo, PersonalInfoVar, PassportComScheme, Pass
 }
 
 #[derive(Clone, Default)]
-pub(crate) struct AgeMultishowExpiryChecker {
-    pub(crate) age_checker: AgeChecker,
-    pub(crate) multishow_checker: RevealingMultishowChecker<Fr>,
-    pub(crate) expiry_checker: ExpiryChecker,
+pub struct AgeMultishowExpiryChecker {
+    pub age_checker: AgeChecker,
+    pub multishow_checker: RevealingMultishowChecker<Fr>,
+    pub expiry_checker: ExpiryChecker,
 }
 
 impl PredicateChecker<Fr, PersonalInfo, PersonalInfoVar, PassportComScheme, PassportComSchemeG>
@@ -203,3 +203,67 @@ impl PredicateChecker<Fr, PersonalInfo, PersonalInfoVar, PassportComScheme, Pass
         .concat()
     }
 }
+
+/// Binds a show to the verifier it's made for and the challenge that verifier issued, so that it
+/// can't be replayed to another verifier, or to the same one later. This says nothing about the
+/// attributes.
+#[derive(Clone, Default)]
+pub struct ShowContextChecker {
+    pub verifier_id: Fr,
+    pub challenge: Fr,
+}
+
+impl PredicateChecker<Fr, PersonalInfo, PersonalInfoVar, PassportComScheme, PassportComSchemeG>
+    for ShowContextChecker
+{
+    /// Returns whether or not the predicate was satisfied
+    fn pred(
+        self,
+        cs: ConstraintSystemRef<Fr>,
+        _attrs: &PersonalInfoVar,
+    ) -> Result<(), SynthesisError> {
+        // These don't need to appear in any constraint. Groth16 setup adds a constraint for every
+        // public input, so a proof is bound to all of them.
+        FpVar::<Fr>::new_input(ns!(cs, "verifier id"), || Ok(self.verifier_id))?;
+        FpVar::<Fr>::new_input(ns!(cs, "challenge"), || Ok(self.challenge))?;
+
+        Ok(())
+    }
+
+    /// This outputs the field elements corresponding to the public inputs of this predicate.
+    /// This DOES NOT include `attrs`.
+    fn public_inputs(&self) -> Vec<Fr> {
+        vec![self.verifier_id, self.challenge]
+    }
+}
+
+/// A predicate that can be shown to a verifier, along with the context of the show
+#[derive(Clone, Default)]
+pub struct ShowChecker<P> {
+    pub pred: P,
+    pub context: ShowContextChecker,
+}
+
+impl<P> PredicateChecker<Fr, PersonalInfo, PersonalInfoVar, PassportComScheme, PassportComSchemeG>
+    for ShowChecker<P>
+where
+    P: PredicateChecker<Fr, PersonalInfo, PersonalInfoVar, PassportComScheme, PassportComSchemeG>,
+{
+    /// Returns whether or not the predicate was satisfied
+    fn pred(
+        self,
+        cs: ConstraintSystemRef<Fr>,
+        attrs: &PersonalInfoVar,
+    ) -> Result<(), SynthesisError> {
+        self.pred.pred(cs.clone(), attrs)?;
+        self.context.pred(cs.clone(), attrs)?;
+
+        Ok(())
+    }
+
+    /// This outputs the field elements corresponding to the public inputs of this predicate.
+    /// This DOES NOT include `attrs`.
+    fn public_inputs(&self) -> Vec<Fr> {
+        [self.pred.public_inputs(), self.context.public_inputs()].concat()
+    }
+}
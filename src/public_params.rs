@@ -0,0 +1,287 @@
+//! Nothing-up-my-sleeve generation of the commitment and tree hash parameters. Every Pedersen base
+//! is hashed to the embedded curve from a domain-separated label, so nobody knows the discrete log
+//! of any base with respect to another, and anyone can recompute the bases from the labels alone.
+//!
+//! Poseidon parameters are already standardized, so with the `poseidon` feature the labels go
+//! unused and the parameters are just the standard ones.
+
+use crate::{
+    error::Error,
+    params::{PassportComScheme, H, PARAM_SET_ID, PARAM_SET_NAMES},
+};
+
+use ark_crypto_primitives::{commitment::CommitmentScheme, crh::TwoToOneCRH};
+use ark_serialize::CanonicalSerialize;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+#[cfg(not(feature = "poseidon"))]
+use crate::params::{EmbeddedCurve, Window9x128, Window9x63};
+#[cfg(not(feature = "poseidon"))]
+use ark_crypto_primitives::crh::pedersen::Window;
+#[cfg(not(feature = "poseidon"))]
+use ark_crypto_primitives::{commitment::pedersen as pedersen_com, crh::bowe_hopwood};
+#[cfg(not(feature = "poseidon"))]
+use ark_ec::{
+    models::ModelParameters,
+    twisted_edwards_extended::{GroupAffine, GroupProjective},
+    AffineCurve, ProjectiveCurve,
+};
+#[cfg(not(feature = "poseidon"))]
+use ark_ff::{PrimeField, Zero};
+
+/// The label the commitment parameters are derived from
+pub const COM_PARAM_LABEL: &str = "zkcreds-passport-cli commitment param";
+/// The label the tree hash parameters are derived from
+pub const CRH_PARAM_LABEL: &str = "zkcreds-passport-cli merkle param";
+
+// Domain separator for hashing to the embedded curve
+#[cfg(not(feature = "poseidon"))]
+const HASH_TO_CURVE_DOMAIN: &[u8] = b"zkcreds-passport-cli hash to curve";
+
+pub type ComParam = <PassportComScheme as CommitmentScheme>::Parameters;
+pub type CrhParam = <H as TwoToOneCRH>::Parameters;
+
+#[cfg(not(feature = "poseidon"))]
+type EdwardsAffine = GroupAffine<EmbeddedCurve>;
+#[cfg(not(feature = "poseidon"))]
+type EdwardsProjective = GroupProjective<EmbeddedCurve>;
+
+/// Derives the Pedersen commitment parameters from the given label. These are laid out the way
+/// `CommitmentScheme::setup` lays them out, with every random base replaced by a hashed one.
+#[cfg(not(feature = "poseidon"))]
+pub fn gen_com_param(label: &str) -> ComParam {
+    // The randomness is multiplied by the powers of two of a single base
+    let num_bits = <EmbeddedCurve as ModelParameters>::ScalarField::size_in_bits();
+    let randomness_generator = powers(hash_to_curve(label, 0), num_bits, 1);
+
+    // Every window has its own base, and its powers of two
+    let generators = (0..Window9x128::NUM_WINDOWS)
+        .map(|i| {
+            powers(
+                hash_to_curve(label, i as u64 + 1),
+                Window9x128::WINDOW_SIZE,
+                1,
+            )
+        })
+        .collect();
+
+    pedersen_com::Parameters {
+        randomness_generator,
+        generators,
+    }
+}
+
+/// Derives the Bowe-Hopwood tree hash parameters from the given label. These are laid out the way
+/// `TwoToOneCRH::setup` lays them out, with every random base replaced by a hashed one.
+#[cfg(not(feature = "poseidon"))]
+pub fn gen_crh_param(label: &str) -> CrhParam {
+    // Every window has its own base. Bowe-Hopwood chunks are 4 bits apart, so each window uses the
+    // powers of 16 of its base.
+    let generators = (0..Window9x63::NUM_WINDOWS)
+        .map(|i| powers(hash_to_curve(label, i as u64), Window9x63::WINDOW_SIZE, 4))
+        .collect();
+
+    bowe_hopwood::Parameters { generators }
+}
+
+/// The standard Poseidon parameters. The label is unused.
+#[cfg(feature = "poseidon")]
+pub fn gen_com_param(_label: &str) -> ComParam {
+    crate::poseidon::poseidon_params()
+}
+
+/// The standard Poseidon parameters. The label is unused.
+#[cfg(feature = "poseidon")]
+pub fn gen_crh_param(_label: &str) -> CrhParam {
+    crate::poseidon::poseidon_params()
+}
+
+/// Returns `base, 2^k·base, 2^(2k)·base, ...`, `n` points in all, where `k` is `doublings`
+#[cfg(not(feature = "poseidon"))]
+fn powers(mut base: EdwardsProjective, n: usize, doublings: usize) -> Vec<EdwardsProjective> {
+    let mut out = Vec::with_capacity(n);
+    for _ in 0..n {
+        out.push(base);
+        for _ in 0..doublings {
+            base.double_in_place();
+        }
+    }
+    out
+}
+
+/// Hashes the label and index to a point in the prime-order subgroup of the embedded curve, by
+/// hashing with an increasing counter until the hash is a valid x-coordinate
+#[cfg(not(feature = "poseidon"))]
+fn hash_to_curve(label: &str, index: u64) -> EdwardsProjective {
+    let mut counter = 0u64;
+    loop {
+        let digest = Sha256::new()
+            .chain_update(HASH_TO_CURVE_DOMAIN)
+            .chain_update((label.len() as u64).to_le_bytes())
+            .chain_update(label)
+            .chain_update(index.to_le_bytes())
+            .chain_update(counter.to_le_bytes())
+            .finalize();
+        if let Some(p) = EdwardsAffine::from_random_bytes(&digest) {
+            let p = p.mul_by_cofactor_to_projective();
+            if !p.is_zero() {
+                return p;
+            }
+        }
+        counter += 1;
+    }
+}
+
+/// The canonical serialization of the commitment parameters, with points in affine form
+#[cfg(not(feature = "poseidon"))]
+fn serialize_com_param(param: &ComParam) -> Result<Vec<u8>, Error> {
+    let mut buf = Vec::new();
+    EdwardsProjective::batch_normalization_into_affine(&param.randomness_generator)
+        .serialize(&mut buf)?;
+    serialize_generators(&param.generators, &mut buf)?;
+    Ok(buf)
+}
+
+/// The canonical serialization of the tree hash parameters, with points in affine form
+#[cfg(not(feature = "poseidon"))]
+fn serialize_crh_param(param: &CrhParam) -> Result<Vec<u8>, Error> {
+    let mut buf = Vec::new();
+    serialize_generators(&param.generators, &mut buf)?;
+    Ok(buf)
+}
+
+#[cfg(not(feature = "poseidon"))]
+fn serialize_generators(
+    generators: &[Vec<EdwardsProjective>],
+    buf: &mut Vec<u8>,
+) -> Result<(), Error> {
+    let affine: Vec<_> = generators
+        .iter()
+        .map(|window| EdwardsProjective::batch_normalization_into_affine(window))
+        .collect();
+    affine.serialize(buf)?;
+    Ok(())
+}
+
+#[cfg(feature = "poseidon")]
+fn serialize_com_param(param: &ComParam) -> Result<Vec<u8>, Error> {
+    serialize_poseidon(param)
+}
+
+#[cfg(feature = "poseidon")]
+fn serialize_crh_param(param: &CrhParam) -> Result<Vec<u8>, Error> {
+    serialize_poseidon(param)
+}
+
+/// The canonical serialization of Poseidon parameters
+#[cfg(feature = "poseidon")]
+fn serialize_poseidon(
+    param: &arkworks_native_gadgets::poseidon::PoseidonParameters<crate::params::Fr>,
+) -> Result<Vec<u8>, Error> {
+    let mut buf = Vec::new();
+    param.mds_matrix.serialize(&mut buf)?;
+    param.round_keys.serialize(&mut buf)?;
+    (param.full_rounds as u64).serialize(&mut buf)?;
+    (param.partial_rounds as u64).serialize(&mut buf)?;
+    (param.width as u64).serialize(&mut buf)?;
+    Ok(buf)
+}
+
+/// One set of parameters, along with how to recompute it
+#[derive(Serialize, Deserialize)]
+pub struct ExportedParam {
+    /// The label the parameters are derived from
+    pub label: String,
+    /// Hex-encoded SHA-256 hash of `data`
+    pub sha256: String,
+    /// Base64-encoded canonical serialization of the parameters
+    pub data: String,
+}
+
+impl ExportedParam {
+    fn new(label: &str, data: Vec<u8>) -> ExportedParam {
+        ExportedParam {
+            label: label.to_string(),
+            sha256: format!("{:x}", Sha256::digest(&data)),
+            data: base64::encode(data),
+        }
+    }
+}
+
+/// The commitment and tree hash parameters this build uses, in a form others can check with
+/// `verify_params`
+#[derive(Serialize, Deserialize)]
+pub struct ExportedParams {
+    /// The name of the parameter set, e.g., `bls12-381/pedersen`
+    pub param_set: String,
+    pub commitment: ExportedParam,
+    pub tree_hash: ExportedParam,
+}
+
+/// Exports the given parameters
+pub fn export_params(com_param: &ComParam, crh_param: &CrhParam) -> Result<ExportedParams, Error> {
+    Ok(ExportedParams {
+        param_set: PARAM_SET_NAMES[PARAM_SET_ID as usize].to_string(),
+        commitment: ExportedParam::new(COM_PARAM_LABEL, serialize_com_param(com_param)?),
+        tree_hash: ExportedParam::new(CRH_PARAM_LABEL, serialize_crh_param(crh_param)?),
+    })
+}
+
+/// The SHA-256 hash of the tree hash parameters, i.e., the `sha256` of the exported tree hash
+/// parameters
+pub fn tree_params_digest(crh_param: &CrhParam) -> Result<[u8; 32], Error> {
+    Ok(Sha256::digest(&serialize_crh_param(crh_param)?).into())
+}
+
+/// Recomputes the exported parameters from their labels, and checks that they match exactly. Also
+/// checks that the labels are the ones this build uses, so parameters that pass are the ones this
+/// build would use.
+pub fn verify_params(exported: &ExportedParams) -> Result<(), Error> {
+    let fail = |msg: String| Err(Error::ProofFailure(format!("invalid parameters: {}", msg)));
+
+    let this_param_set = PARAM_SET_NAMES[PARAM_SET_ID as usize];
+    if exported.param_set != this_param_set {
+        return fail(format!(
+            "they're for the {} parameter set, and this build uses {}",
+            exported.param_set, this_param_set
+        ));
+    }
+
+    let recomputed = export_params(
+        &gen_com_param(&exported.commitment.label),
+        &gen_crh_param(&exported.tree_hash.label),
+    )?;
+    for (name, ours, theirs, label) in [
+        (
+            "commitment",
+            &recomputed.commitment,
+            &exported.commitment,
+            COM_PARAM_LABEL,
+        ),
+        (
+            "tree hash",
+            &recomputed.tree_hash,
+            &exported.tree_hash,
+            CRH_PARAM_LABEL,
+        ),
+    ] {
+        if theirs.label != label {
+            return fail(format!(
+                "the {} label is {:?}, and this build uses {:?}",
+                name, theirs.label, label
+            ));
+        }
+        if theirs.data != ours.data {
+            return fail(format!(
+                "the {} parameters weren't derived from their label",
+                name
+            ));
+        }
+        if theirs.sha256 != ours.sha256 {
+            return fail(format!("the {} parameters' hash is wrong", name));
+        }
+    }
+
+    Ok(())
+}
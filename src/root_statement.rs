@@ -0,0 +1,304 @@
+//! Signed statements from the issuer about the root of its tree. A statement says which root the
+//! issuer published, when, under which tree parameters, and for which issuance verifying key.
+//! Each statement has an epoch, which goes up by one every time the issuer publishes, so a
+//! verifier that has seen a statement can tell if it's later handed an older one. Statements are
+//! signed with the issuer's Ed25519 signing key.
+//!
+//! The same key signs revocations, which tell a running issuer service to revoke a credential.
+
+use crate::{
+    error::Error,
+    params::{ComTreeRoot, MERKLE_CRH_PARAM, PARAM_SET_ID, PARAM_SET_NAMES},
+    public_params::tree_params_digest,
+    tree::TREE_HEIGHT,
+};
+
+use std::{
+    io::{Read, Write},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize, SerializationError};
+use clap::Args;
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use rand::{CryptoRng, RngCore};
+
+// Domain separator for root statement signatures
+const ROOT_STATEMENT_DOMAIN: &[u8] = b"zkcreds-passport root statement";
+// Domain separator for revocation signatures
+const REVOCATION_DOMAIN: &[u8] = b"zkcreds-passport revocation";
+
+/// The issuer's key for signing root statements
+pub struct IssuerSigningKey(SigningKey);
+
+/// The public half of an [`IssuerSigningKey`], which verifiers use to check root statements
+pub struct IssuerPublicKey(VerifyingKey);
+
+impl IssuerSigningKey {
+    pub fn generate<R: RngCore + CryptoRng>(rng: &mut R) -> IssuerSigningKey {
+        IssuerSigningKey(SigningKey::generate(rng))
+    }
+
+    pub fn public_key(&self) -> IssuerPublicKey {
+        IssuerPublicKey(self.0.verifying_key())
+    }
+}
+
+impl CanonicalSerialize for IssuerSigningKey {
+    fn serialize<W: Write>(&self, mut writer: W) -> Result<(), SerializationError> {
+        Ok(writer.write_all(&self.0.to_bytes())?)
+    }
+
+    fn serialized_size(&self) -> usize {
+        32
+    }
+}
+
+impl CanonicalDeserialize for IssuerSigningKey {
+    fn deserialize<R: Read>(mut reader: R) -> Result<Self, SerializationError> {
+        let mut bytes = [0u8; 32];
+        reader.read_exact(&mut bytes)?;
+        Ok(IssuerSigningKey(SigningKey::from_bytes(&bytes)))
+    }
+}
+
+impl CanonicalSerialize for IssuerPublicKey {
+    fn serialize<W: Write>(&self, mut writer: W) -> Result<(), SerializationError> {
+        Ok(writer.write_all(self.0.as_bytes())?)
+    }
+
+    fn serialized_size(&self) -> usize {
+        32
+    }
+}
+
+impl CanonicalDeserialize for IssuerPublicKey {
+    fn deserialize<R: Read>(mut reader: R) -> Result<Self, SerializationError> {
+        let mut bytes = [0u8; 32];
+        reader.read_exact(&mut bytes)?;
+        VerifyingKey::from_bytes(&bytes)
+            .map(IssuerPublicKey)
+            .map_err(|_| SerializationError::InvalidData)
+    }
+}
+
+/// How old a root statement can be, by default, before verifiers stop using it: one day
+pub const DEFAULT_MAX_AGE: u64 = 24 * 60 * 60;
+
+/// How recent a root statement must be for a verifier to use it. A signed statement stays valid
+/// forever, so without a limit, a holder could keep showing a credential against a root from
+/// before it was revoked.
+#[derive(Clone, Debug, PartialEq, Eq, Args)]
+pub struct Freshness {
+    /// The earliest epoch of root statement to accept
+    #[clap(long)]
+    pub min_epoch: Option<u64>,
+
+    /// How old a root statement can be, in seconds. A revoked credential can still be shown for
+    /// this long after its revocation, and the issuer must publish a statement at least this
+    /// often.
+    #[clap(long, default_value_t = DEFAULT_MAX_AGE)]
+    pub max_age: u64,
+}
+
+impl Default for Freshness {
+    fn default() -> Freshness {
+        Freshness {
+            min_epoch: None,
+            max_age: DEFAULT_MAX_AGE,
+        }
+    }
+}
+
+/// What the issuer says about a root of its tree
+#[derive(Clone, CanonicalSerialize, CanonicalDeserialize)]
+pub struct RootStatement {
+    pub root: ComTreeRoot,
+    /// How many statements the issuer published before this one
+    pub epoch: u64,
+    /// When the statement was made, in seconds since the Unix epoch
+    pub timestamp: u64,
+    /// The index of the parameter set, as in a frame header
+    pub param_set: u8,
+    pub tree_height: u32,
+    /// The SHA-256 hash of the tree hash parameters
+    pub tree_params_digest: Vec<u8>,
+    // The hex-encoded fingerprint of the issuance verifying key. This is always ASCII.
+    key_fingerprint: Vec<u8>,
+}
+
+impl RootStatement {
+    /// Makes a statement about the given root as of now, under this build's tree parameters.
+    /// `key_fingerprint` is the fingerprint of the issuance verifying key.
+    pub fn new(
+        root: ComTreeRoot,
+        epoch: u64,
+        key_fingerprint: &str,
+    ) -> Result<RootStatement, Error> {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+
+        Ok(RootStatement {
+            root,
+            epoch,
+            timestamp,
+            param_set: PARAM_SET_ID,
+            tree_height: TREE_HEIGHT,
+            tree_params_digest: tree_params_digest(&MERKLE_CRH_PARAM)?.to_vec(),
+            key_fingerprint: key_fingerprint.as_bytes().to_vec(),
+        })
+    }
+
+    /// How many seconds ago the statement was made
+    pub fn age(&self) -> u64 {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        now.saturating_sub(self.timestamp)
+    }
+
+    pub fn key_fingerprint(&self) -> String {
+        String::from_utf8_lossy(&self.key_fingerprint).into_owned()
+    }
+
+    /// Signs the statement
+    pub fn sign(self, key: &IssuerSigningKey) -> Result<SignedRootStatement, Error> {
+        let signature = key.0.sign(&self.message()?).to_bytes().to_vec();
+        Ok(SignedRootStatement {
+            statement: self,
+            signature,
+        })
+    }
+
+    /// The bytes that get signed
+    fn message(&self) -> Result<Vec<u8>, Error> {
+        let mut msg = ROOT_STATEMENT_DOMAIN.to_vec();
+        self.serialize(&mut msg)?;
+        Ok(msg)
+    }
+}
+
+/// A root statement along with the issuer's signature over it
+#[derive(Clone, CanonicalSerialize, CanonicalDeserialize)]
+pub struct SignedRootStatement {
+    pub statement: RootStatement,
+    signature: Vec<u8>,
+}
+
+impl SignedRootStatement {
+    /// Checks that the statement is signed by the given issuer, and that it's about a tree with
+    /// the same parameters this build uses
+    pub fn verify(&self, key: &IssuerPublicKey) -> Result<&RootStatement, Error> {
+        let fail = |msg: &str| {
+            Err(Error::ProofFailure(format!(
+                "invalid root statement: {}",
+                msg
+            )))
+        };
+
+        let statement = &self.statement;
+        let signature = match Signature::from_slice(&self.signature) {
+            Ok(signature) => signature,
+            Err(_) => return fail("malformed signature"),
+        };
+        if key
+            .0
+            .verify_strict(&statement.message()?, &signature)
+            .is_err()
+        {
+            return fail("it isn't signed by the issuer");
+        }
+
+        if statement.param_set != PARAM_SET_ID {
+            return fail(&format!(
+                "it's for the {} parameter set, and this build uses {}",
+                PARAM_SET_NAMES
+                    .get(statement.param_set as usize)
+                    .unwrap_or(&"unknown"),
+                PARAM_SET_NAMES[PARAM_SET_ID as usize]
+            ));
+        }
+        if statement.tree_height != TREE_HEIGHT
+            || statement.tree_params_digest != tree_params_digest(&MERKLE_CRH_PARAM)?
+        {
+            return fail("it's for a tree with different parameters");
+        }
+
+        Ok(statement)
+    }
+
+    /// Checks the statement like [`SignedRootStatement::verify`], and also that it's as recent as
+    /// `freshness` requires
+    pub fn verify_fresh(
+        &self,
+        key: &IssuerPublicKey,
+        freshness: &Freshness,
+    ) -> Result<&RootStatement, Error> {
+        let statement = self.verify(key)?;
+
+        if let Some(min_epoch) = freshness.min_epoch {
+            if statement.epoch < min_epoch {
+                return Err(Error::ProofFailure(format!(
+                    "invalid root statement: it's from epoch {}, and the earliest accepted is {}",
+                    statement.epoch, min_epoch
+                )));
+            }
+        }
+        let age = statement.age();
+        if age > freshness.max_age {
+            return Err(Error::ProofFailure(format!(
+                "invalid root statement: it's {} seconds old, and at most {} are accepted",
+                age, freshness.max_age
+            )));
+        }
+
+        Ok(statement)
+    }
+}
+
+/// The issuer's order to revoke the credential at a leaf index. Revoking a credential can't be
+/// undone, so a replayed order does nothing, and orders don't need an epoch or a timestamp.
+#[derive(Clone, CanonicalSerialize, CanonicalDeserialize)]
+pub struct SignedRevocation {
+    pub leaf_index: u64,
+    signature: Vec<u8>,
+}
+
+impl SignedRevocation {
+    /// Signs an order to revoke the credential at the given leaf index
+    pub fn new(leaf_index: u64, key: &IssuerSigningKey) -> SignedRevocation {
+        let signature = key
+            .0
+            .sign(&revocation_message(leaf_index))
+            .to_bytes()
+            .to_vec();
+        SignedRevocation {
+            leaf_index,
+            signature,
+        }
+    }
+
+    /// Checks that the order is signed by the given issuer, and returns the leaf index to revoke
+    pub fn verify(&self, key: &IssuerPublicKey) -> Result<u64, Error> {
+        let signed = Signature::from_slice(&self.signature).map_or(false, |signature| {
+            key.0
+                .verify_strict(&revocation_message(self.leaf_index), &signature)
+                .is_ok()
+        });
+        if !signed {
+            return Err(Error::ProofFailure(
+                "invalid revocation: it isn't signed by the issuer".to_string(),
+            ));
+        }
+
+        Ok(self.leaf_index)
+    }
+}
+
+/// The bytes that get signed in a revocation
+fn revocation_message(leaf_index: u64) -> Vec<u8> {
+    let mut msg = REVOCATION_DOMAIN.to_vec();
+    msg.extend_from_slice(&leaf_index.to_be_bytes());
+    msg
+}
@@ -0,0 +1,448 @@
+//! Showing a credential to a verifier. A show proves that the holder has a credential in the
+//! issuer's tree whose attributes satisfy one of the predicates in [`crate::preds`], without saying
+//! which credential it is. It's made of zkcreds proofs about the same hidden commitment: a
+//! predicate proof, a proof that the commitment is in a tree, and a proof that the tree's root is
+//! the issuer's. zkcreds links these into a single proof, so the verifier learns that they're about
+//! the same credential and nothing else. Shows of a credential can't be linked to each other or to
+//! its issuance.
+//!
+//! The membership proofs only depend on the credential and the root, so a holder can make them once
+//! per root and reuse them in every show against it (see [`MembershipProofs`]). zkcreds
+//! rerandomizes them when linking, so reusing them doesn't make shows linkable.
+//!
+//! Every show proof also proves knowledge of the verifier's identifier and a challenge the verifier
+//! issued (see [`crate::preds::ShowContextChecker`]), so it can't be replayed elsewhere.
+
+use crate::{
+    circuit_stats::Circuit,
+    error::Error,
+    output::to_base64,
+    params::{
+        ComForestRoots, ComTreePath, ComTreeRoot, ForestProof, ForestProvingKey,
+        ForestVerifyingKey, Fr, LinkProof, LinkProofCtx, LinkVerifyingKey, PassportComScheme,
+        PassportComSchemeG, PredProvingKey, PredVerifyingKey, TreeProof, TreeProvingKey,
+        TreeVerifyingKey, MERKLE_CRH_PARAM,
+    },
+    passport_info::{PersonalInfo, PersonalInfoVar},
+    preds::{AgeAndExpiryChecker, AgeChecker, ExpiryChecker, ShowChecker, ShowContextChecker},
+    tree::TREE_HEIGHT,
+};
+
+use zkcreds::{
+    attrs::Attrs,
+    com_forest::gen_forest_memb_crs,
+    com_tree::gen_tree_memb_crs,
+    link::{link_proofs, verify_link, PredPublicInputs},
+    pred::{prove_pred, PredicateChecker},
+};
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use ark_ff::PrimeField;
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use ark_std::rand::Rng;
+use clap::Args;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+// The domain separator for hashing verifier identifiers to the field
+const VERIFIER_ID_DOMAIN: &[u8] = b"zkcreds-passport verifier id";
+
+// zkcreds proves that a credential's tree is one of a forest of trees. The issuer has a single
+// tree, so its forest has one root.
+const NUM_TREES: usize = 1;
+
+/// What a verifier requires of a credential
+#[derive(Clone, Debug, PartialEq, Eq, Args, Serialize, Deserialize)]
+pub struct ShowPolicy {
+    /// The predicate the credential must satisfy. Only `show-age`, `show-expiry`, and
+    /// `show-age-and-expiry` can be shown.
+    #[clap(long, arg_enum, default_value = "show-age-and-expiry")]
+    pub circuit: Circuit,
+
+    /// The date the policy is applied on, as an integer whose base-10 representation is YYYYMMDD.
+    /// If it's `None`, it's the current date in UTC. A verifier fixes it when it issues a
+    /// challenge, and sends it to the holder with the rest of the policy.
+    #[clap(skip)]
+    pub today: Option<u32>,
+
+    /// For age predicates, how old the holder must be today, in years
+    #[clap(long, default_value = "18")]
+    pub min_age: u32,
+
+    /// The verifier's identifier, which is the host name holders reach it at, e.g., example.com.
+    /// A show is only accepted by the verifier it was made for, and `present` only makes shows
+    /// for the host it's talking to.
+    #[clap(long)]
+    pub verifier_id: String,
+}
+
+impl ShowPolicy {
+    /// The date the policy is applied on
+    pub fn today(&self) -> u32 {
+        self.today.unwrap_or_else(current_date)
+    }
+
+    /// This policy, with its date fixed to the one it would be applied on now
+    pub fn dated(&self) -> ShowPolicy {
+        ShowPolicy {
+            today: Some(self.today()),
+            ..self.clone()
+        }
+    }
+
+    // Holders born on or before this date are old enough
+    fn age_checker(&self) -> AgeChecker {
+        AgeChecker {
+            threshold_dob: Fr::from(
+                self.today()
+                    .saturating_sub(self.min_age.saturating_mul(10000)),
+            ),
+        }
+    }
+
+    // Passports must expire after today
+    fn expiry_checker(&self) -> ExpiryChecker {
+        ExpiryChecker {
+            threshold_expiry: Fr::from(self.today()),
+        }
+    }
+
+    fn age_and_expiry_checker(&self) -> AgeAndExpiryChecker {
+        AgeAndExpiryChecker {
+            age_checker: self.age_checker(),
+            expiry_checker: self.expiry_checker(),
+        }
+    }
+
+    // Binds the predicate to this verifier and the given challenge
+    fn show_checker<P>(&self, pred: P, challenge: Fr) -> ShowChecker<P> {
+        let verifier_id = Sha256::new()
+            .chain_update(VERIFIER_ID_DOMAIN)
+            .chain_update(self.verifier_id.as_bytes())
+            .finalize();
+        ShowChecker {
+            pred,
+            context: ShowContextChecker {
+                verifier_id: Fr::from_le_bytes_mod_order(&verifier_id),
+                challenge,
+            },
+        }
+    }
+}
+
+/// The keys a holder needs to prove that their credential is in the issuer's tree. Unlike the
+/// predicate keys, these are the same for every policy.
+#[derive(CanonicalSerialize, CanonicalDeserialize)]
+pub struct MembershipProvingKey {
+    pub tree: TreeProvingKey,
+    pub forest: ForestProvingKey,
+}
+
+/// The keys a verifier needs to check that a shown credential is in the issuer's tree
+#[derive(Clone, CanonicalSerialize, CanonicalDeserialize)]
+pub struct MembershipVerifyingKey {
+    pub tree: TreeVerifyingKey,
+    pub forest: ForestVerifyingKey,
+}
+
+impl MembershipProvingKey {
+    pub fn prepare_verifying_key(&self) -> MembershipVerifyingKey {
+        MembershipVerifyingKey {
+            tree: self.tree.prepare_verifying_key(),
+            forest: self.forest.prepare_verifying_key(),
+        }
+    }
+}
+
+/// Generates the keys for proving membership in the issuer's tree
+pub fn gen_membership_crs<R: Rng>(
+    rng: &mut R,
+) -> Result<(MembershipProvingKey, MembershipVerifyingKey), Error> {
+    let pk = MembershipProvingKey {
+        tree: gen_tree_memb_crs(rng, MERKLE_CRH_PARAM.clone(), TREE_HEIGHT)?,
+        forest: gen_forest_memb_crs(rng, NUM_TREES)?,
+    };
+    let vk = pk.prepare_verifying_key();
+    Ok((pk, vk))
+}
+
+/// A credential's proofs that it's in the issuer's tree with the given root. They don't depend on
+/// the verifier or its challenge, so they can be reused in every show against the same root.
+#[derive(Clone, CanonicalSerialize, CanonicalDeserialize)]
+pub struct MembershipProofs {
+    pub root: ComTreeRoot,
+    pub tree: TreeProof,
+    pub forest: ForestProof,
+}
+
+/// A holder proves that their credential is in the issuer's tree with the given root.
+/// `auth_path` must be the credential's auth path in that tree.
+pub fn prove_membership<R: Rng>(
+    rng: &mut R,
+    membership_pk: &MembershipProvingKey,
+    info: &PersonalInfo,
+    auth_path: &ComTreePath,
+    root: &ComTreeRoot,
+) -> Result<MembershipProofs, Error> {
+    let attrs_com = info.commit();
+    let tree = auth_path.prove_membership(
+        rng,
+        &membership_pk.tree,
+        &*MERKLE_CRH_PARAM,
+        attrs_com.clone(),
+    )?;
+    let forest =
+        forest_roots(root).prove_membership(rng, &membership_pk.forest, *root, attrs_com)?;
+
+    Ok(MembershipProofs {
+        root: *root,
+        tree,
+        forest,
+    })
+}
+
+/// Returns the credential's membership proofs for the given root. The ones in `cache` are reused
+/// if they're for that root. Otherwise, fresh ones are made and replace them.
+pub fn cached_membership_proofs<'a, R: Rng>(
+    rng: &mut R,
+    membership_pk: &MembershipProvingKey,
+    info: &PersonalInfo,
+    auth_path: &ComTreePath,
+    root: &ComTreeRoot,
+    cache: &'a mut Option<MembershipProofs>,
+) -> Result<&'a MembershipProofs, Error> {
+    if !matches!(cache, Some(proofs) if proofs.root == *root) {
+        *cache = Some(prove_membership(rng, membership_pk, info, auth_path, root)?);
+    }
+    Ok(cache.as_ref().unwrap())
+}
+
+/// A proof that a credential in the issuer's tree satisfies a verifier's policy. It doesn't say
+/// which credential.
+#[derive(Clone, CanonicalSerialize, CanonicalDeserialize)]
+pub struct ShowProof {
+    /// The root of the issuer's tree that the credential is shown to be in
+    pub root: ComTreeRoot,
+    /// The challenge the verifier issued for this show. The proof is bound to it.
+    pub challenge: Fr,
+    /// The predicate and membership proofs, linked through the hidden credential
+    pub proof: LinkProof,
+}
+
+/// A holder proves that their credential satisfies the given policy, and is in the issuer's tree
+/// with the given root. `pk` must be the proving key for the policy's circuit, and `auth_path` the
+/// credential's auth path in that tree.
+#[allow(clippy::too_many_arguments)]
+pub fn prove_show<R: Rng>(
+    rng: &mut R,
+    pk: &PredProvingKey,
+    membership_pk: &MembershipProvingKey,
+    info: &PersonalInfo,
+    auth_path: &ComTreePath,
+    root: &ComTreeRoot,
+    policy: &ShowPolicy,
+    challenge: Fr,
+) -> Result<ShowProof, Error> {
+    let membership = prove_membership(rng, membership_pk, info, auth_path, root)?;
+    prove_show_with_membership(
+        rng,
+        pk,
+        membership_pk,
+        info,
+        auth_path,
+        &membership,
+        policy,
+        challenge,
+    )
+}
+
+/// Like `prove_show`, but with membership proofs the holder already has, e.g., from
+/// `cached_membership_proofs`. The show is against their root.
+#[allow(clippy::too_many_arguments)]
+pub fn prove_show_with_membership<R: Rng>(
+    rng: &mut R,
+    pk: &PredProvingKey,
+    membership_pk: &MembershipProvingKey,
+    info: &PersonalInfo,
+    auth_path: &ComTreePath,
+    membership: &MembershipProofs,
+    policy: &ShowPolicy,
+    challenge: Fr,
+) -> Result<ShowProof, Error> {
+    let proof = match policy.circuit {
+        Circuit::ShowAge => {
+            let checker = policy.show_checker(policy.age_checker(), challenge);
+            prove_linked(rng, pk, membership_pk, info, auth_path, membership, checker)?
+        }
+        Circuit::ShowExpiry => {
+            let checker = policy.show_checker(policy.expiry_checker(), challenge);
+            prove_linked(rng, pk, membership_pk, info, auth_path, membership, checker)?
+        }
+        Circuit::ShowAgeAndExpiry => {
+            let checker = policy.show_checker(policy.age_and_expiry_checker(), challenge);
+            prove_linked(rng, pk, membership_pk, info, auth_path, membership, checker)?
+        }
+        other => return Err(unsupported(other)),
+    };
+
+    Ok(ShowProof {
+        root: membership.root,
+        challenge,
+        proof,
+    })
+}
+
+/// A verifier checks a show against its policy, and checks that the shown credential is in the
+/// issuer's tree. `vk` must be the verifying key for the policy's circuit, `root` the root of the
+/// issuer's tree as of its latest signed statement, and `challenge` the challenge the verifier
+/// issued.
+pub fn verify_show(
+    vk: &PredVerifyingKey,
+    membership_vk: &MembershipVerifyingKey,
+    show: &ShowProof,
+    policy: &ShowPolicy,
+    root: &ComTreeRoot,
+    challenge: Fr,
+) -> Result<(), Error> {
+    if show.challenge != challenge {
+        return Err(Error::ProofFailure(
+            "show was made for a different challenge".to_string(),
+        ));
+    }
+    if show.root != *root {
+        return Err(Error::ProofFailure(
+            "show was made against a different root of the issuer's tree".to_string(),
+        ));
+    }
+
+    // The proof is checked against the verifier's own challenge, identifier, and root, not the
+    // holder's
+    let verified = match policy.circuit {
+        Circuit::ShowAge => {
+            let checker = policy.show_checker(policy.age_checker(), challenge);
+            verify_link(&link_vk(vk, membership_vk, root, &checker), &show.proof)
+        }
+        Circuit::ShowExpiry => {
+            let checker = policy.show_checker(policy.expiry_checker(), challenge);
+            verify_link(&link_vk(vk, membership_vk, root, &checker), &show.proof)
+        }
+        Circuit::ShowAgeAndExpiry => {
+            let checker = policy.show_checker(policy.age_and_expiry_checker(), challenge);
+            verify_link(&link_vk(vk, membership_vk, root, &checker), &show.proof)
+        }
+        other => return Err(unsupported(other)),
+    };
+    if !verified {
+        return Err(Error::ProofFailure("show proof did not verify".to_string()));
+    }
+
+    Ok(())
+}
+
+// Proves that the hidden credential satisfies the predicate, and links that proof to its membership
+// proofs
+fn prove_linked<R: Rng, P>(
+    rng: &mut R,
+    pk: &PredProvingKey,
+    membership_pk: &MembershipProvingKey,
+    info: &PersonalInfo,
+    auth_path: &ComTreePath,
+    membership: &MembershipProofs,
+    checker: P,
+) -> Result<LinkProof, Error>
+where
+    P: PredicateChecker<Fr, PersonalInfo, PersonalInfoVar, PassportComScheme, PassportComSchemeG>,
+{
+    let root = &membership.root;
+    let vk = link_vk(
+        &pk.prepare_verifying_key(),
+        &membership_pk.prepare_verifying_key(),
+        root,
+        &checker,
+    );
+
+    let pred_proof = prove_pred(rng, pk, checker, info.clone(), auth_path)?;
+    let ctx = LinkProofCtx {
+        attrs_com: info.commit(),
+        merkle_root: *root,
+        forest_proof: membership.forest.clone(),
+        tree_proof: membership.tree.clone(),
+        pred_proofs: vec![pred_proof],
+        vk,
+    };
+    Ok(link_proofs(rng, &ctx))
+}
+
+// The key that links proofs about the given predicate and root are checked under
+fn link_vk<P>(
+    vk: &PredVerifyingKey,
+    membership_vk: &MembershipVerifyingKey,
+    root: &ComTreeRoot,
+    checker: &P,
+) -> LinkVerifyingKey
+where
+    P: PredicateChecker<Fr, PersonalInfo, PersonalInfoVar, PassportComScheme, PassportComSchemeG>,
+{
+    let mut pred_inputs = PredPublicInputs::default();
+    pred_inputs.prepare_pred_checker(vk, checker);
+
+    LinkVerifyingKey {
+        pred_inputs,
+        com_forest_roots: forest_roots(root),
+        forest_verif_key: membership_vk.forest.clone(),
+        tree_verif_key: membership_vk.tree.clone(),
+        pred_verif_keys: vec![vk.clone()],
+    }
+}
+
+fn forest_roots(root: &ComTreeRoot) -> ComForestRoots {
+    let mut roots = ComForestRoots::new(NUM_TREES);
+    roots.roots[0] = *root;
+    roots
+}
+
+/// The current date in UTC, as an integer whose base-10 representation is YYYYMMDD
+pub fn current_date() -> u32 {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    date_from_days(secs / 86400)
+}
+
+// Converts a number of days since 1970-01-01 to a date in the proleptic Gregorian calendar. This is
+// the civil_from_days algorithm from http://howardhinnant.github.io/date_algorithms.html
+fn date_from_days(days: u64) -> u32 {
+    // Shift the epoch to 0000-03-01, so leap days come at the end of a year
+    let days = days + 719468;
+    let era = days / 146097;
+    let day_of_era = days % 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+
+    (year * 10000 + month * 100 + day) as u32
+}
+
+/// Encodes a challenge for sending to a holder
+pub fn encode_challenge(challenge: &Fr) -> Result<String, Error> {
+    to_base64(challenge)
+}
+
+/// Decodes a challenge made by `encode_challenge`
+pub fn decode_challenge(challenge: &str) -> Result<Fr, Error> {
+    let bytes = base64::decode(challenge).map_err(Error::deser("couldn't decode challenge"))?;
+    Fr::deserialize_uncompressed(&bytes[..]).map_err(Error::deser("couldn't deserialize challenge"))
+}
+
+fn unsupported(circuit: Circuit) -> Error {
+    Error::Parse(format!("the {:?} circuit can't be shown", circuit))
+}
@@ -1,4 +1,10 @@
-use rsa::{padding::PaddingScheme, pkcs8::FromPublicKey, Hash, PublicKey, RsaPublicKey};
+use crate::error::Error;
+
+use rsa::{
+    padding::PaddingScheme,
+    pkcs8::{FromPublicKey, ToPublicKey},
+    Hash, PublicKey, RsaPublicKey,
+};
 
 // A PKCS#8 encoding of the US State Department's passport signing pubkey. This was a pain to
 // extract. See the below link for instructions
@@ -14,6 +20,8 @@ TADYn/CxmJVqhyL98MMhUuvLwopdpjcWuwXRThCpSbcvBNYTYmI/rQ0DgmFq/v7z
 1wIDAQAB
 -----END PUBLIC KEY-----";
 
+/// The pubkey that passport signatures are checked against, i.e., the trust anchor
+#[derive(Clone, Debug, PartialEq)]
 pub struct IssuerPubkey(RsaPublicKey);
 
 pub fn load_usa_pubkey() -> IssuerPubkey {
@@ -22,6 +30,19 @@ pub fn load_usa_pubkey() -> IssuerPubkey {
 }
 
 impl IssuerPubkey {
+    /// Parses a PEM-encoded SubjectPublicKeyInfo, i.e., a `-----BEGIN PUBLIC KEY-----` block
+    pub fn from_pem(pem: &str) -> Result<IssuerPubkey, Error> {
+        RsaPublicKey::from_public_key_pem(pem)
+            .map(IssuerPubkey)
+            .map_err(|e| Error::Parse(format!("couldn't parse trust anchor: {}", e)))
+    }
+
+    /// Encodes this pubkey as a PEM-encoded SubjectPublicKeyInfo
+    pub fn to_pem(&self) -> String {
+        // Encoding an RSA pubkey we already hold can't fail
+        self.0.to_public_key_pem().unwrap()
+    }
+
     #[must_use]
     pub fn verify(&self, sig: &[u8], hash: &[u8]) -> bool {
         self.0
@@ -35,3 +56,9 @@ impl IssuerPubkey {
             .is_ok()
     }
 }
+
+impl From<RsaPublicKey> for IssuerPubkey {
+    fn from(pubkey: RsaPublicKey) -> IssuerPubkey {
+        IssuerPubkey(pubkey)
+    }
+}
@@ -0,0 +1,163 @@
+//! Exports a Groth16 verifying key as a Solidity contract. This only makes sense over BN254, since
+//! that's the only pairing the EVM has precompiles for, so this module needs the `bn254` feature.
+
+use crate::{error::Error, groth16::groth16_vk, params::PredVerifyingKey};
+
+use std::fmt::Write;
+
+use ark_bn254::{Fq, Fq2, G1Affine, G2Affine};
+use ark_ff::{BigInteger, PrimeField, Zero};
+
+/// Generates a Solidity contract that verifies proofs under the given predicate verifying key. The
+/// contract's `verifyProof` takes the proof points and the proof's public inputs, in the order
+/// zkcreds feeds them to Groth16.
+pub fn solidity_verifier(vk: &PredVerifyingKey) -> Result<String, Error> {
+    let vk = groth16_vk(vk)?;
+    let num_inputs = vk.gamma_abc_g1.len() - 1;
+
+    let mut ic = String::new();
+    for (i, p) in vk.gamma_abc_g1.iter().enumerate() {
+        writeln!(ic, "        vk.ic[{}] = {};", i, g1(p)).unwrap();
+    }
+
+    Ok(VERIFIER_TEMPLATE
+        .replace("{{NUM_INPUTS}}", &num_inputs.to_string())
+        .replace("{{NUM_IC}}", &vk.gamma_abc_g1.len().to_string())
+        .replace("{{ALPHA}}", &g1(&vk.alpha_g1))
+        .replace("{{BETA}}", &g2(&vk.beta_g2))
+        .replace("{{GAMMA}}", &g2(&vk.gamma_g2))
+        .replace("{{DELTA}}", &g2(&vk.delta_g2))
+        .replace("{{IC}}", ic.trim_end()))
+}
+
+/// A base field element as a Solidity hex literal
+fn fq(x: &Fq) -> String {
+    let bytes = x.into_repr().to_bytes_be();
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    format!("0x{}", hex)
+}
+
+/// A G1 point as a Solidity `G1Point` constructor. The precompiles encode the point at infinity as
+/// (0, 0), which isn't how arkworks represents it, so it's written out separately.
+fn g1(p: &G1Affine) -> String {
+    if p.is_zero() {
+        return format!("G1Point({}, {})", fq(&Fq::zero()), fq(&Fq::zero()));
+    }
+    format!("G1Point({}, {})", fq(&p.x), fq(&p.y))
+}
+
+/// A G2 point as a Solidity `G2Point` constructor. The precompiles expect each coordinate's
+/// imaginary part first, and encode the point at infinity as all zeros.
+fn g2(p: &G2Affine) -> String {
+    let fq2 = |x: &Fq2| format!("[{}, {}]", fq(&x.c1), fq(&x.c0));
+    if p.is_zero() {
+        return format!("G2Point({}, {})", fq2(&Fq2::zero()), fq2(&Fq2::zero()));
+    }
+    format!("G2Point({}, {})", fq2(&p.x), fq2(&p.y))
+}
+
+const VERIFIER_TEMPLATE: &str = r#"// SPDX-License-Identifier: MIT
+// Generated by zkcreds-passport-cli. Do not edit.
+pragma solidity ^0.8.0;
+
+contract Verifier {
+    struct G1Point {
+        uint256 x;
+        uint256 y;
+    }
+
+    // Coordinates are [imaginary, real]
+    struct G2Point {
+        uint256[2] x;
+        uint256[2] y;
+    }
+
+    struct VerifyingKey {
+        G1Point alpha;
+        G2Point beta;
+        G2Point gamma;
+        G2Point delta;
+        G1Point[{{NUM_IC}}] ic;
+    }
+
+    uint256 constant FIELD_MODULUS =
+        21888242871839275222246405745257275088696311157297823662689037894645226208583;
+    uint256 constant SCALAR_MODULUS =
+        21888242871839275222246405745257275088548364400416034343698204186575808495617;
+
+    function verifyingKey() internal pure returns (VerifyingKey memory vk) {
+        vk.alpha = {{ALPHA}};
+        vk.beta = {{BETA}};
+        vk.gamma = {{GAMMA}};
+        vk.delta = {{DELTA}};
+{{IC}}
+    }
+
+    function negate(G1Point memory p) internal pure returns (G1Point memory) {
+        if (p.x == 0 && p.y == 0) {
+            return p;
+        }
+        return G1Point(p.x, FIELD_MODULUS - (p.y % FIELD_MODULUS));
+    }
+
+    function add(G1Point memory p, G1Point memory q) internal view returns (G1Point memory r) {
+        uint256[4] memory input = [p.x, p.y, q.x, q.y];
+        bool success;
+        assembly {
+            success := staticcall(gas(), 0x06, input, 0x80, r, 0x40)
+        }
+        require(success, "G1 addition failed");
+    }
+
+    function mul(G1Point memory p, uint256 s) internal view returns (G1Point memory r) {
+        uint256[3] memory input = [p.x, p.y, s];
+        bool success;
+        assembly {
+            success := staticcall(gas(), 0x07, input, 0x60, r, 0x40)
+        }
+        require(success, "G1 scalar multiplication failed");
+    }
+
+    // Checks that the product of the pairings e(a[i], b[i]) is 1
+    function pairing(G1Point[4] memory a, G2Point[4] memory b) internal view returns (bool) {
+        uint256[24] memory input;
+        for (uint256 i = 0; i < 4; i++) {
+            input[i * 6 + 0] = a[i].x;
+            input[i * 6 + 1] = a[i].y;
+            input[i * 6 + 2] = b[i].x[0];
+            input[i * 6 + 3] = b[i].x[1];
+            input[i * 6 + 4] = b[i].y[0];
+            input[i * 6 + 5] = b[i].y[1];
+        }
+        uint256[1] memory out;
+        bool success;
+        assembly {
+            success := staticcall(gas(), 0x08, input, 0x300, out, 0x20)
+        }
+        require(success, "pairing failed");
+        return out[0] != 0;
+    }
+
+    function verifyProof(
+        uint256[2] calldata a,
+        uint256[2][2] calldata b,
+        uint256[2] calldata c,
+        uint256[{{NUM_INPUTS}}] calldata input
+    ) external view returns (bool) {
+        VerifyingKey memory vk = verifyingKey();
+
+        // Compute the linear combination of the public inputs
+        G1Point memory x = vk.ic[0];
+        for (uint256 i = 0; i < input.length; i++) {
+            require(input[i] < SCALAR_MODULUS, "input out of range");
+            x = add(x, mul(vk.ic[i + 1], input[i]));
+        }
+
+        // Check e(A, B) = e(alpha, beta) * e(x, gamma) * e(C, delta)
+        return pairing(
+            [negate(G1Point(a[0], a[1])), vk.alpha, x, G1Point(c[0], c[1])],
+            [G2Point(b[0], b[1]), vk.beta, vk.gamma, vk.delta]
+        );
+    }
+}
+"#;
@@ -0,0 +1,398 @@
+//! Synthetic passports, for tests and demos. These are built the way a real passport is: a
+//! throwaway Country Signing CA (CSCA) certifies a throwaway Document Signer (DS), and the DS
+//! signs the passport's data groups. The resulting dump passes passive authentication, and
+//! issuance, under the DS pubkey as the trust anchor.
+
+use crate::{
+    error::Error,
+    params::{DATE_LEN, DOCUMENT_NUMBER_LEN, NAME_LEN, STATE_ID_LEN},
+    passport_dump::PassportDump,
+    sig_verif::IssuerPubkey,
+};
+
+use ark_std::rand::{CryptoRng, RngCore};
+use rsa::{padding::PaddingScheme, pkcs8::ToPublicKey, Hash, RsaPrivateKey, RsaPublicKey};
+use sha2::{Digest, Sha256};
+
+// DER tags
+const INTEGER: &[u8] = &[0x02];
+const BIT_STRING: &[u8] = &[0x03];
+const OCTET_STRING: &[u8] = &[0x04];
+const OID: &[u8] = &[0x06];
+const PRINTABLE_STRING: &[u8] = &[0x13];
+const UTC_TIME: &[u8] = &[0x17];
+const SEQUENCE: &[u8] = &[0x30];
+const SET: &[u8] = &[0x31];
+const EXPLICIT_0: &[u8] = &[0xa0];
+
+// ICAO doc 9303, part 10, §4.7 tags
+const DG1_TAG: &[u8] = &[0x61];
+const MRZ_TAG: &[u8] = &[0x5f, 0x1f];
+const DG2_TAG: &[u8] = &[0x75];
+
+// DER-encoded OIDs
+const SHA256_OID: &[u8] = &[0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x01];
+const SHA256_WITH_RSA_OID: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x0b];
+const CONTENT_TYPE_OID: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x09, 0x03];
+const MESSAGE_DIGEST_OID: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x09, 0x04];
+const SIGNING_TIME_OID: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x09, 0x05];
+const LDS_SECURITY_OBJECT_OID: &[u8] = &[0x67, 0x81, 0x08, 0x01, 0x01, 0x01];
+const COMMON_NAME_OID: &[u8] = &[0x55, 0x04, 0x03];
+const ORGANIZATION_OID: &[u8] = &[0x55, 0x04, 0x0a];
+
+// Fixed timestamps, in UTCTime format. These are chosen so that the default issuance policy
+// considers everything current.
+const SIGNING_TIME: &[u8] = b"220101000000Z";
+const CERT_NOT_BEFORE: &[u8] = b"200101000000Z";
+const CERT_NOT_AFTER: &[u8] = b"320101000000Z";
+
+// The number of random bytes standing in for the facial image in DG2
+const FAKE_BIOMETRICS_LEN: usize = 1024;
+
+/// The contents of a synthetic passport's MRZ, and the size of the keys that sign it
+#[derive(Clone, Debug)]
+pub struct TestPassportParams {
+    /// The 3-letter code of the issuing state
+    pub issuer: String,
+    /// The 3-letter code of the holder's nationality
+    pub nationality: String,
+    /// The holder's name in MRZ form, e.g., `DOE<<JANE`. At most 39 characters.
+    pub name: String,
+    /// At most 9 characters
+    pub document_number: String,
+    /// The holder's date of birth, in the form YYMMDD
+    pub dob: String,
+    /// The passport's expiry date, in the form YYMMDD
+    pub expiry: String,
+    /// `M`, `F`, or `<`
+    pub sex: char,
+    /// The modulus size of the CSCA and DS keys, in bits
+    pub key_bits: usize,
+}
+
+impl Default for TestPassportParams {
+    /// A US passport that satisfies the default issuance policy
+    fn default() -> TestPassportParams {
+        TestPassportParams {
+            issuer: "USA".to_string(),
+            nationality: "USA".to_string(),
+            name: "DOE<<JANE".to_string(),
+            document_number: "123456789".to_string(),
+            dob: "900101".to_string(),
+            expiry: "300101".to_string(),
+            sex: 'F',
+            key_bits: 2048,
+        }
+    }
+}
+
+/// A synthetic passport dump and the pubkey it verifies under
+pub struct TestPassport {
+    pub dump: PassportDump,
+    /// The Document Signer's pubkey. Use this as the trust anchor in the issuance policy.
+    pub trust_anchor: IssuerPubkey,
+}
+
+/// Computes the check digit of an MRZ field, as an ASCII digit. See ICAO doc 9303, part 3, §4.9.
+pub fn mrz_check_digit(field: &[u8]) -> u8 {
+    const WEIGHTS: [u32; 3] = [7, 3, 1];
+
+    let sum: u32 = field
+        .iter()
+        .zip(WEIGHTS.iter().cycle())
+        .map(|(&c, w)| {
+            let val = match c {
+                b'0'..=b'9' => (c - b'0') as u32,
+                b'A'..=b'Z' => (c - b'A') as u32 + 10,
+                // The filler character '<' and anything else counts as 0
+                _ => 0,
+            };
+            val * w
+        })
+        .sum();
+
+    b'0' + (sum % 10) as u8
+}
+
+/// Makes a passport with the given MRZ contents, signed by a freshly generated Document Signer
+pub fn gen_test_passport<R: RngCore + CryptoRng>(
+    rng: &mut R,
+    params: &TestPassportParams,
+) -> Result<TestPassport, Error> {
+    let dg1 = make_dg1(params)?;
+    let dg2 = make_dg2(rng);
+    let pre_econtent = make_pre_econtent(rng, &dg1, &dg2);
+    let econtent = make_econtent(&pre_econtent);
+
+    // Make the signers. The CSCA only exists to certify the DS
+    let csca_key = gen_key(rng, params.key_bits)?;
+    let ds_key = gen_key(rng, params.key_bits)?;
+    let ds_pubkey = RsaPublicKey::from(&ds_key);
+    let cert = make_ds_cert(rng, &csca_key, &ds_pubkey)?;
+
+    // The DS signs the hash of econtent
+    let sig = sign(&ds_key, &econtent)?;
+
+    let dump = PassportDump {
+        dg1,
+        dg2,
+        pre_econtent,
+        econtent,
+        sig,
+        cert,
+        digest_alg: "sha256".to_string(),
+        sig_alg: "sha256WithRSAEncryption".to_string(),
+    };
+
+    Ok(TestPassport {
+        dump,
+        trust_anchor: IssuerPubkey::from(ds_pubkey),
+    })
+}
+
+/// DER-encodes a tag-length-value triple
+fn tlv(tag: &[u8], value: &[u8]) -> Vec<u8> {
+    let mut out = tag.to_vec();
+
+    // Short-form lengths fit in 7 bits. Long-form lengths are 0x80 | (number of length bytes),
+    // followed by the big-endian length with no leading zeros.
+    let len = value.len();
+    if len < 0x80 {
+        out.push(len as u8);
+    } else {
+        let len_bytes = len.to_be_bytes();
+        let first_nonzero = len_bytes.iter().position(|&b| b != 0).unwrap();
+        out.push(0x80 | (len_bytes.len() - first_nonzero) as u8);
+        out.extend_from_slice(&len_bytes[first_nonzero..]);
+    }
+
+    out.extend_from_slice(value);
+    out
+}
+
+/// DER-encodes an AlgorithmIdentifier with NULL parameters
+fn alg_id(oid: &[u8]) -> Vec<u8> {
+    tlv(SEQUENCE, &[tlv(OID, oid), vec![0x05, 0x00]].concat())
+}
+
+/// Pads an MRZ field to the given length with `<`, checking that it only has characters allowed in
+/// the MRZ
+fn mrz_field(what: &str, val: &str, len: usize) -> Result<Vec<u8>, Error> {
+    let is_mrz_char = |c: u8| c.is_ascii_uppercase() || c.is_ascii_digit() || c == b'<';
+    if val.len() > len || !val.bytes().all(is_mrz_char) {
+        return Err(Error::Parse(format!(
+            "{} must be at most {} characters of A-Z, 0-9, or <",
+            what, len
+        )));
+    }
+
+    let mut field = val.as_bytes().to_vec();
+    field.resize(len, b'<');
+    Ok(field)
+}
+
+/// Like `mrz_field`, but the field must be exactly `len` characters
+fn exact_mrz_field(what: &str, val: &str, len: usize) -> Result<Vec<u8>, Error> {
+    if val.len() != len {
+        return Err(Error::Parse(format!(
+            "{} must be exactly {} characters",
+            what, len
+        )));
+    }
+    mrz_field(what, val, len)
+}
+
+/// Makes a TD3 DG1 with valid check digits. See ICAO doc 9303, part 4, §4.2.2.
+fn make_dg1(params: &TestPassportParams) -> Result<Vec<u8>, Error> {
+    let issuer = exact_mrz_field("issuer", &params.issuer, STATE_ID_LEN)?;
+    let nationality = exact_mrz_field("nationality", &params.nationality, STATE_ID_LEN)?;
+    let name = mrz_field("name", &params.name, NAME_LEN)?;
+    let document_number = mrz_field(
+        "document number",
+        &params.document_number,
+        DOCUMENT_NUMBER_LEN,
+    )?;
+    let dob = exact_mrz_field("date of birth", &params.dob, DATE_LEN)?;
+    let expiry = exact_mrz_field("expiry", &params.expiry, DATE_LEN)?;
+    if !dob.iter().chain(expiry.iter()).all(u8::is_ascii_digit) {
+        return Err(Error::Parse("dates must be of the form YYMMDD".to_string()));
+    }
+    if !matches!(params.sex, 'M' | 'F' | '<') {
+        return Err(Error::Parse("sex must be M, F, or <".to_string()));
+    }
+    let optional_data = [b'<'; 14];
+
+    // The first line is the document type, issuer, and name
+    let mut mrz = b"P<".to_vec();
+    mrz.extend_from_slice(&issuer);
+    mrz.extend_from_slice(&name);
+
+    // The second line is everything else, with check digits
+    let mut line2 = Vec::new();
+    line2.extend_from_slice(&document_number);
+    line2.push(mrz_check_digit(&document_number));
+    line2.extend_from_slice(&nationality);
+    line2.extend_from_slice(&dob);
+    line2.push(mrz_check_digit(&dob));
+    line2.push(params.sex as u8);
+    line2.extend_from_slice(&expiry);
+    line2.push(mrz_check_digit(&expiry));
+    line2.extend_from_slice(&optional_data);
+    line2.push(mrz_check_digit(&optional_data));
+
+    // The composite check digit covers the document number, dates, and optional data, along with
+    // their check digits
+    let composite = [&line2[0..10], &line2[13..20], &line2[21..43]].concat();
+    line2.push(mrz_check_digit(&composite));
+    mrz.extend_from_slice(&line2);
+
+    Ok(tlv(DG1_TAG, &tlv(MRZ_TAG, &mrz)))
+}
+
+/// Makes a DG2 whose contents are random bytes. Nothing here parses DG2, it just gets hashed.
+fn make_dg2<R: RngCore>(rng: &mut R) -> Vec<u8> {
+    let mut biometrics = vec![0u8; FAKE_BIOMETRICS_LEN];
+    rng.fill_bytes(&mut biometrics);
+    tlv(DG2_TAG, &biometrics)
+}
+
+/// Makes the LDSSecurityObject holding the hashes of the data groups. Besides DG1 and DG2, this
+/// has random hashes for DG11 and DG12, like a US passport does. See ICAO doc 9303, part 10, §4.6.2.
+fn make_pre_econtent<R: RngCore>(rng: &mut R, dg1: &[u8], dg2: &[u8]) -> Vec<u8> {
+    let dg_hash = |num: u8, hash: &[u8]| {
+        tlv(
+            SEQUENCE,
+            &[tlv(INTEGER, &[num]), tlv(OCTET_STRING, hash)].concat(),
+        )
+    };
+    let mut random_hash = || {
+        let mut hash = [0u8; 32];
+        rng.fill_bytes(&mut hash);
+        hash
+    };
+    let dg11_hash = random_hash();
+    let dg12_hash = random_hash();
+
+    let dg_hashes = [
+        dg_hash(1, &Sha256::digest(dg1)),
+        dg_hash(2, &Sha256::digest(dg2)),
+        dg_hash(11, &dg11_hash),
+        dg_hash(12, &dg12_hash),
+    ]
+    .concat();
+
+    tlv(
+        SEQUENCE,
+        &[
+            tlv(INTEGER, &[0]),
+            alg_id(SHA256_OID),
+            tlv(SEQUENCE, &dg_hashes),
+        ]
+        .concat(),
+    )
+}
+
+/// Makes the signed attributes of the passport's SignedData. The messageDigest attribute holds the
+/// hash of pre-econtent. See RFC 5652, §5.3.
+fn make_econtent(pre_econtent: &[u8]) -> Vec<u8> {
+    let attr = |oid: &[u8], val: &[u8]| tlv(SEQUENCE, &[tlv(OID, oid), tlv(SET, val)].concat());
+
+    tlv(
+        SET,
+        &[
+            attr(CONTENT_TYPE_OID, &tlv(OID, LDS_SECURITY_OBJECT_OID)),
+            attr(SIGNING_TIME_OID, &tlv(UTC_TIME, SIGNING_TIME)),
+            attr(
+                MESSAGE_DIGEST_OID,
+                &tlv(OCTET_STRING, &Sha256::digest(pre_econtent)),
+            ),
+        ]
+        .concat(),
+    )
+}
+
+/// Makes an X.509 certificate for the DS pubkey, signed by the CSCA. See RFC 5280, §4.1.
+fn make_ds_cert<R: RngCore>(
+    rng: &mut R,
+    csca_key: &RsaPrivateKey,
+    ds_pubkey: &RsaPublicKey,
+) -> Result<Vec<u8>, Error> {
+    let name = |common_name: &[u8]| {
+        let rdn = |oid: &[u8], val: &[u8]| {
+            tlv(
+                SET,
+                &tlv(
+                    SEQUENCE,
+                    &[tlv(OID, oid), tlv(PRINTABLE_STRING, val)].concat(),
+                ),
+            )
+        };
+        tlv(
+            SEQUENCE,
+            &[
+                rdn(ORGANIZATION_OID, b"zkcreds-passport-cli test"),
+                rdn(COMMON_NAME_OID, common_name),
+            ]
+            .concat(),
+        )
+    };
+
+    // Serial numbers are positive integers, so clear the top bit. Set the next one so the
+    // encoding has no leading zero byte.
+    let mut serial = [0u8; 8];
+    rng.fill_bytes(&mut serial);
+    serial[0] = (serial[0] & 0x3f) | 0x40;
+
+    let spki = ds_pubkey
+        .to_public_key_der()
+        .map_err(|e| Error::Crypto(format!("couldn't encode DS pubkey: {}", e)))?;
+    let validity = tlv(
+        SEQUENCE,
+        &[
+            tlv(UTC_TIME, CERT_NOT_BEFORE),
+            tlv(UTC_TIME, CERT_NOT_AFTER),
+        ]
+        .concat(),
+    );
+    let tbs_cert = tlv(
+        SEQUENCE,
+        &[
+            tlv(EXPLICIT_0, &tlv(INTEGER, &[2])),
+            tlv(INTEGER, &serial),
+            alg_id(SHA256_WITH_RSA_OID),
+            name(b"Test CSCA"),
+            validity,
+            name(b"Test Document Signer"),
+            spki.as_ref().to_vec(),
+        ]
+        .concat(),
+    );
+
+    // BIT STRINGs begin with the number of unused bits in the last byte
+    let sig = sign(csca_key, &tbs_cert)?;
+    let sig_bits = [&[0u8][..], &sig].concat();
+
+    Ok(tlv(
+        SEQUENCE,
+        &[
+            tbs_cert,
+            alg_id(SHA256_WITH_RSA_OID),
+            tlv(BIT_STRING, &sig_bits),
+        ]
+        .concat(),
+    ))
+}
+
+fn gen_key<R: RngCore + CryptoRng>(rng: &mut R, bits: usize) -> Result<RsaPrivateKey, Error> {
+    RsaPrivateKey::new(rng, bits)
+        .map_err(|e| Error::Crypto(format!("couldn't generate {}-bit RSA key: {}", bits, e)))
+}
+
+/// Signs the SHA-256 hash of `msg` with RSA-PKCS1v1.5-SHA256
+fn sign(key: &RsaPrivateKey, msg: &[u8]) -> Result<Vec<u8>, Error> {
+    let padding = PaddingScheme::PKCS1v15Sign {
+        hash: Some(Hash::SHA2_256),
+    };
+    key.sign(padding, &Sha256::digest(msg))
+        .map_err(|e| Error::Crypto(format!("couldn't sign: {}", e)))
+}
@@ -0,0 +1,30 @@
+use crate::params::{ComTree, ComTreeWireFormat, PassportComScheme, MERKLE_CRH_PARAM};
+
+use zkcreds::Com;
+
+/// The log of the total number of credentials an issuer can issue
+pub const LOG2_NUM_LEAVES: u32 = 31;
+/// The log of the number of trees the issuer's credentials are split across
+pub const LOG2_NUM_TREES: u32 = 8;
+/// The height of each of the issuer's trees
+pub const TREE_HEIGHT: u32 = LOG2_NUM_LEAVES + 1 - LOG2_NUM_TREES;
+
+/// Makes an empty credential tree
+pub fn empty_tree() -> ComTree {
+    ComTree::empty(MERKLE_CRH_PARAM.clone(), TREE_HEIGHT)
+}
+
+/// Makes a credential tree whose i-th leaf is the i-th given credential
+pub fn tree_from_creds<'a>(creds: impl IntoIterator<Item = &'a Com<PassportComScheme>>) -> ComTree {
+    let mut tree = empty_tree();
+    for (i, cred) in creds.into_iter().enumerate() {
+        tree.insert(i as u64, cred);
+    }
+
+    tree
+}
+
+/// Adds the CRH params to a deserialized tree to make it a fully fledged `ComTree`
+pub fn tree_from_wire_format(raw_tree: ComTreeWireFormat) -> ComTree {
+    raw_tree.into_com_tree(MERKLE_CRH_PARAM.clone())
+}
@@ -0,0 +1,362 @@
+//! A local HTTP service for a relying party. It hands out challenges, and checks shows against its
+//! policy and against the root of the issuer's tree, which it gets from the latest signed root
+//! statement of an issuer service. The endpoints are
+//!
+//! | Method | Path         | Response                                                          |
+//! |--------|--------------|-------------------------------------------------------------------|
+//! | GET    | `/challenge` | A fresh challenge, the verifying key fingerprint, and the policy  |
+//! | POST   | `/show`      | The verdict on the show                                           |
+//!
+//! Shows can be in any format the CLI reads. Verdicts are JSON envelopes with the root the show was
+//! checked against, and errors are JSON objects with the error's `kind` and `msg`.
+
+use crate::{
+    error::Error,
+    http::{self, read_json, status_code, upstream_error, Response},
+    output::{
+        deser_untrusted_from_reader, fingerprint, to_base64, to_frame, Envelope, Frame, ObjectType,
+        Verdict,
+    },
+    params::{ComTreePath, ComTreeRoot, Fr, PredProvingKey, PredVerifyingKey},
+    passport_info::PersonalInfo,
+    root_statement::{Freshness, IssuerPublicKey, SignedRootStatement},
+    show::{
+        cached_membership_proofs, decode_challenge, encode_challenge, prove_show_with_membership,
+        verify_show, MembershipProofs, MembershipProvingKey, MembershipVerifyingKey, ShowPolicy,
+        ShowProof,
+    },
+};
+
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use ark_ff::UniformRand;
+use ark_serialize::CanonicalDeserialize;
+use ark_std::rand::Rng;
+use serde::{Deserialize, Serialize};
+
+// How many challenges can be outstanding at once. Past this, the oldest are forgotten, and shows
+// made for them are rejected.
+const MAX_PENDING_CHALLENGES: usize = 1024;
+
+/// The challenges a verifier has issued that haven't been used or expired yet. Every challenge is
+/// good for one show, within `ttl` of being issued. Each is issued for a date, which the show is
+/// checked against, so a show started just before midnight isn't checked against the next day.
+pub struct ChallengeStore {
+    ttl: Duration,
+    // In the order they were issued, which is also the order they expire in
+    pending: VecDeque<(Fr, u32, Instant)>,
+}
+
+impl ChallengeStore {
+    pub fn new(ttl: Duration) -> ChallengeStore {
+        ChallengeStore {
+            ttl,
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Makes a fresh challenge for the given date, as YYYYMMDD, and remembers it
+    pub fn issue<R: Rng>(&mut self, rng: &mut R, today: u32) -> Fr {
+        self.expire();
+        if self.pending.len() == MAX_PENDING_CHALLENGES {
+            self.pending.pop_front();
+        }
+
+        let challenge = Fr::rand(rng);
+        self.pending.push_back((challenge, today, Instant::now()));
+        challenge
+    }
+
+    /// Forgets the given challenge, and returns the date it was issued for. Fails if it was never
+    /// issued, or has been used or expired.
+    pub fn redeem(&mut self, challenge: &Fr) -> Result<u32, Error> {
+        self.expire();
+        let i = self
+            .pending
+            .iter()
+            .position(|(c, ..)| c == challenge)
+            .ok_or_else(|| {
+                Error::ProofFailure(
+                    "show wasn't made for an outstanding challenge, or the challenge expired"
+                        .to_string(),
+                )
+            })?;
+        let (_, today, _) = self.pending.remove(i).unwrap();
+
+        Ok(today)
+    }
+
+    fn expire(&mut self) {
+        let now = Instant::now();
+        while let Some((.., issued)) = self.pending.front() {
+            if now.duration_since(*issued) < self.ttl {
+                break;
+            }
+            self.pending.pop_front();
+        }
+    }
+}
+
+/// What a verifier sends a holder before a show
+#[derive(Serialize, Deserialize)]
+pub struct ShowRequest {
+    /// Base64-encoded challenge, which the show must be made for
+    pub challenge: String,
+    /// Hex-encoded SHA-256 hash of the verifying key the show must be made for
+    pub key_fingerprint: String,
+    pub policy: ShowPolicy,
+}
+
+/// A verifier's state: its keys, its policy, who and where its issuer is, and the challenges it has
+/// handed out
+pub struct VerifierService {
+    vk: PredVerifyingKey,
+    vk_fingerprint: String,
+    membership_vk: MembershipVerifyingKey,
+    policy: ShowPolicy,
+    issuer_url: String,
+    issuer_key: IssuerPublicKey,
+    freshness: Freshness,
+    // The epoch of the latest root statement seen from the issuer
+    issuer_epoch: Option<u64>,
+    challenges: ChallengeStore,
+}
+
+impl VerifierService {
+    /// Makes a verifier service. `vk` must be the verifying key for the policy's circuit,
+    /// `issuer_url` the base URL of an issuer service, and `issuer_key` the key that issuer signs
+    /// its root statements with. The issuer's statements are only used if they're as recent as
+    /// `freshness` requires. Challenges expire `challenge_ttl` after they're issued.
+    pub fn new(
+        vk: PredVerifyingKey,
+        membership_vk: MembershipVerifyingKey,
+        policy: ShowPolicy,
+        issuer_url: &str,
+        issuer_key: IssuerPublicKey,
+        freshness: Freshness,
+        challenge_ttl: Duration,
+    ) -> Result<VerifierService, Error> {
+        Ok(VerifierService {
+            vk_fingerprint: fingerprint(&vk)?,
+            vk,
+            membership_vk,
+            policy,
+            issuer_url: issuer_url.trim_end_matches('/').to_string(),
+            issuer_key,
+            freshness,
+            issuer_epoch: None,
+            challenges: ChallengeStore::new(challenge_ttl),
+        })
+    }
+
+    /// Handles a single HTTP request
+    pub fn handle(&mut self, method: &str, path: &str, body: &[u8]) -> Response {
+        match (method, path.trim_end_matches('/')) {
+            ("GET", "/challenge") => self.challenge(),
+            ("POST", "/show") => self.show(body),
+            _ => Response::not_found(method, path),
+        }
+    }
+
+    /// Serves requests on the given address until the process is killed
+    pub fn serve(mut self, addr: &str) -> Result<(), Error> {
+        http::serve(addr, |method, path, body| self.handle(method, path, body))
+    }
+
+    /// Issues a challenge under the policy as of today. Unless the policy has a fixed date, that's
+    /// the current date, so the age and expiry thresholds move with the clock.
+    fn challenge(&mut self) -> Response {
+        let policy = self.policy.dated();
+        let challenge = self
+            .challenges
+            .issue(&mut rand::thread_rng(), policy.today());
+        let res = encode_challenge(&challenge).map(|challenge| ShowRequest {
+            challenge,
+            key_fingerprint: self.vk_fingerprint.clone(),
+            policy,
+        });
+        match res {
+            Ok(req) => Response::json(200, &req),
+            Err(e) => Response::error(&e),
+        }
+    }
+
+    /// Checks the show, and returns a verdict either way
+    fn show(&mut self, body: &[u8]) -> Response {
+        let res = self.try_show(body);
+        let status = res.as_ref().map_or_else(status_code, |_| 200);
+        let mut envelope = Envelope::new(ObjectType::ShowProof)
+            .key_fingerprint(self.vk_fingerprint.clone())
+            .verdict(
+                res.as_ref()
+                    .map_or_else(Verdict::from, |_| Verdict::Accepted),
+            );
+        envelope.root = res.ok();
+        Response::envelope(status, &envelope).unwrap_or_else(|e| Response::error(&e))
+    }
+
+    /// Checks the show, and returns the root it was checked against
+    fn try_show(&mut self, mut body: &[u8]) -> Result<String, Error> {
+        let frame = Frame::read(&mut body, ObjectType::ShowProof)?;
+        frame.check_key(&self.vk_fingerprint)?;
+        let show: ShowProof = frame.deser_untrusted()?;
+
+        // Every challenge is good for one show, even if the show is rejected. The show is checked
+        // under the policy as of the date the challenge was issued for.
+        let today = self.challenges.redeem(&show.challenge)?;
+        let policy = ShowPolicy {
+            today: Some(today),
+            ..self.policy.clone()
+        };
+
+        let root = self.issuer_statement()?.statement.root;
+        verify_show(
+            &self.vk,
+            &self.membership_vk,
+            &show,
+            &policy,
+            &root,
+            show.challenge,
+        )?;
+        to_base64(&root)
+    }
+
+    /// Gets the issuer's latest root statement, and checks that it's signed by the issuer, is fresh
+    /// enough, and isn't older than any statement seen before
+    fn issuer_statement(&mut self) -> Result<SignedRootStatement, Error> {
+        let payload = get_envelope(&format!("{}/root-statement", self.issuer_url))?
+            .payload
+            .ok_or_else(|| {
+                Error::Deserialization("issuer's root statement has no payload".to_string())
+            })?;
+        let signed: SignedRootStatement =
+            deser_untrusted_from_reader(&mut payload.as_bytes(), ObjectType::RootStatement)?;
+        let epoch = signed
+            .verify_fresh(&self.issuer_key, &self.freshness)?
+            .epoch;
+
+        if self.issuer_epoch.map_or(false, |last| epoch < last) {
+            return Err(Error::ProofFailure(format!(
+                "issuer's root statement is from epoch {}, but one from epoch {} was already seen",
+                epoch,
+                self.issuer_epoch.unwrap()
+            )));
+        }
+        self.issuer_epoch = Some(epoch);
+
+        Ok(signed)
+    }
+}
+
+/// A holder gets a challenge from the verifier service at `url`, shows their credential for it,
+/// and returns the verifier's verdict. The credential's auth path, and the root it's under, are
+/// fetched from the issuer service at `issuer_url`. `pk` must be the proving key for the
+/// verifier's policy. The membership proofs in `cache` are reused if they're for the issuer's
+/// current root, and replaced with fresh ones otherwise.
+#[allow(clippy::too_many_arguments)]
+pub fn present<R: Rng>(
+    rng: &mut R,
+    url: &str,
+    issuer_url: &str,
+    pk: &PredProvingKey,
+    membership_pk: &MembershipProvingKey,
+    info: &PersonalInfo,
+    leaf_index: u64,
+    cache: &mut Option<MembershipProofs>,
+) -> Result<Envelope, Error> {
+    let url = url.trim_end_matches('/');
+    let (status, body) = http::get(&format!("{}/challenge", url))?;
+    let req: ShowRequest = read_json(&format!("{}/challenge", url), status, &body)?;
+
+    let vk_fingerprint = fingerprint(&pk.prepare_verifying_key())?;
+    if req.key_fingerprint != vk_fingerprint {
+        return Err(Error::ProofFailure(format!(
+            "the verifier wants a show for verifying key {}, but this proving key is for {}",
+            req.key_fingerprint, vk_fingerprint
+        )));
+    }
+
+    // Only show to the verifier we're talking to. Otherwise a verifier could pass on another
+    // verifier's challenge and policy, and relay the show to it as if it were the holder.
+    let verifier_id = expected_verifier_id(url)?;
+    if req.policy.verifier_id != verifier_id {
+        return Err(Error::ProofFailure(format!(
+            "the verifier at {} wants a show for verifier {:?}, but its identifier is {:?}",
+            url, req.policy.verifier_id, verifier_id
+        )));
+    }
+
+    let (auth_path, root) = issuer_auth_path(issuer_url, leaf_index)?;
+    let challenge = decode_challenge(&req.challenge)?;
+    let membership = cached_membership_proofs(rng, membership_pk, info, &auth_path, &root, cache)?;
+    let show = prove_show_with_membership(
+        rng,
+        pk,
+        membership_pk,
+        info,
+        &auth_path,
+        membership,
+        &req.policy,
+        challenge,
+    )?;
+    let body = base64::encode(to_frame(
+        ObjectType::ShowProof,
+        Some(&vk_fingerprint),
+        &show,
+    )?);
+
+    // Rejections come back as envelopes too, so only a response that isn't one is an error
+    let (status, body) = http::post(&format!("{}/show", url), body.as_bytes())?;
+    serde_json::from_slice(&body)
+        .map_err(|_| upstream_error(&format!("{}/show", url), status, &body))
+}
+
+/// The identifier of the verifier service at `url`, which is the URL's host. For example, the
+/// verifier at `https://example.com:8443/verify` must use the identifier `example.com`.
+pub fn expected_verifier_id(url: &str) -> Result<String, Error> {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or_default();
+    let host_port = authority.rsplit('@').next().unwrap_or_default();
+    let host = match host_port.strip_prefix('[') {
+        // An IPv6 address, like [::1]:8081
+        Some(addr) => addr.split(']').next().unwrap_or_default(),
+        None => host_port.split(':').next().unwrap_or_default(),
+    };
+
+    if host.is_empty() {
+        return Err(Error::Parse(format!("verifier URL {:?} has no host", url)));
+    }
+    Ok(host.to_ascii_lowercase())
+}
+
+/// Gets the auth path of the credential at the given leaf index from an issuer service, along with
+/// the root it's under. The issuer is a different party, so this is strict.
+fn issuer_auth_path(
+    issuer_url: &str,
+    leaf_index: u64,
+) -> Result<(ComTreePath, ComTreeRoot), Error> {
+    let url = format!(
+        "{}/auth-path/{}",
+        issuer_url.trim_end_matches('/'),
+        leaf_index
+    );
+    let envelope = get_envelope(&url)?;
+    let missing = |what: &str| Error::Deserialization(format!("{} has no {}", url, what));
+
+    let payload = envelope.payload.ok_or_else(|| missing("payload"))?;
+    let auth_path = deser_untrusted_from_reader(&mut payload.as_bytes(), ObjectType::AuthPath)?;
+    let root = envelope.root.ok_or_else(|| missing("root"))?;
+    let root = base64::decode(root).map_err(Error::deser("couldn't decode the issuer's root"))?;
+    let root = ComTreeRoot::deserialize_uncompressed(&root[..])
+        .map_err(Error::deser("couldn't deserialize the issuer's root"))?;
+
+    Ok((auth_path, root))
+}
+
+fn get_envelope(url: &str) -> Result<Envelope, Error> {
+    let (status, body) = http::get(url)?;
+    read_json(url, status, &body)
+}
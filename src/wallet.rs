@@ -0,0 +1,239 @@
+//! The holder's wallet. It keeps the opening of each of the holder's credentials, i.e., their
+//! personal info and commitment nonce, along with its leaf index and, if the holder has them, its
+//! auth path and its membership proofs for the issuer's latest root. The openings are as sensitive
+//! as the passport itself, so the whole wallet is encrypted with ChaCha20-Poly1305 under a key
+//! derived from a passphrase with Argon2id.
+
+use crate::{
+    error::Error,
+    output::{Envelope, Frame, ObjectType, OutputFormat},
+    params::ComTreePath,
+    passport_info::PersonalInfo,
+    show::MembershipProofs,
+};
+
+use std::{
+    fs::{self, File},
+    io::{self, BufRead},
+    path::Path,
+};
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use chacha20poly1305::{
+    aead::{Aead, KeyInit},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use rand::{CryptoRng, Rng};
+
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+/// A credential in the wallet
+#[derive(Clone, CanonicalSerialize, CanonicalDeserialize)]
+pub struct WalletCred {
+    // The name the holder gave the credential. This is always UTF-8.
+    name: Vec<u8>,
+    pub info: PersonalInfo,
+    /// The credential's leaf index in the issuer's tree. This is `None` until the issuer grants
+    /// the credential.
+    pub leaf_index: Option<u64>,
+    pub auth_path: Option<ComTreePath>,
+    /// The credential's membership proofs for the last root it was shown against
+    pub membership_proofs: Option<MembershipProofs>,
+}
+
+impl WalletCred {
+    pub fn new(
+        name: &str,
+        info: PersonalInfo,
+        leaf_index: Option<u64>,
+        auth_path: Option<ComTreePath>,
+    ) -> WalletCred {
+        WalletCred {
+            name: name.as_bytes().to_vec(),
+            info,
+            leaf_index,
+            auth_path,
+            membership_proofs: None,
+        }
+    }
+
+    pub fn name(&self) -> String {
+        String::from_utf8_lossy(&self.name).into_owned()
+    }
+}
+
+/// The decrypted contents of a wallet
+#[derive(Default, CanonicalSerialize, CanonicalDeserialize)]
+pub struct Wallet {
+    pub creds: Vec<WalletCred>,
+}
+
+/// A wallet as it's stored on disk
+#[derive(CanonicalSerialize, CanonicalDeserialize)]
+struct SealedWallet {
+    // The Argon2id memory cost in KiB, number of passes, and degree of parallelism the key was
+    // derived with. They're kept so that changing the defaults doesn't lock anyone out.
+    m_cost: u32,
+    t_cost: u32,
+    p_cost: u32,
+    salt: Vec<u8>,
+    nonce: Vec<u8>,
+    ciphertext: Vec<u8>,
+}
+
+impl Wallet {
+    /// Makes an empty wallet at the given path. Fails if there's already a file there.
+    pub fn init<R: Rng + CryptoRng>(
+        rng: &mut R,
+        path: &Path,
+        passphrase: &str,
+    ) -> Result<(), Error> {
+        if path.exists() {
+            return Err(Error::Io(
+                "couldn't create wallet".to_string(),
+                io::Error::new(io::ErrorKind::AlreadyExists, "file exists"),
+            ));
+        }
+
+        Wallet::default().save(rng, path, passphrase)
+    }
+
+    /// Opens and decrypts the wallet at the given path
+    pub fn open(path: &Path, passphrase: &str) -> Result<Wallet, Error> {
+        let sealed: SealedWallet = Frame::open(path, ObjectType::Wallet)?.deser_untrusted()?;
+        if sealed.salt.len() != SALT_LEN || sealed.nonce.len() != NONCE_LEN {
+            return Err(Error::Deserialization("malformed wallet".to_string()));
+        }
+
+        let params = Params::new(sealed.m_cost, sealed.t_cost, sealed.p_cost, None)
+            .map_err(|_| Error::Deserialization("malformed wallet".to_string()))?;
+        let cipher = ChaCha20Poly1305::new(&derive_key(passphrase, &sealed.salt, params)?);
+        let plaintext = cipher
+            .decrypt(
+                Nonce::from_slice(&sealed.nonce),
+                sealed.ciphertext.as_slice(),
+            )
+            .map_err(|_| {
+                Error::Deserialization(
+                    "couldn't decrypt wallet: the passphrase is wrong, or the wallet is corrupted"
+                        .to_string(),
+                )
+            })?;
+
+        // The ciphertext is authenticated, so the contents are exactly what we wrote
+        Wallet::deserialize(plaintext.as_slice())
+            .map_err(Error::deser("couldn't deserialize wallet"))
+    }
+
+    /// Encrypts the wallet under a fresh salt and nonce, and writes it to the given path. The old
+    /// wallet is only replaced once the new one is completely written.
+    pub fn save<R: Rng + CryptoRng>(
+        &self,
+        rng: &mut R,
+        path: &Path,
+        passphrase: &str,
+    ) -> Result<(), Error> {
+        let mut plaintext = Vec::new();
+        self.serialize(&mut plaintext)?;
+
+        let mut salt = vec![0u8; SALT_LEN];
+        let mut nonce = vec![0u8; NONCE_LEN];
+        rng.fill_bytes(&mut salt);
+        rng.fill_bytes(&mut nonce);
+        let params = Params::default();
+        let cipher = ChaCha20Poly1305::new(&derive_key(passphrase, &salt, params.clone())?);
+        let ciphertext = cipher
+            .encrypt(Nonce::from_slice(&nonce), plaintext.as_slice())
+            .map_err(|_| Error::Crypto("couldn't encrypt wallet".to_string()))?;
+        let sealed = SealedWallet {
+            m_cost: params.m_cost(),
+            t_cost: params.t_cost(),
+            p_cost: params.p_cost(),
+            salt,
+            nonce,
+            ciphertext,
+        };
+
+        let tmp_path = path.with_extension("tmp");
+        let mut tmp_file =
+            File::create(&tmp_path).map_err(Error::io("couldn't create wallet file"))?;
+        Envelope::new(ObjectType::Wallet)
+            .payload(&sealed)?
+            .write(OutputFormat::Human, &mut tmp_file)?;
+        tmp_file
+            .sync_all()
+            .map_err(Error::io("couldn't write wallet file"))?;
+        fs::rename(&tmp_path, path).map_err(Error::io("couldn't replace wallet file"))
+    }
+
+    /// Adds a credential to the wallet. Fails if there's already one with the same name.
+    pub fn insert(&mut self, cred: WalletCred) -> Result<(), Error> {
+        if self.creds.iter().any(|c| c.name == cred.name) {
+            return Err(Error::Parse(format!(
+                "the wallet already has a credential named {:?}",
+                cred.name()
+            )));
+        }
+
+        self.creds.push(cred);
+        Ok(())
+    }
+
+    /// Returns the credential with the given name. If no name is given, returns the only
+    /// credential in the wallet, and fails if there are more.
+    pub fn get(&self, name: Option<&str>) -> Result<&WalletCred, Error> {
+        let i = self.position(name)?;
+        Ok(&self.creds[i])
+    }
+
+    /// Like `get`, but the credential can be changed
+    pub fn get_mut(&mut self, name: Option<&str>) -> Result<&mut WalletCred, Error> {
+        let i = self.position(name)?;
+        Ok(&mut self.creds[i])
+    }
+
+    fn position(&self, name: Option<&str>) -> Result<usize, Error> {
+        match name {
+            Some(name) => self
+                .creds
+                .iter()
+                .position(|c| c.name == name.as_bytes())
+                .ok_or_else(|| {
+                    Error::Parse(format!("the wallet has no credential named {:?}", name))
+                }),
+            None => match self.creds.as_slice() {
+                [_] => Ok(0),
+                [] => Err(Error::Parse("the wallet has no credentials".to_string())),
+                _ => Err(Error::Parse(
+                    "the wallet has more than one credential, so one must be named".to_string(),
+                )),
+            },
+        }
+    }
+}
+
+/// Reads a passphrase from the first line of the given file
+pub fn read_passphrase(path: &Path) -> Result<String, Error> {
+    let file = File::open(path).map_err(Error::io("couldn't open passphrase file"))?;
+    let mut passphrase = String::new();
+    io::BufReader::new(file)
+        .read_line(&mut passphrase)
+        .map_err(Error::io("couldn't read passphrase file"))?;
+
+    let passphrase = passphrase.trim_end_matches(&['\r', '\n'][..]);
+    if passphrase.is_empty() {
+        return Err(Error::Parse("the passphrase is empty".to_string()));
+    }
+    Ok(passphrase.to_string())
+}
+
+/// Derives the wallet key from the passphrase and salt with Argon2id
+fn derive_key(passphrase: &str, salt: &[u8], params: Params) -> Result<Key, Error> {
+    let mut key = Key::default();
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| Error::Crypto(format!("couldn't derive wallet key: {}", e)))?;
+    Ok(key)
+}
@@ -0,0 +1,57 @@
+//! Constraint count regression tests. If a change pushes a circuit over its limit, either find the
+//! regression with `circuit-stats` or, if the growth is intended, raise the limit here.
+
+use zkcreds_passport_cli::circuit_stats::{circuit_stats, Circuit};
+
+// Upper bounds on the number of constraints in each circuit. These leave some headroom, so they
+// catch regressions, not every small change. The issuance circuit is dominated by its eight
+// in-circuit SHA-256 compression function calls, at about 30k constraints each. Two of them
+// compute the issuance nullifier, since its 64-byte preimage doesn't leave room for padding in a
+// single block.
+const MAX_CONSTRAINTS: [(Circuit, usize); 10] = [
+    (Circuit::Issuance, 260_000),
+    (Circuit::Age, 10_000),
+    (Circuit::Expiry, 10_000),
+    (Circuit::Face, 10_000),
+    (Circuit::AgeFaceExpiry, 15_000),
+    (Circuit::AgeAndExpiry, 15_000),
+    (Circuit::AgeMultishowExpiry, 50_000),
+    (Circuit::ShowAge, 10_000),
+    (Circuit::ShowExpiry, 10_000),
+    (Circuit::ShowAgeAndExpiry, 15_000),
+];
+
+#[test]
+fn constraint_counts_dont_regress() {
+    for (circuit, max) in MAX_CONSTRAINTS {
+        let stats = circuit_stats(circuit, 1).unwrap();
+        assert!(
+            stats.num_constraints <= max,
+            "{:?} has {} constraints, more than the limit of {}",
+            circuit,
+            stats.num_constraints,
+            max
+        );
+    }
+}
+
+#[test]
+fn constraints_are_attributed_to_namespaces() {
+    let stats = circuit_stats(Circuit::Issuance, 1).unwrap();
+    let count = |path: &str| {
+        stats
+            .namespaces
+            .iter()
+            .find(|ns| ns.path == path)
+            .map(|ns| ns.constraints)
+            .unwrap_or_else(|| panic!("namespace {} is missing", path))
+    };
+
+    // Every hash costs something, and the top-level namespaces can't account for more
+    // constraints than there are
+    for hash in ["dg1 hash", "pre-econtent hash", "econtent hash check"] {
+        assert!(count(hash) > 0);
+    }
+    let top_level_total: usize = stats.namespaces.iter().map(|ns| ns.constraints).sum();
+    assert!(top_level_total <= stats.num_constraints);
+}
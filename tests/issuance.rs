@@ -0,0 +1,321 @@
+//! End-to-end tests of issuance over synthetic passports
+
+use zkcreds_passport_cli::{
+    check_issuance, check_issuance_batch, gen_issuance_crs,
+    issuance::batch_verify_birth,
+    issuance_checker::PassportHashChecker,
+    issuer_db::IssuerDb,
+    output::{deser_untrusted_from_reader, to_frame, ObjectType},
+    params::{PredProvingKey, PredVerifyingKey, MAX_SIG_LEN},
+    passive_auth::PassiveAuthStep,
+    test_passport::{gen_test_passport, TestPassport, TestPassportParams},
+    tree::{empty_tree, tree_from_creds, tree_from_wire_format},
+    user_req_issuance, Error, IssuancePolicy, IssuanceReq,
+};
+
+use ark_serialize::CanonicalSerialize;
+use lazy_static::lazy_static;
+
+lazy_static! {
+    // Generating the CRS is slow, so every test shares one
+    static ref CRS: (PredProvingKey, PredVerifyingKey) =
+        gen_issuance_crs(&mut rand::thread_rng()).unwrap();
+}
+
+/// Makes a synthetic passport with the given params. Keys are small so the tests run quickly.
+fn passport(params: TestPassportParams) -> TestPassport {
+    let params = TestPassportParams {
+        key_bits: 1024,
+        ..params
+    };
+    gen_test_passport(&mut rand::thread_rng(), &params).unwrap()
+}
+
+/// The default policy, but trusting the given passport's signer
+fn policy_for(passport: &TestPassport) -> IssuancePolicy {
+    IssuancePolicy {
+        trust_anchor: passport.trust_anchor.clone(),
+        ..Default::default()
+    }
+}
+
+/// Makes an issuance request for the given passport under the given policy
+fn req_issuance(passport: &TestPassport, policy: &IssuancePolicy) -> Result<IssuanceReq, Error> {
+    let (pk, _) = &*CRS;
+    user_req_issuance(&mut rand::thread_rng(), &passport.dump, pk, policy).map(|(_, req)| req)
+}
+
+#[test]
+fn issuance_end_to_end() {
+    let (_, vk) = &*CRS;
+
+    // Issue credentials to two different people
+    let alice = passport(TestPassportParams::default());
+    let bob = passport(TestPassportParams {
+        name: "ROE<<RICHARD".to_string(),
+        document_number: "987654321".to_string(),
+        dob: "850615".to_string(),
+        ..Default::default()
+    });
+    let mut creds = Vec::new();
+    for holder in [&alice, &bob] {
+        let policy = policy_for(holder);
+        let req = req_issuance(holder, &policy).unwrap();
+        check_issuance(vk, &req, &policy).unwrap();
+        creds.push(req.attrs_com);
+    }
+
+    // Put them in a tree and make sure the root survives a round trip through the wire format
+    let tree = tree_from_creds(&creds);
+    let root = tree.root();
+    assert!(root != empty_tree().root());
+    assert!(tree_from_wire_format(tree.into_wire_format()).root() == root);
+}
+
+#[test]
+fn wrong_issuer_is_rejected() {
+    let (_, vk) = &*CRS;
+    let canadian = passport(TestPassportParams {
+        issuer: "CAN".to_string(),
+        nationality: "CAN".to_string(),
+        ..Default::default()
+    });
+
+    // The user can't request issuance under a USA-only policy
+    let policy = policy_for(&canadian);
+    let res = req_issuance(&canadian, &policy);
+    assert!(matches!(res, Err(Error::PolicyViolation(_))));
+
+    // A proof made for a CAN-only policy doesn't convince a USA-only issuer
+    let can_policy = IssuancePolicy {
+        issuing_state: *b"CAN",
+        ..policy.clone()
+    };
+    let req = req_issuance(&canadian, &can_policy).unwrap();
+    let res = check_issuance(vk, &req, &policy);
+    assert!(matches!(res, Err(Error::ProofFailure(_))));
+}
+
+#[test]
+fn expired_passport_is_rejected() {
+    let (_, vk) = &*CRS;
+    let expired = passport(TestPassportParams {
+        expiry: "200101".to_string(),
+        ..Default::default()
+    });
+
+    // The user can't request issuance if their passport has expired
+    let policy = policy_for(&expired);
+    let res = req_issuance(&expired, &policy);
+    assert!(matches!(res, Err(Error::PolicyViolation(_))));
+
+    // A proof made before the passport expired doesn't convince an issuer after it expired
+    let past_policy = IssuancePolicy {
+        today: 20190101,
+        ..policy.clone()
+    };
+    let req = req_issuance(&expired, &past_policy).unwrap();
+    let res = check_issuance(vk, &req, &policy);
+    assert!(matches!(res, Err(Error::ProofFailure(_))));
+}
+
+#[test]
+fn tampered_dg1_is_rejected() {
+    let mut tampered = passport(TestPassportParams::default());
+    let policy = policy_for(&tampered);
+
+    // Change the first letter of the holder's name
+    tampered.dump.dg1[10] = b'X';
+    let res = req_issuance(&tampered, &policy);
+    assert!(matches!(
+        res,
+        Err(Error::PassiveAuthFailure(PassiveAuthStep::Dg1Hash, _))
+    ));
+}
+
+#[test]
+fn wrong_signature_is_rejected() {
+    let (_, vk) = &*CRS;
+    let holder = passport(TestPassportParams::default());
+    let policy = policy_for(&holder);
+    let mut req = req_issuance(&holder, &policy).unwrap();
+
+    // A passport signed by someone other than the trust anchor is rejected
+    let stranger = passport(TestPassportParams::default());
+    let res = check_issuance(vk, &req, &policy_for(&stranger));
+    assert!(matches!(res, Err(Error::SignatureFailure)));
+
+    // So is a corrupted signature
+    req.sig[0] ^= 1;
+    let res = check_issuance(vk, &req, &policy);
+    assert!(matches!(res, Err(Error::SignatureFailure)));
+}
+
+#[test]
+fn tampered_econtent_prefix_is_rejected() {
+    let (_, vk) = &*CRS;
+    let holder = passport(TestPassportParams::default());
+    let policy = policy_for(&holder);
+
+    // The issuer hashes the prefix itself, so the proof doesn't hold for any other prefix
+    let mut req = req_issuance(&holder, &policy).unwrap();
+    req.econtent_prefix[0] ^= 1;
+    let res = check_issuance(vk, &req, &policy);
+    assert!(matches!(res, Err(Error::ProofFailure(_))));
+
+    req.econtent_prefix.pop();
+    let res = check_issuance(vk, &req, &policy);
+    assert!(matches!(res, Err(Error::Parse(_))));
+}
+
+#[test]
+fn swapped_commitment_is_rejected() {
+    let (_, vk) = &*CRS;
+    let alice = passport(TestPassportParams::default());
+    let bob = passport(TestPassportParams {
+        name: "ROE<<RICHARD".to_string(),
+        ..Default::default()
+    });
+    let mut alice_req = req_issuance(&alice, &policy_for(&alice)).unwrap();
+    let bob_req = req_issuance(&bob, &policy_for(&bob)).unwrap();
+
+    // Alice's proof doesn't open Bob's commitment
+    alice_req.attrs_com = bob_req.attrs_com;
+    let res = check_issuance(vk, &alice_req, &policy_for(&alice));
+    assert!(matches!(res, Err(Error::ProofFailure(_))));
+}
+
+#[test]
+fn batch_matches_individual_checks() {
+    let (_, vk) = &*CRS;
+    let alice = passport(TestPassportParams::default());
+    let policy = policy_for(&alice);
+    let good = req_issuance(&alice, &policy).unwrap();
+
+    // A request with a bad proof, one with a bad signature, and one with a malformed hash
+    let mut bad_proof = req_issuance(&alice, &policy).unwrap();
+    bad_proof.attrs_com = good.attrs_com;
+    let mut bad_sig = req_issuance(&alice, &policy).unwrap();
+    bad_sig.sig[0] ^= 1;
+    let mut bad_hash = req_issuance(&alice, &policy).unwrap();
+    bad_hash.econtent_hash.pop();
+
+    let reqs = [good, bad_proof, bad_sig, bad_hash];
+    let results = check_issuance_batch(&mut rand::thread_rng(), vk, &reqs, &policy).unwrap();
+    assert_eq!(results.len(), reqs.len());
+    assert!(results[0].is_ok());
+    assert!(matches!(results[1], Err(Error::ProofFailure(_))));
+    assert!(matches!(results[2], Err(Error::SignatureFailure)));
+    assert!(matches!(results[3], Err(Error::Parse(_))));
+
+    // A batch of good requests passes
+    let good_reqs = [
+        req_issuance(&alice, &policy).unwrap(),
+        req_issuance(&alice, &policy).unwrap(),
+    ];
+    let results = check_issuance_batch(&mut rand::thread_rng(), vk, &good_reqs, &policy).unwrap();
+    assert!(results.iter().all(Result::is_ok));
+
+    // It passes in the batch check itself, not just in the individual fallback
+    let checkers: Vec<_> = good_reqs
+        .iter()
+        .map(|req| {
+            PassportHashChecker::from_issuance_req(
+                req,
+                policy.issuing_state,
+                policy.today,
+                policy.max_valid_years,
+                policy.nullifier_key,
+            )
+            .unwrap()
+        })
+        .collect();
+    let batch: Vec<_> = good_reqs.iter().zip(&checkers).collect();
+    assert!(batch_verify_birth(&mut rand::thread_rng(), vk, &batch).unwrap());
+
+    // And a batch with a bad proof in it fails there
+    let mut bad_batch = batch.clone();
+    bad_batch.push((&reqs[1], &checkers[0]));
+    assert!(!batch_verify_birth(&mut rand::thread_rng(), vk, &bad_batch).unwrap());
+}
+
+#[test]
+fn malformed_requests_are_rejected() {
+    let holder = passport(TestPassportParams::default());
+    let req = req_issuance(&holder, &policy_for(&holder)).unwrap();
+    let deser = |bytes: &[u8]| {
+        let b64 = base64::encode(bytes);
+        deser_untrusted_from_reader::<_, IssuanceReq>(
+            &mut b64.as_bytes(),
+            ObjectType::IssuanceRequest,
+        )
+    };
+
+    // A well-formed request survives the round trip
+    let bytes = to_frame(ObjectType::IssuanceRequest, None, &req).unwrap();
+    assert!(deser(&bytes).is_ok());
+
+    // The commitment is followed by the length-prefixed econtent hash, econtent prefix,
+    // nullifier, and signature, then the proof. Claim a signature longer than the limit.
+    let sig_len_offset = bytes.len() - req.hash_proof.uncompressed_size() - req.sig.len() - 8;
+    let mut long_sig = bytes.clone();
+    long_sig[sig_len_offset..sig_len_offset + 8]
+        .copy_from_slice(&(MAX_SIG_LEN as u64 + 1).to_le_bytes());
+    assert!(matches!(deser(&long_sig), Err(Error::Deserialization(_))));
+
+    // Knock the first point of the proof off the curve
+    let mut bad_point = bytes.clone();
+    bad_point[sig_len_offset + 8 + req.sig.len() + 1] ^= 1;
+    assert!(matches!(deser(&bad_point), Err(Error::Deserialization(_))));
+
+    // Trailing data is rejected too
+    let mut trailing = bytes;
+    trailing.push(0);
+    assert!(matches!(deser(&trailing), Err(Error::Deserialization(_))));
+}
+
+#[test]
+fn one_credential_per_passport() {
+    let (_, vk) = &*CRS;
+    let alice = passport(TestPassportParams::default());
+    let policy = policy_for(&alice);
+
+    // Every request for the same passport has the same nullifier, even though the commitments
+    // differ
+    let first = req_issuance(&alice, &policy).unwrap();
+    let second = req_issuance(&alice, &policy).unwrap();
+    assert!(first.attrs_com != second.attrs_com);
+    assert_eq!(first.nullifier, second.nullifier);
+
+    // A different passport, or a different nullifier key, gives a different nullifier
+    let renewed = passport(TestPassportParams {
+        document_number: "987654321".to_string(),
+        ..Default::default()
+    });
+    let renewed_req = req_issuance(&renewed, &policy_for(&renewed)).unwrap();
+    assert_ne!(first.nullifier, renewed_req.nullifier);
+    let other_issuer = IssuancePolicy {
+        nullifier_key: IssuancePolicy::parse_nullifier_key(&"01".repeat(32)).unwrap(),
+        ..policy.clone()
+    };
+    assert_eq!(other_issuer.nullifier_key, [1u8; 32]);
+    assert!(IssuancePolicy::parse_nullifier_key("0101").is_err());
+    let other_req = req_issuance(&alice, &other_issuer).unwrap();
+    assert_ne!(first.nullifier, other_req.nullifier);
+    check_issuance(vk, &other_req, &other_issuer).unwrap();
+
+    // The nullifier is bound to the proof
+    let mut tampered = req_issuance(&alice, &policy).unwrap();
+    tampered.nullifier[0] ^= 1;
+    let res = check_issuance(vk, &tampered, &policy);
+    assert!(matches!(res, Err(Error::ProofFailure(_))));
+
+    // The issuer's database only lets the passport through once
+    let db = IssuerDb::temporary().unwrap();
+    for req in [&first, &renewed_req] {
+        db.grant(&req.attrs_com, &req.nullifier_bytes().unwrap())
+            .unwrap();
+    }
+    let res = db.grant(&second.attrs_com, &second.nullifier_bytes().unwrap());
+    assert!(matches!(res, Err(Error::PolicyViolation(_))));
+}
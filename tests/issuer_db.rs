@@ -0,0 +1,81 @@
+//! Tests of the issuer's database
+
+use zkcreds_passport_cli::{
+    issuer_db::IssuerDb,
+    output::to_base64,
+    params::{Fr, PassportComScheme},
+    tree::{empty_tree, tree_from_creds},
+    Error, PersonalInfo,
+};
+
+use zkcreds::{attrs::Attrs, Com};
+
+use ark_ff::UniformRand;
+
+/// A commitment to some made-up personal info
+fn cred() -> Com<PassportComScheme> {
+    let info = PersonalInfo {
+        seed: Fr::rand(&mut rand::thread_rng()),
+        ..Default::default()
+    };
+    info.commit()
+}
+
+#[test]
+fn grant_revoke_and_reopen() {
+    let path = std::env::temp_dir().join(format!("zkpp-issuer-db-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&path);
+    let creds = [cred(), cred(), cred()];
+
+    {
+        let db = IssuerDb::open(&path).unwrap();
+        let status = db.status().unwrap();
+        assert_eq!(status.num_granted, 0);
+        assert_eq!(status.last_granted_at, None);
+        assert_eq!(status.root, to_base64(&empty_tree().root()).unwrap());
+
+        // Credentials get consecutive leaf indices, and the tree matches the one gen-tree makes
+        for (i, cred) in creds.iter().enumerate() {
+            let leaf_index = db.grant(cred, &[i as u8; 32]).unwrap();
+            assert_eq!(leaf_index, i as u64);
+        }
+        assert!(db.tree().unwrap().root() == tree_from_creds(&creds).root());
+
+        // A passport only gets one credential, even after its credential is revoked
+        let res = db.grant(&cred(), &[0u8; 32]);
+        assert!(matches!(res, Err(Error::PolicyViolation(_))));
+        db.revoke(1).unwrap();
+        assert!(matches!(
+            db.check_nullifier(&[1u8; 32]),
+            Err(Error::PolicyViolation(_))
+        ));
+        db.check_nullifier(&[3u8; 32]).unwrap();
+        assert!(db.revoke(3).is_err());
+    }
+
+    // Everything survives reopening, and the revoked credential is out of the tree
+    let db = IssuerDb::open(&path).unwrap();
+    let status = db.status().unwrap();
+    assert_eq!(status.num_granted, 3);
+    assert_eq!(status.num_revoked, 1);
+    assert_eq!(status.next_leaf_index, 3);
+    assert!(status.last_granted_at.is_some());
+
+    let mut expected = empty_tree();
+    expected.insert(0, &creds[0]);
+    expected.insert(2, &creds[2]);
+    assert!(db.tree().unwrap().root() == expected.root());
+    assert_eq!(status.root, to_base64(&expected.root()).unwrap());
+
+    let records = db.records().unwrap();
+    assert!(records[1].revoked);
+    assert_eq!(records[2].nullifier, vec![2u8; 32]);
+    assert_eq!(db.grant(&cred(), &[3u8; 32]).unwrap(), 3);
+
+    // Epochs only go up
+    let epoch = db.next_epoch().unwrap();
+    assert_eq!(db.next_epoch().unwrap(), epoch + 1);
+
+    drop(db);
+    std::fs::remove_dir_all(&path).unwrap();
+}
@@ -0,0 +1,133 @@
+//! Tests of the issuer HTTP service, without the HTTP
+
+use zkcreds_passport_cli::{
+    gen_issuance_crs,
+    issuer_db::IssuerDb,
+    issuer_service::IssuerService,
+    output::{deser_untrusted_from_reader, to_frame, Envelope, Frame, ObjectType},
+    root_statement::{IssuerSigningKey, SignedRevocation, SignedRootStatement},
+    test_passport::{gen_test_passport, TestPassportParams},
+    user_req_issuance, IssuancePolicy,
+};
+
+#[test]
+fn issue_and_fetch_paths() {
+    let mut rng = rand::thread_rng();
+    let (pk, vk) = gen_issuance_crs(&mut rng).unwrap();
+    let params = TestPassportParams {
+        key_bits: 1024,
+        ..Default::default()
+    };
+    let passport = gen_test_passport(&mut rng, &params).unwrap();
+    let policy = IssuancePolicy {
+        trust_anchor: passport.trust_anchor.clone(),
+        ..Default::default()
+    };
+    let signing_key = IssuerSigningKey::generate(&mut rng);
+    let issuer_key = signing_key.public_key();
+    // The service takes the key, so keep a copy to sign revocations with
+    let frame = to_frame(ObjectType::IssuerSigningKey, None, &signing_key).unwrap();
+    let revoker: IssuerSigningKey =
+        Frame::read(&mut frame.as_slice(), ObjectType::IssuerSigningKey)
+            .unwrap()
+            .deser()
+            .unwrap();
+    let db = IssuerDb::temporary().unwrap();
+    let mut service = IssuerService::new(vk, signing_key, policy.clone(), db).unwrap();
+    let parse = |body: &str| -> Envelope { serde_json::from_str(body).unwrap() };
+
+    let res = service.handle("GET", "/verifying-key", &[]);
+    assert_eq!(res.status, 200);
+    let vk_fingerprint = parse(&res.body).key_fingerprint.unwrap();
+    let empty_root = parse(&service.handle("GET", "/root", &[]).body).root;
+
+    // Issue a credential
+    let (_, req) = user_req_issuance(&mut rng, &passport.dump, &pk, &policy).unwrap();
+    let body =
+        base64::encode(to_frame(ObjectType::IssuanceRequest, Some(&vk_fingerprint), &req).unwrap());
+    let res = service.handle("POST", "/issue", body.as_bytes());
+    assert_eq!(res.status, 200, "{}", res.body);
+    let cred = parse(&res.body);
+    assert_eq!(cred.leaf_index, Some(0));
+
+    // The root changed, and the new credential has an auth path under it
+    let root = parse(&service.handle("GET", "/root", &[]).body).root;
+    assert_ne!(root, empty_root);
+    assert_eq!(cred.root, root);
+    let res = service.handle("GET", "/auth-path/0", &[]);
+    assert_eq!(res.status, 200);
+    assert_eq!(parse(&res.body).root, root);
+
+    // Fetching a path doesn't change the tree
+    assert_eq!(parse(&service.handle("GET", "/root", &[]).body).root, root);
+
+    // The issuer signed a statement about the new root, in a later epoch than the first one
+    let statement = parse(&service.handle("GET", "/root-statement", &[]).body);
+    assert_eq!(statement.root, root);
+    let statement: SignedRootStatement = deser_untrusted_from_reader(
+        &mut statement.payload.unwrap().as_bytes(),
+        ObjectType::RootStatement,
+    )
+    .unwrap();
+    let statement = statement.verify(&issuer_key).unwrap();
+    assert_eq!(statement.epoch, 1);
+    assert_eq!(statement.key_fingerprint(), vk_fingerprint);
+
+    // Another request for the same passport is rejected
+    let (_, again) = user_req_issuance(&mut rng, &passport.dump, &pk, &policy).unwrap();
+    let body = base64::encode(
+        to_frame(ObjectType::IssuanceRequest, Some(&vk_fingerprint), &again).unwrap(),
+    );
+    let res = service.handle("POST", "/issue", body.as_bytes());
+    assert_eq!(res.status, 422);
+    assert!(parse(&res.body).ty == ObjectType::IssuanceVerdict);
+
+    // Garbage, unknown leaves, and unknown endpoints are rejected
+    assert_eq!(service.handle("POST", "/issue", b"garbage").status, 400);
+    assert_eq!(service.handle("GET", "/auth-path/1", &[]).status, 404);
+    assert_eq!(service.handle("GET", "/auth-path/x", &[]).status, 400);
+    assert_eq!(service.handle("DELETE", "/root", &[]).status, 404);
+
+    // A request made for a different key is rejected, and the tree is unchanged
+    let body = base64::encode(
+        to_frame(ObjectType::IssuanceRequest, Some(&"00".repeat(32)), &req).unwrap(),
+    );
+    let res = service.handle("POST", "/issue", body.as_bytes());
+    assert_eq!(res.status, 422);
+    assert!(parse(&res.body).ty == ObjectType::IssuanceVerdict);
+    assert_eq!(parse(&service.handle("GET", "/root", &[]).body).root, root);
+
+    // Revocations only go through if they're signed by the issuer, and for a granted credential
+    let revocation = |key: &IssuerSigningKey, leaf_index| {
+        let revocation = SignedRevocation::new(leaf_index, key);
+        base64::encode(to_frame(ObjectType::Revocation, None, &revocation).unwrap())
+    };
+    let forged = revocation(&IssuerSigningKey::generate(&mut rng), 0);
+    assert_eq!(
+        service.handle("POST", "/revoke", forged.as_bytes()).status,
+        403
+    );
+    assert_eq!(service.handle("GET", "/auth-path/0", &[]).status, 200);
+    let unknown = revocation(&revoker, 1);
+    assert_eq!(
+        service.handle("POST", "/revoke", unknown.as_bytes()).status,
+        400
+    );
+
+    // Revoking takes the credential out of the tree right away, under a new root statement
+    let res = service.handle("POST", "/revoke", revocation(&revoker, 0).as_bytes());
+    assert_eq!(res.status, 200, "{}", res.body);
+    let statement = parse(&res.body);
+    assert_eq!(statement.root, empty_root);
+    assert_eq!(
+        parse(&service.handle("GET", "/root", &[]).body).root,
+        empty_root
+    );
+    assert_eq!(service.handle("GET", "/auth-path/0", &[]).status, 404);
+    let statement: SignedRootStatement = deser_untrusted_from_reader(
+        &mut statement.payload.unwrap().as_bytes(),
+        ObjectType::RootStatement,
+    )
+    .unwrap();
+    assert_eq!(statement.verify(&issuer_key).unwrap().epoch, 2);
+}
@@ -0,0 +1,157 @@
+//! Tests of the multi-party CRS ceremony
+
+use zkcreds_passport_cli::{
+    circuit_stats::Circuit,
+    mpc::{crs_contribute, crs_init, gen_circuit_crs, tau_log_size, verify_transcript},
+    output::fingerprint,
+    params::Fr,
+    powers_of_tau::{tau_contribute, tau_init, verify_tau_transcript, TauTranscript},
+    show::{gen_membership_crs, prove_show, verify_show, ShowPolicy},
+    test_passport::{gen_test_passport, TestPassportParams},
+    tree::empty_tree,
+    Error, PersonalInfo,
+};
+
+use zkcreds::attrs::Attrs;
+
+use ark_ff::UniformRand;
+
+/// Runs phase 1 with two contributions, big enough for the given circuit
+fn powers_for(circuit: Circuit) -> TauTranscript {
+    let mut rng = rand::thread_rng();
+    let mut powers = tau_init(tau_log_size(circuit).unwrap()).unwrap();
+    tau_contribute(&mut rng, &mut powers).unwrap();
+    tau_contribute(&mut rng, &mut powers).unwrap();
+    powers
+}
+
+#[test]
+fn powers_of_tau_verify() {
+    let mut rng = rand::thread_rng();
+    let mut powers = tau_init(4).unwrap();
+
+    // The initial powers are public, so they don't verify on their own
+    let res = verify_tau_transcript(&mut rng, &powers);
+    assert!(matches!(res, Err(Error::ProofFailure(_))));
+
+    let hash1 = tau_contribute(&mut rng, &mut powers).unwrap();
+    let hash2 = tau_contribute(&mut rng, &mut powers).unwrap();
+    assert_ne!(hash1, hash2);
+    verify_tau_transcript(&mut rng, &powers).unwrap();
+
+    // A contribution whose proof of knowledge is someone else's
+    let mut swapped = powers.clone();
+    swapped.contributions[0].tau_proof = swapped.contributions[1].tau_proof.clone();
+    let res = verify_tau_transcript(&mut rng, &swapped);
+    assert!(matches!(res, Err(Error::ProofFailure(_))));
+
+    // Powers that were changed without recording the contribution
+    let mut unrecorded = powers.clone();
+    tau_contribute(&mut rng, &mut unrecorded).unwrap();
+    unrecorded.contributions.pop();
+    let res = verify_tau_transcript(&mut rng, &unrecorded);
+    assert!(matches!(res, Err(Error::ProofFailure(_))));
+
+    // Powers that aren't consecutive
+    let mut skipped = powers;
+    skipped.current.tau_g1.swap(2, 3);
+    let res = verify_tau_transcript(&mut rng, &skipped);
+    assert!(matches!(res, Err(Error::ProofFailure(_))));
+}
+
+#[test]
+fn ceremony_verifies() {
+    let mut rng = rand::thread_rng();
+    let powers = powers_for(Circuit::Age);
+    let mut transcript = crs_init(&mut rng, &powers, Circuit::Age).unwrap();
+
+    // δ is still 1, so the transcript doesn't verify until someone contributes
+    let res = verify_transcript(&mut rng, &transcript, &powers, Circuit::Age);
+    assert!(matches!(res, Err(Error::ProofFailure(_))));
+
+    // Every contribution changes the key, and the transcript still verifies
+    let hash1 = crs_contribute(&mut rng, &mut transcript).unwrap();
+    let (_, vk1) = verify_transcript(&mut rng, &transcript, &powers, Circuit::Age).unwrap();
+    let hash2 = crs_contribute(&mut rng, &mut transcript).unwrap();
+    assert_ne!(hash1, hash2);
+    let (_, vk2) = verify_transcript(&mut rng, &transcript, &powers, Circuit::Age).unwrap();
+    assert_ne!(fingerprint(&vk1).unwrap(), fingerprint(&vk2).unwrap());
+}
+
+#[test]
+fn tampered_ceremony_is_rejected() {
+    let mut rng = rand::thread_rng();
+    let powers = powers_for(Circuit::Age);
+    let mut transcript = crs_init(&mut rng, &powers, Circuit::Age).unwrap();
+    crs_contribute(&mut rng, &mut transcript).unwrap();
+    crs_contribute(&mut rng, &mut transcript).unwrap();
+
+    // A contribution whose proof of knowledge is someone else's
+    let mut swapped = transcript.clone();
+    swapped.contributions[0].r_delta = swapped.contributions[1].r_delta;
+    let res = verify_transcript(&mut rng, &swapped, &powers, Circuit::Age);
+    assert!(matches!(res, Err(Error::ProofFailure(_))));
+
+    // A key that was changed without recording the contribution
+    let mut unrecorded = transcript.clone();
+    crs_contribute(&mut rng, &mut unrecorded).unwrap();
+    unrecorded.contributions.pop();
+    let res = verify_transcript(&mut rng, &unrecorded, &powers, Circuit::Age);
+    assert!(matches!(res, Err(Error::ProofFailure(_))));
+
+    // A key swapped out for one made by a single party
+    let mut replaced = transcript.clone();
+    replaced.current = gen_circuit_crs(&mut rng, Circuit::Age).unwrap().0;
+    let res = verify_transcript(&mut rng, &replaced, &powers, Circuit::Age);
+    assert!(matches!(res, Err(Error::ProofFailure(_))));
+
+    // A key derived from other powers of tau
+    let other_powers = powers_for(Circuit::Age);
+    let res = verify_transcript(&mut rng, &transcript, &other_powers, Circuit::Age);
+    assert!(matches!(res, Err(Error::ProofFailure(_))));
+
+    // The key is bound to its circuit
+    let res = verify_transcript(&mut rng, &transcript, &powers, Circuit::Expiry);
+    assert!(res.is_err());
+}
+
+#[test]
+fn derived_crs_proves() {
+    let mut rng = rand::thread_rng();
+    let powers = powers_for(Circuit::ShowAgeAndExpiry);
+    let mut transcript = crs_init(&mut rng, &powers, Circuit::ShowAgeAndExpiry).unwrap();
+    crs_contribute(&mut rng, &mut transcript).unwrap();
+    let (pk, vk) =
+        verify_transcript(&mut rng, &transcript, &powers, Circuit::ShowAgeAndExpiry).unwrap();
+
+    // The derived key works with zkcreds' prover and verifier. The test passport's holder was
+    // born in 1990, and it expires in 2030.
+    let params = TestPassportParams {
+        key_bits: 1024,
+        ..Default::default()
+    };
+    let passport = gen_test_passport(&mut rng, &params).unwrap();
+    let info = PersonalInfo::from_passport(&mut rng, &passport.dump, 20220101, 10).unwrap();
+    let policy = ShowPolicy {
+        circuit: Circuit::ShowAgeAndExpiry,
+        today: Some(20220101),
+        min_age: 18,
+        verifier_id: "example.com".to_string(),
+    };
+    let mut tree = empty_tree();
+    let auth_path = tree.insert(0, &info.commit());
+    let (membership_pk, membership_vk) = gen_membership_crs(&mut rng).unwrap();
+    let challenge = Fr::rand(&mut rng);
+    let show = prove_show(
+        &mut rng,
+        &pk,
+        &membership_pk,
+        &info,
+        &auth_path,
+        &tree.root(),
+        &policy,
+        challenge,
+    )
+    .unwrap();
+    verify_show(&vk, &membership_vk, &show, &policy, &tree.root(), challenge).unwrap();
+}
@@ -0,0 +1,153 @@
+//! Tests of the framed file format
+
+use zkcreds_passport_cli::{
+    output::{
+        deser_from_file, deser_from_reader, to_frame, to_frame_as, Frame, ObjectType,
+        StorageFormat, FRAME_VERSION, MAX_DECOMPRESSED_LEN,
+    },
+    params::Fr,
+    Error,
+};
+
+const FINGERPRINT: &str = "00112233445566778899aabbccddeeff00112233445566778899aabbccddeeff";
+
+/// Frames the given value and base64-encodes it
+fn framed_b64(ty: ObjectType, fingerprint: Option<&str>, val: &Fr) -> String {
+    base64::encode(to_frame(ty, fingerprint, val).unwrap())
+}
+
+#[test]
+fn frame_round_trip() {
+    let val = Fr::from(1234u32);
+    let b64 = framed_b64(ObjectType::Root, Some(FINGERPRINT), &val);
+
+    let frame = Frame::read(&mut b64.as_bytes(), ObjectType::Root).unwrap();
+    assert_eq!(frame.key_fingerprint.as_deref(), Some(FINGERPRINT));
+    assert!(frame.check_key(FINGERPRINT).is_ok());
+    assert!(frame.deser_untrusted::<Fr>().unwrap() == val);
+}
+
+#[test]
+fn wrong_type_is_rejected() {
+    let b64 = framed_b64(ObjectType::Tree, None, &Fr::from(1u32));
+    let res = deser_from_reader::<_, Fr>(&mut b64.as_bytes(), ObjectType::VerifyingKey);
+    match res {
+        Err(Error::Deserialization(msg)) => assert!(msg.contains("is a tree"), "{}", msg),
+        _ => panic!("expected a deserialization error"),
+    }
+}
+
+#[test]
+fn malformed_headers_are_rejected() {
+    let frame = to_frame(ObjectType::Root, None, &Fr::from(1u32)).unwrap();
+    let read = |bytes: &[u8]| {
+        let b64 = base64::encode(bytes);
+        Frame::read(&mut b64.as_bytes(), ObjectType::Root).map(|_| ())
+    };
+    assert!(read(&frame).is_ok());
+
+    // Unframed data, e.g., from an older version of this tool
+    let res = read(&frame[8..]);
+    assert!(matches!(res, Err(Error::Deserialization(_))));
+
+    // A future format version
+    let mut future = frame.clone();
+    future[4] = FRAME_VERSION + 1;
+    assert!(matches!(read(&future), Err(Error::Deserialization(_))));
+
+    // A different parameter set
+    let mut other_params = frame.clone();
+    other_params[6] ^= 1;
+    assert!(matches!(
+        read(&other_params),
+        Err(Error::Deserialization(_))
+    ));
+
+    // A truncated key hash
+    let mut truncated = frame[..8].to_vec();
+    truncated[7] = 1;
+    assert!(matches!(read(&truncated), Err(Error::Deserialization(_))));
+}
+
+#[test]
+fn wrong_key_is_rejected() {
+    let other = "ff".repeat(32);
+    let b64 = framed_b64(ObjectType::IssuanceRequest, Some(&other), &Fr::from(1u32));
+    let frame = Frame::read(&mut b64.as_bytes(), ObjectType::IssuanceRequest).unwrap();
+    assert!(matches!(
+        frame.check_key(FINGERPRINT),
+        Err(Error::ProofFailure(_))
+    ));
+
+    // An object that isn't bound to any key is rejected too
+    let b64 = framed_b64(ObjectType::IssuanceRequest, None, &Fr::from(1u32));
+    let frame = Frame::read(&mut b64.as_bytes(), ObjectType::IssuanceRequest).unwrap();
+    assert!(matches!(
+        frame.check_key(FINGERPRINT),
+        Err(Error::ProofFailure(_))
+    ));
+}
+
+#[test]
+fn storage_formats_round_trip() {
+    let val = Fr::from(5678u32);
+    let path = std::env::temp_dir().join(format!("zkpp-frame-{}", std::process::id()));
+
+    for storage in [
+        StorageFormat::Base64,
+        StorageFormat::Binary,
+        StorageFormat::BinaryZstd,
+    ] {
+        let frame = to_frame_as(ObjectType::Root, Some(FINGERPRINT), &val, storage).unwrap();
+
+        // Binary frames are read as is, and base64 ones are decoded first
+        let bytes = match storage {
+            StorageFormat::Base64 => base64::encode(&frame).into_bytes(),
+            _ => frame,
+        };
+        let read = Frame::read(&mut bytes.as_slice(), ObjectType::Root).unwrap();
+        assert!(read.storage == storage);
+        assert_eq!(read.key_fingerprint.as_deref(), Some(FINGERPRINT));
+        assert!(read.deser_untrusted::<Fr>().unwrap() == val);
+
+        // The same goes for files, which are memory-mapped when they're binary
+        std::fs::write(&path, &bytes).unwrap();
+        assert!(deser_from_file::<Fr>(&path, ObjectType::Root).unwrap() == val);
+    }
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn unknown_flags_are_rejected() {
+    let mut frame = to_frame_as(
+        ObjectType::Root,
+        None,
+        &Fr::from(1u32),
+        StorageFormat::Binary,
+    )
+    .unwrap();
+    frame[7] |= 0x80;
+    let res = Frame::read(&mut frame.as_slice(), ObjectType::Root);
+    assert!(matches!(res, Err(Error::Deserialization(_))));
+}
+
+#[test]
+fn oversized_decompression_is_rejected() {
+    // A small frame whose body decompresses to just over the limit
+    let header = to_frame_as(
+        ObjectType::Root,
+        None,
+        &Fr::from(1u32),
+        StorageFormat::BinaryZstd,
+    )
+    .unwrap()[..8]
+        .to_vec();
+    let body = zstd::encode_all(&vec![0u8; MAX_DECOMPRESSED_LEN + 1][..], 0).unwrap();
+    let frame = [header, body].concat();
+    let res = Frame::read(&mut frame.as_slice(), ObjectType::Root);
+    match res {
+        Err(Error::Deserialization(msg)) => assert!(msg.contains("decompresses"), "{}", msg),
+        _ => panic!("expected a deserialization error"),
+    }
+}
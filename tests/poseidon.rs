@@ -0,0 +1,77 @@
+//! Tests that the Poseidon commitment and tree hash agree with their gadgets
+#![cfg(feature = "poseidon")]
+
+use zkcreds_passport_cli::{
+    params::Fr,
+    poseidon::{
+        poseidon_params, PoseidonCom, PoseidonComGadget, PoseidonParamsVar, PoseidonTwoToOneCrh,
+        PoseidonTwoToOneCrhGadget,
+    },
+};
+
+use ark_crypto_primitives::{
+    commitment::{CommitmentGadget, CommitmentScheme},
+    crh::{TwoToOneCRH, TwoToOneCRHGadget},
+};
+use ark_ff::UniformRand;
+use ark_r1cs_std::{alloc::AllocVar, fields::fp::FpVar, uint8::UInt8, R1CSVar};
+use ark_relations::r1cs::ConstraintSystem;
+use rand::RngCore;
+
+#[test]
+fn commitment_matches_gadget() {
+    let mut rng = rand::thread_rng();
+    let params = poseidon_params();
+
+    // Try lengths that do and don't fill the last chunk
+    for len in [0, 1, 31, 32, 93, 200] {
+        let mut input = vec![0u8; len];
+        rng.fill_bytes(&mut input);
+        let r = Fr::rand(&mut rng);
+        let com = PoseidonCom::commit(&params, &input, &r).unwrap();
+
+        let cs = ConstraintSystem::<Fr>::new_ref();
+        let params_var = PoseidonParamsVar::new_constant(cs.clone(), &params).unwrap();
+        let input_var = UInt8::new_witness_vec(cs.clone(), &input).unwrap();
+        let r_var = FpVar::new_witness(cs.clone(), || Ok(r)).unwrap();
+        let com_var = PoseidonComGadget::commit(&params_var, &input_var, &r_var).unwrap();
+
+        assert!(cs.is_satisfied().unwrap());
+        assert_eq!(com_var.value().unwrap(), com, "mismatch for length {}", len);
+    }
+}
+
+#[test]
+fn commitment_binds_length() {
+    let mut rng = rand::thread_rng();
+    let params = poseidon_params();
+    let r = Fr::rand(&mut rng);
+
+    // The empty input doesn't commit to the randomness alone, and trailing zeros aren't ignored
+    let empty = PoseidonCom::commit(&params, &[], &r).unwrap();
+    assert_ne!(empty, r);
+    let short = PoseidonCom::commit(&params, &[1], &r).unwrap();
+    let padded = PoseidonCom::commit(&params, &[1, 0], &r).unwrap();
+    assert_ne!(short, padded);
+}
+
+#[test]
+fn tree_hash_matches_gadget() {
+    let mut rng = rand::thread_rng();
+    let params = poseidon_params();
+    let mut left = [0u8; 32];
+    let mut right = [0u8; 32];
+    rng.fill_bytes(&mut left);
+    rng.fill_bytes(&mut right);
+    let digest = PoseidonTwoToOneCrh::evaluate(&params, &left, &right).unwrap();
+
+    let cs = ConstraintSystem::<Fr>::new_ref();
+    let params_var = PoseidonParamsVar::new_constant(cs.clone(), &params).unwrap();
+    let left_var = UInt8::new_witness_vec(cs.clone(), &left).unwrap();
+    let right_var = UInt8::new_witness_vec(cs.clone(), &right).unwrap();
+    let digest_var =
+        PoseidonTwoToOneCrhGadget::evaluate(&params_var, &left_var, &right_var).unwrap();
+
+    assert!(cs.is_satisfied().unwrap());
+    assert_eq!(digest_var.value().unwrap(), digest);
+}
@@ -0,0 +1,62 @@
+//! Tests of the public parameter generation
+
+use zkcreds_passport_cli::{
+    params::{MERKLE_CRH_PARAM, PASSPORT_COM_PARAM},
+    public_params::{
+        export_params, gen_com_param, gen_crh_param, verify_params, COM_PARAM_LABEL,
+        CRH_PARAM_LABEL,
+    },
+    Error,
+};
+
+#[test]
+fn exported_params_verify() {
+    let exported = export_params(&PASSPORT_COM_PARAM, &MERKLE_CRH_PARAM).unwrap();
+    verify_params(&exported).unwrap();
+
+    // Generation is deterministic. Generate the parameters from scratch twice, rather than
+    // exporting the cached ones again.
+    let first = export_params(
+        &gen_com_param(COM_PARAM_LABEL),
+        &gen_crh_param(CRH_PARAM_LABEL),
+    )
+    .unwrap();
+    let second = export_params(
+        &gen_com_param(COM_PARAM_LABEL),
+        &gen_crh_param(CRH_PARAM_LABEL),
+    )
+    .unwrap();
+    for again in [&first, &second] {
+        assert_eq!(exported.commitment.sha256, again.commitment.sha256);
+        assert_eq!(exported.tree_hash.sha256, again.tree_hash.sha256);
+    }
+}
+
+#[test]
+fn tampered_params_are_rejected() {
+    let fresh = || export_params(&PASSPORT_COM_PARAM, &MERKLE_CRH_PARAM).unwrap();
+
+    // A different label
+    let mut other_label = fresh();
+    other_label.commitment.label.push('!');
+    assert!(matches!(
+        verify_params(&other_label),
+        Err(Error::ProofFailure(_))
+    ));
+
+    // Parameters that weren't derived from the label
+    let mut other_data = fresh();
+    other_data.commitment.data = base64::encode(b"some other parameters");
+    assert!(matches!(
+        verify_params(&other_data),
+        Err(Error::ProofFailure(_))
+    ));
+
+    // A wrong hash
+    let mut wrong_hash = fresh();
+    wrong_hash.tree_hash.sha256 = "00".repeat(32);
+    assert!(matches!(
+        verify_params(&wrong_hash),
+        Err(Error::ProofFailure(_))
+    ));
+}
@@ -0,0 +1,95 @@
+//! Tests of the issuer's signed root statements
+
+use zkcreds_passport_cli::{
+    output::{to_frame, Frame, ObjectType},
+    root_statement::{
+        Freshness, IssuerSigningKey, RootStatement, SignedRevocation, SignedRootStatement,
+    },
+    tree::tree_from_creds,
+    Error, PersonalInfo,
+};
+
+use zkcreds::attrs::Attrs;
+
+const FINGERPRINT: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+#[test]
+fn root_statements() {
+    let mut rng = rand::thread_rng();
+    let key = IssuerSigningKey::generate(&mut rng);
+    let other_key = IssuerSigningKey::generate(&mut rng);
+
+    let tree = tree_from_creds(&[PersonalInfo::default().commit()]);
+    let statement = RootStatement::new(tree.root(), 3, FINGERPRINT)
+        .unwrap()
+        .sign(&key)
+        .unwrap();
+
+    // The statement survives a round trip, and only verifies under the issuer's key
+    let frame = to_frame(ObjectType::RootStatement, None, &statement).unwrap();
+    let statement: SignedRootStatement =
+        Frame::read(&mut frame.as_slice(), ObjectType::RootStatement)
+            .unwrap()
+            .deser_untrusted()
+            .unwrap();
+    let verified = statement.verify(&key.public_key()).unwrap();
+    assert_eq!(verified.epoch, 3);
+    assert_eq!(verified.key_fingerprint(), FINGERPRINT);
+    assert!(verified.root == tree.root());
+    assert!(matches!(
+        statement.verify(&other_key.public_key()),
+        Err(Error::ProofFailure(_))
+    ));
+
+    // It's only fresh enough if it's from a late enough epoch, and recent enough
+    let fresh = |min_epoch, max_age| Freshness { min_epoch, max_age };
+    let pk = key.public_key();
+    statement.verify_fresh(&pk, &Freshness::default()).unwrap();
+    statement.verify_fresh(&pk, &fresh(Some(3), 60)).unwrap();
+    assert!(matches!(
+        statement.verify_fresh(&pk, &fresh(Some(4), 60)),
+        Err(Error::ProofFailure(_))
+    ));
+    let mut old = RootStatement::new(tree.root(), 3, FINGERPRINT).unwrap();
+    old.timestamp -= 3600;
+    let old = old.sign(&key).unwrap();
+    assert!(old.statement.age() >= 3600);
+    old.verify_fresh(&pk, &fresh(None, 7200)).unwrap();
+    assert!(matches!(
+        old.verify_fresh(&pk, &fresh(None, 60)),
+        Err(Error::ProofFailure(_))
+    ));
+
+    // Changing anything that was signed breaks the signature
+    let mut tampered = statement.clone();
+    tampered.statement.epoch += 1;
+    assert!(tampered.verify(&key.public_key()).is_err());
+    let mut tampered = statement;
+    tampered.statement.tree_height += 1;
+    assert!(tampered.verify(&key.public_key()).is_err());
+}
+
+#[test]
+fn revocations() {
+    let mut rng = rand::thread_rng();
+    let key = IssuerSigningKey::generate(&mut rng);
+    let other_key = IssuerSigningKey::generate(&mut rng);
+
+    // A revocation survives a round trip, and only verifies under the issuer's key
+    let revocation = SignedRevocation::new(5, &key);
+    let frame = to_frame(ObjectType::Revocation, None, &revocation).unwrap();
+    let revocation: SignedRevocation = Frame::read(&mut frame.as_slice(), ObjectType::Revocation)
+        .unwrap()
+        .deser_untrusted()
+        .unwrap();
+    assert_eq!(revocation.verify(&key.public_key()).unwrap(), 5);
+    assert!(matches!(
+        revocation.verify(&other_key.public_key()),
+        Err(Error::ProofFailure(_))
+    ));
+
+    // It can't be turned into a revocation of another credential
+    let mut tampered = revocation;
+    tampered.leaf_index = 6;
+    assert!(tampered.verify(&key.public_key()).is_err());
+}
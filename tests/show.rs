@@ -0,0 +1,140 @@
+//! Tests of showing credentials
+
+use zkcreds_passport_cli::{
+    circuit_stats::Circuit,
+    gen_issuance_crs,
+    mpc::gen_circuit_crs,
+    params::{ComTreeRoot, Fr},
+    show::{
+        cached_membership_proofs, gen_membership_crs, prove_show, prove_show_with_membership,
+        verify_show, ShowPolicy, ShowProof,
+    },
+    test_passport::{gen_test_passport, TestPassportParams},
+    tree::empty_tree,
+    user_req_issuance, Error, IssuancePolicy,
+};
+
+use ark_ff::UniformRand;
+
+#[test]
+fn show_round_trip() {
+    let mut rng = rand::thread_rng();
+
+    // Get a credential
+    let (issuance_pk, _) = gen_issuance_crs(&mut rng).unwrap();
+    let params = TestPassportParams {
+        key_bits: 1024,
+        ..Default::default()
+    };
+    let passport = gen_test_passport(&mut rng, &params).unwrap();
+    let issuance_policy = IssuancePolicy {
+        trust_anchor: passport.trust_anchor.clone(),
+        ..Default::default()
+    };
+    let (info, req) =
+        user_req_issuance(&mut rng, &passport.dump, &issuance_pk, &issuance_policy).unwrap();
+
+    // Put it in the issuer's tree, somewhere other than the first leaf
+    let mut tree = empty_tree();
+    let auth_path = tree.insert(1, &req.attrs_com);
+    let root = tree.root();
+
+    // Show it. The test passport's holder was born in 1990, and it expires in 2030.
+    let policy = ShowPolicy {
+        circuit: Circuit::ShowAgeAndExpiry,
+        today: Some(20220101),
+        min_age: 18,
+        verifier_id: "example.com".to_string(),
+    };
+    let (pk, vk) = gen_circuit_crs(&mut rng, policy.circuit).unwrap();
+    let (membership_pk, membership_vk) = gen_membership_crs(&mut rng).unwrap();
+    let challenge = Fr::rand(&mut rng);
+    let show = prove_show(
+        &mut rng,
+        &pk,
+        &membership_pk,
+        &info,
+        &auth_path,
+        &root,
+        &policy,
+        challenge,
+    )
+    .unwrap();
+    let verify = |show: &ShowProof, policy: &ShowPolicy, root: &ComTreeRoot, challenge: Fr| {
+        verify_show(&vk, &membership_vk, show, policy, root, challenge)
+    };
+    verify(&show, &policy, &root, challenge).unwrap();
+
+    // The challenge and verifier are bound into the proof, so changing them along with the show's
+    // copy of the challenge doesn't help
+    let mut replayed = show.clone();
+    replayed.challenge = Fr::rand(&mut rng);
+    assert!(matches!(
+        verify(&replayed, &policy, &root, replayed.challenge),
+        Err(Error::ProofFailure(_))
+    ));
+    let other_verifier = ShowPolicy {
+        verifier_id: "example.org".to_string(),
+        ..policy.clone()
+    };
+    assert!(matches!(
+        verify(&show, &other_verifier, &root, challenge),
+        Err(Error::ProofFailure(_))
+    ));
+
+    // A different challenge or a different root fails, even if the show claims that root
+    let other_challenge = Fr::rand(&mut rng);
+    assert!(matches!(
+        verify(&show, &policy, &root, other_challenge),
+        Err(Error::ProofFailure(_))
+    ));
+    let empty_root = empty_tree().root();
+    assert!(matches!(
+        verify(&show, &policy, &empty_root, challenge),
+        Err(Error::ProofFailure(_))
+    ));
+    let mut moved = show.clone();
+    moved.root = empty_root;
+    assert!(matches!(
+        verify(&moved, &policy, &empty_root, challenge),
+        Err(Error::ProofFailure(_))
+    ));
+
+    // Membership proofs can be reused for another challenge against the same root
+    let mut cache = None;
+    for _ in 0..2 {
+        let challenge = Fr::rand(&mut rng);
+        let membership = cached_membership_proofs(
+            &mut rng,
+            &membership_pk,
+            &info,
+            &auth_path,
+            &root,
+            &mut cache,
+        )
+        .unwrap();
+        let show = prove_show_with_membership(
+            &mut rng,
+            &pk,
+            &membership_pk,
+            &info,
+            &auth_path,
+            membership,
+            &policy,
+            challenge,
+        )
+        .unwrap();
+        verify(&show, &policy, &root, challenge).unwrap();
+    }
+    assert!(cache.unwrap().root == root);
+
+    // A policy the holder doesn't satisfy fails
+    let strict_policy = ShowPolicy {
+        min_age: 40,
+        ..policy.clone()
+    };
+    assert!(matches!(
+        verify(&show, &strict_policy, &root, challenge),
+        Err(Error::ProofFailure(_))
+    ));
+}
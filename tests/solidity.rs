@@ -0,0 +1,60 @@
+//! Tests that the Solidity verifier has the verifying key's values in it
+#![cfg(feature = "bn254")]
+
+use zkcreds_passport_cli::{
+    circuit_stats::Circuit,
+    groth16::{groth16_vk, split_pk},
+    mpc::gen_circuit_crs,
+    solidity::solidity_verifier,
+};
+
+use ark_bn254::{Fq, Fq2, G1Affine, G2Affine};
+use ark_serialize::CanonicalSerialize;
+
+/// A base field element as a Solidity hex literal. This goes through the canonical serialization,
+/// which is little-endian, rather than the big-endian conversion the exporter uses.
+fn fq(x: &Fq) -> String {
+    let mut bytes = Vec::new();
+    x.serialize(&mut bytes).unwrap();
+    let hex: String = bytes.iter().rev().map(|b| format!("{:02x}", b)).collect();
+    format!("0x{}", hex)
+}
+
+fn g1(p: &G1Affine) -> String {
+    format!("G1Point({}, {})", fq(&p.x), fq(&p.y))
+}
+
+fn g2(p: &G2Affine) -> String {
+    let fq2 = |x: &Fq2| format!("[{}, {}]", fq(&x.c1), fq(&x.c0));
+    format!("G2Point({}, {})", fq2(&p.x), fq2(&p.y))
+}
+
+#[test]
+fn verifier_has_vk_values() {
+    let (pk, pred_vk) = gen_circuit_crs(&mut rand::thread_rng(), Circuit::Age).unwrap();
+    let vk = groth16_vk(&pred_vk).unwrap();
+
+    // The verifying key inside the predicate verifying key is the one inside the proving key
+    let (groth16_pk, _) = split_pk(&pk).unwrap();
+    assert!(groth16_pk.vk == vk);
+
+    let contract = solidity_verifier(&pred_vk).unwrap();
+    for (name, point) in [
+        ("alpha", g1(&vk.alpha_g1)),
+        ("beta", g2(&vk.beta_g2)),
+        ("gamma", g2(&vk.gamma_g2)),
+        ("delta", g2(&vk.delta_g2)),
+    ] {
+        assert!(
+            contract.contains(&format!("vk.{} = {};", name, point)),
+            "{} is missing",
+            name
+        );
+    }
+    for (i, p) in vk.gamma_abc_g1.iter().enumerate() {
+        assert!(contract.contains(&format!("vk.ic[{}] = {};", i, g1(p))));
+    }
+
+    let num_inputs = vk.gamma_abc_g1.len() - 1;
+    assert!(contract.contains(&format!("uint256[{}] calldata input", num_inputs)));
+}
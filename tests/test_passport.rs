@@ -0,0 +1,76 @@
+//! Tests of the synthetic passport generator itself. These don't do any proving.
+
+use zkcreds_passport_cli::{
+    passive_auth::{check_step, passive_auth, PassiveAuthStep},
+    test_passport::{gen_test_passport, mrz_check_digit, TestPassportParams},
+    Error, PassportDump,
+};
+
+fn params() -> TestPassportParams {
+    TestPassportParams {
+        key_bits: 1024,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn check_digits_match_icao_example() {
+    // From ICAO doc 9303, part 3, §4.9
+    assert_eq!(mrz_check_digit(b"L898902C3"), b'6');
+    assert_eq!(mrz_check_digit(b"740812"), b'2');
+    assert_eq!(mrz_check_digit(b"120415"), b'9');
+    assert_eq!(mrz_check_digit(b"<<<<<<<<<<<<<<"), b'0');
+}
+
+#[test]
+fn generated_passport_passes_passive_auth() {
+    let passport = gen_test_passport(&mut rand::thread_rng(), &params()).unwrap();
+    passive_auth(&passport.dump, &passport.trust_anchor).unwrap();
+
+    // The MRZ has what we asked for
+    assert_eq!(passport.dump.issuer(), b"USA");
+    assert_eq!(passport.dump.dob(), b"900101");
+    assert_eq!(passport.dump.expiry(), b"300101");
+
+    // The dump survives a round trip through JSON
+    let json = serde_json::to_string(&passport.dump).unwrap();
+    let dump: PassportDump = serde_json::from_str(&json).unwrap();
+    passive_auth(&dump, &passport.trust_anchor).unwrap();
+}
+
+#[test]
+fn truncated_dump_is_an_error() {
+    let passport = gen_test_passport(&mut rand::thread_rng(), &params()).unwrap();
+    let mut dump = passport.dump;
+    dump.pre_econtent.truncate(10);
+    dump.econtent.clear();
+
+    // The hash checks fail cleanly instead of slicing out of range
+    for step in [PassiveAuthStep::Dg1Hash, PassiveAuthStep::MessageDigest] {
+        let res = check_step(&dump, &passport.trust_anchor, step);
+        assert!(matches!(res, Err(Error::Parse(_))));
+    }
+    assert!(passive_auth(&dump, &passport.trust_anchor).is_err());
+}
+
+#[test]
+fn malformed_params_are_rejected() {
+    let bad_params = [
+        TestPassportParams {
+            issuer: "US".to_string(),
+            ..params()
+        },
+        TestPassportParams {
+            dob: "9001O1".to_string(),
+            ..params()
+        },
+        TestPassportParams {
+            name: "doe<<jane".to_string(),
+            ..params()
+        },
+    ];
+    for p in &bad_params {
+        let res = gen_test_passport(&mut rand::thread_rng(), p);
+        assert!(matches!(res, Err(Error::Parse(_))));
+    }
+}
@@ -0,0 +1,117 @@
+//! Tests of the verifier HTTP service, without the HTTP or the issuer
+
+use zkcreds_passport_cli::{
+    circuit_stats::Circuit,
+    mpc::gen_circuit_crs,
+    output::fingerprint,
+    root_statement::{Freshness, IssuerSigningKey},
+    show::{current_date, decode_challenge, gen_membership_crs, ShowPolicy},
+    verifier_service::{expected_verifier_id, ChallengeStore, ShowRequest, VerifierService},
+    Error,
+};
+
+use std::time::Duration;
+
+#[test]
+fn challenges_and_rejections() {
+    let mut rng = rand::thread_rng();
+    let policy = ShowPolicy {
+        circuit: Circuit::ShowAge,
+        today: Some(20220101),
+        min_age: 21,
+        verifier_id: "example.com".to_string(),
+    };
+    let (_, vk) = gen_circuit_crs(&mut rng, policy.circuit).unwrap();
+    let vk_fingerprint = fingerprint(&vk).unwrap();
+    let ttl = Duration::from_secs(60);
+    let issuer_key = IssuerSigningKey::generate(&mut rng).public_key();
+    let (_, membership_vk) = gen_membership_crs(&mut rng).unwrap();
+    let mut service = VerifierService::new(
+        vk,
+        membership_vk,
+        policy.clone(),
+        "http://127.0.0.1:1",
+        issuer_key,
+        Freshness::default(),
+        ttl,
+    )
+    .unwrap();
+
+    // Challenges come with the key and the policy, and are fresh every time
+    let res = service.handle("GET", "/challenge", &[]);
+    assert_eq!(res.status, 200);
+    let req: ShowRequest = serde_json::from_str(&res.body).unwrap();
+    assert_eq!(req.key_fingerprint, vk_fingerprint);
+    assert_eq!(req.policy, policy);
+    let next: ShowRequest =
+        serde_json::from_str(&service.handle("GET", "/challenge", &[]).body).unwrap();
+    assert_ne!(
+        decode_challenge(&req.challenge).unwrap(),
+        decode_challenge(&next.challenge).unwrap()
+    );
+
+    // Garbage and unknown endpoints are rejected
+    assert_eq!(service.handle("POST", "/show", b"garbage").status, 400);
+    assert_eq!(service.handle("GET", "/show", &[]).status, 404);
+}
+
+#[test]
+fn challenges_are_single_use_and_expire() {
+    let mut rng = rand::thread_rng();
+
+    let mut store = ChallengeStore::new(Duration::from_secs(60));
+    let challenge = store.issue(&mut rng, 20220101);
+    let other = store.issue(&mut rng, 20220102);
+    assert_eq!(store.redeem(&challenge).unwrap(), 20220101);
+    assert!(matches!(
+        store.redeem(&challenge),
+        Err(Error::ProofFailure(_))
+    ));
+    assert_eq!(store.redeem(&other).unwrap(), 20220102);
+
+    // Challenges with no time to live are expired as soon as they're issued
+    let mut store = ChallengeStore::new(Duration::ZERO);
+    let challenge = store.issue(&mut rng, 20220101);
+    assert!(matches!(
+        store.redeem(&challenge),
+        Err(Error::ProofFailure(_))
+    ));
+}
+
+#[test]
+fn policies_without_a_date_are_dated_on_issue() {
+    let policy = ShowPolicy {
+        circuit: Circuit::ShowAge,
+        today: None,
+        min_age: 21,
+        verifier_id: "example.com".to_string(),
+    };
+    let before = current_date();
+    let dated = policy.dated();
+    assert!((before..=current_date()).contains(&dated.today.unwrap()));
+    assert!(before > 20220101 && before < 100000000);
+
+    // A fixed date is kept
+    let fixed = ShowPolicy {
+        today: Some(20220101),
+        ..policy
+    };
+    assert_eq!(fixed.dated(), fixed);
+}
+
+#[test]
+fn verifier_id_is_the_url_host() {
+    for (url, id) in [
+        ("https://example.com", "example.com"),
+        ("https://Example.com:8443/verify?x=1", "example.com"),
+        ("http://user@127.0.0.1:8081/", "127.0.0.1"),
+        ("http://[::1]:8081", "::1"),
+        ("example.org/show", "example.org"),
+    ] {
+        assert_eq!(expected_verifier_id(url).unwrap(), id);
+    }
+    assert!(matches!(
+        expected_verifier_id("http:///show"),
+        Err(Error::Parse(_))
+    ));
+}
@@ -0,0 +1,68 @@
+//! Tests of the holder's wallet
+
+use zkcreds_passport_cli::{
+    params::Fr,
+    wallet::{Wallet, WalletCred},
+    Error, PersonalInfo,
+};
+
+use zkcreds::attrs::Attrs;
+
+use ark_ff::UniformRand;
+
+#[test]
+fn wallet_round_trip() {
+    let mut rng = rand::thread_rng();
+    let path = std::env::temp_dir().join(format!("zkpp-wallet-{}", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    Wallet::init(&mut rng, &path, "correct horse").unwrap();
+    assert!(Wallet::init(&mut rng, &path, "correct horse").is_err());
+
+    // An empty wallet has nothing to show
+    let mut wallet = Wallet::open(&path, "correct horse").unwrap();
+    assert!(matches!(wallet.get(None), Err(Error::Parse(_))));
+
+    let info = PersonalInfo {
+        seed: Fr::rand(&mut rng),
+        ..Default::default()
+    };
+    let com = info.commit();
+    wallet
+        .insert(WalletCred::new("passport", info.clone(), Some(7), None))
+        .unwrap();
+    assert!(wallet
+        .insert(WalletCred::new("passport", info, Some(8), None))
+        .is_err());
+    wallet.save(&mut rng, &path, "correct horse").unwrap();
+
+    // The credential survives, and is the default since it's the only one
+    let wallet = Wallet::open(&path, "correct horse").unwrap();
+    let cred = wallet.get(None).unwrap();
+    assert_eq!(cred.name(), "passport");
+    assert_eq!(cred.leaf_index, Some(7));
+    assert!(cred.info.commit() == com);
+    assert!(wallet.get(Some("passport")).is_ok());
+    assert!(wallet.get(Some("other")).is_err());
+
+    // A credential saved before it's granted gets its leaf index later
+    let mut wallet = wallet;
+    let pending = PersonalInfo {
+        seed: Fr::rand(&mut rng),
+        ..Default::default()
+    };
+    wallet
+        .insert(WalletCred::new("pending", pending, None, None))
+        .unwrap();
+    assert!(matches!(wallet.get(None), Err(Error::Parse(_))));
+    wallet.get_mut(Some("pending")).unwrap().leaf_index = Some(8);
+    wallet.save(&mut rng, &path, "correct horse").unwrap();
+    let wallet = Wallet::open(&path, "correct horse").unwrap();
+    assert_eq!(wallet.get(Some("pending")).unwrap().leaf_index, Some(8));
+
+    // The wrong passphrase doesn't open it
+    let res = Wallet::open(&path, "wrong horse");
+    assert!(matches!(res, Err(Error::Deserialization(_))));
+
+    std::fs::remove_file(&path).unwrap();
+}